@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use base_types::*;
+use fem::shape::{self, ElementKind};
+use geometry::GeometryObject;
+use meshing::{self, Mesh};
+use model::{Model, GeometryRef};
+use solvers::LinearSolver;
+use super::elasticity::*;
+
+// Zienkiewicz-Zhu estimate of the discretization error in the energy norm
+#[derive(Debug, Clone)]
+pub struct ErrorEstimate {
+    // Error norm of every element
+    pub element_error: Vec<f64>,
+    // Superconvergent patch recovery of the stress vector at the nodes, in
+    // the components of the analysis (xx, yy, xy and the hoop stress)
+    pub recovered_stress: Vec<Vec<f64>>,
+    // sqrt(int s . D^-1 s dV) of the finite element solution
+    pub energy_norm: f64,
+    pub error_norm: f64
+}
+
+impl ErrorEstimate {
+    // Error relative to the energy of the exact solution, |e| / sqrt(|u|^2 + |e|^2)
+    pub fn relative_error(&self) -> f64 {
+        let total = self.energy_norm.hypot(self.error_norm);
+        if total > 0.0 { self.error_norm / total } else { 0.0 }
+    }
+}
+
+// Monomials of complete polynomials of the given degree
+fn monomials(p: &Vec2, degree: usize) -> Vec<f64> {
+    let mut m = Vec::new();
+    for d in 0..degree + 1 {
+        for i in 0..d + 1 {
+            m.push(p.0.powi((d - i) as i32) * p.1.powi(i as i32));
+        }
+    }
+    m
+}
+
+// Least squares polynomial fit of `values` (one vector per sample) at
+// `points`, dropping to a lower degree when the patch has too few samples
+fn fit_patch(points: &[Vec2], values: &[Vec<f64>], degree: usize) -> Option<(usize, Vec<Vec<f64>>)> {
+    for degree in (0..degree + 1).rev() {
+        let terms = monomials(&Vec2(0.0, 0.0), degree).len();
+        let mut ata = DMatrix::zeros(terms, terms);
+        let mut atb = vec![vec![0.0; terms]; values[0].len()];
+        for (p, v) in points.iter().zip(values) {
+            let m = monomials(p, degree);
+            for i in 0..terms {
+                for j in 0..terms {
+                    ata[(i, j)] += m[i] * m[j];
+                }
+                for (rhs, vc) in atb.iter_mut().zip(v) {
+                    rhs[i] += m[i] * vc;
+                }
+            }
+        }
+        if let Ok(chol) = ata.cholesky() {
+            // reject nearly singular patches
+            let pivots = (0..terms).map(|i| chol.l()[(i, i)]);
+            let (lo, hi) = pivots.fold((f64::INFINITY, 0.0f64), |(lo, hi), d| (lo.min(d), hi.max(d)));
+            if lo > 1e-6 * hi {
+                return Some((degree, atb.iter().map(|b| chol.solve(b)).collect()));
+            }
+        }
+    }
+    None
+}
+
+// Superconvergent patch recovery: around every corner node the stresses at
+// the sampling points of the adjacent elements are fitted with a
+// polynomial of the element order, which is evaluated at the nodes of the
+// patch. Nodes covered by several patches take the average.
+pub fn recover_patch_stresses(problem: &ElasticityProblem, solution: &ElasticSolution)
+    -> Result<Vec<Vec<f64>>, String> {
+    let mesh = problem.mesh();
+    let mode = problem.mode();
+    let components = mode.strain_components();
+
+    // stress samples at the reduced integration points of every element,
+    // where the stresses are most accurate
+    let mut samples: Vec<Vec<(Vec2, Vec<f64>)>> = Vec::with_capacity(mesh.elements.len());
+    for e in &mesh.elements {
+        let d = problem.material(&e.region)?.d_matrix(mode)?;
+        let coords = mesh.coords(&e.nodes);
+        let ue = element_displacements(&e.nodes, &solution.displacement);
+        let mut points = Vec::new();
+        for q in e.kind.quadrature(2 * e.kind.order() - 1) {
+            let iso = shape::map(e.kind, &coords, &q.point)?;
+            let s0 = problem.thermal_stress(e, &iso)?;
+            let s = d.mul_vec(&b_matrix(&iso, mode).mul_vec(&ue)).iter().zip(&s0)
+                .map(|(s, s0)| s - s0).collect();
+            points.push((iso.x.clone(), s));
+        }
+        samples.push(points);
+    }
+
+    let mut patches: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, e) in mesh.elements.iter().enumerate() {
+        for &n in &e.nodes[..e.kind.corner_count()] {
+            patches.entry(n).or_default().push(i);
+        }
+    }
+
+    let mut sum = vec![vec![0.0; components]; mesh.node_count()];
+    let mut count = vec![0usize; mesh.node_count()];
+    for (&centre, elements) in &patches {
+        let origin = mesh.nodes[centre].clone();
+        let mut points = Vec::new();
+        let mut values = Vec::new();
+        for &i in elements {
+            for (x, s) in &samples[i] {
+                points.push(x.clone() - origin.clone());
+                values.push(s.clone());
+            }
+        }
+        // scale the local coordinates to the patch size for conditioning
+        let size = points.iter().fold(0.0f64, |m, p| m.max(p.0.abs()).max(p.1.abs()));
+        let scale = if size > 0.0 { 1.0 / size } else { 1.0 };
+        let points: Vec<Vec2> = points.into_iter().map(|p| p * scale).collect();
+        let order = elements.iter().map(|&i| mesh.elements[i].kind.order()).max().unwrap();
+        let (degree, coefficients) = fit_patch(&points, &values, order)
+            .ok_or_else(|| format!("stress recovery failed around node {}", centre))?;
+
+        for &i in elements {
+            for &n in &mesh.elements[i].nodes {
+                let m = monomials(&((mesh.nodes[n].clone() - origin.clone()) * scale), degree);
+                for (s, c) in sum[n].iter_mut().zip(&coefficients) {
+                    *s += m.iter().zip(c).map(|(a, b)| a * b).sum::<f64>();
+                }
+                count[n] += 1;
+            }
+        }
+    }
+    Ok(sum.into_iter().zip(count).map(|(s, c)| {
+        if c > 0 { s.into_iter().map(|v| v / c as f64).collect() } else { vec![0.0; components] }
+    }).collect())
+}
+
+// Error estimate from the difference between the recovered and the finite
+// element stresses, |e|^2 = int (s* - s)^T D^-1 (s* - s) dV
+pub fn estimate_error(problem: &ElasticityProblem, solution: &ElasticSolution) -> Result<ErrorEstimate, String> {
+    let mesh = problem.mesh();
+    let mode = problem.mode();
+    let recovered_stress = recover_patch_stresses(problem, solution)?;
+    let mut compliance: HashMap<&str, (DMatrix, DMatrix)> = HashMap::new();
+    let mut element_error = Vec::with_capacity(mesh.elements.len());
+    let mut energy = 0.0;
+    for e in &mesh.elements {
+        if !compliance.contains_key(e.region.as_str()) {
+            let d = problem.material(&e.region)?.d_matrix(mode)?;
+            let c = d.inverse()?;
+            compliance.insert(&e.region, (d, c));
+        }
+        let (d, c) = &compliance[e.region.as_str()];
+        let coords = mesh.coords(&e.nodes);
+        let ue = element_displacements(&e.nodes, &solution.displacement);
+        let mut error = 0.0;
+        for q in e.kind.quadrature(e.kind.quadrature_degree()) {
+            let iso = shape::map(e.kind, &coords, &q.point)?;
+            let w = q.weight * iso.det_j * problem.weight(&iso.x);
+            let s0 = problem.thermal_stress(e, &iso)?;
+            let s: Vec<f64> = d.mul_vec(&b_matrix(&iso, mode).mul_vec(&ue)).iter().zip(&s0)
+                .map(|(s, s0)| s - s0).collect();
+            let mut diff = vec![0.0; s.len()];
+            for (n, &node) in iso.n.iter().zip(&e.nodes) {
+                for (dk, r) in diff.iter_mut().zip(&recovered_stress[node]) {
+                    *dk += n * r;
+                }
+            }
+            for (dk, sk) in diff.iter_mut().zip(&s) {
+                *dk -= sk;
+            }
+            let dot = |a: &[f64]| a.iter().zip(c.mul_vec(a)).map(|(x, y)| x * y).sum::<f64>();
+            error += dot(&diff) * w;
+            energy += dot(&s) * w;
+        }
+        element_error.push(error.max(0.0).sqrt());
+    }
+    let error_norm = element_error.iter().map(|e| e * e).sum::<f64>().sqrt();
+    Ok(ErrorEstimate { element_error, recovered_stress, energy_norm: energy.max(0.0).sqrt(), error_norm })
+}
+
+// Target element size as a function of position, given by the sizes of the
+// elements of a background mesh. Points take the size of the element with
+// the closest centre.
+#[derive(Debug, Clone)]
+pub struct SizeField {
+    centres: Vec<Vec2>,
+    sizes: Vec<f64>,
+    // centres by cell of a square grid over their bounding box
+    origin: Vec2,
+    cell: f64,
+    nx: usize,
+    ny: usize,
+    cells: Vec<Vec<usize>>
+}
+
+impl SizeField {
+    fn new(centres: Vec<Vec2>, sizes: Vec<f64>) -> SizeField {
+        let min = centres.iter().fold(Vec2(f64::MAX, f64::MAX), |m, p| Vec2(m.0.min(p.0), m.1.min(p.1)));
+        let max = centres.iter().fold(Vec2(f64::MIN, f64::MIN), |m, p| Vec2(m.0.max(p.0), m.1.max(p.1)));
+        // about one centre per cell
+        let (w, h, n) = (max.0 - min.0, max.1 - min.1, centres.len() as f64);
+        let cell = (w * h / n).sqrt().max(w.max(h) / n);
+        let cell = if cell > 0.0 { cell } else { 1.0 };
+        let (nx, ny) = ((w / cell) as usize + 1, (h / cell) as usize + 1);
+        let mut field = SizeField { centres: Vec::new(), sizes, origin: min, cell, nx, ny, cells: vec![Vec::new(); nx * ny] };
+        for (i, c) in centres.iter().enumerate() {
+            let (x, y) = field.cell_of(c);
+            field.cells[y as usize * nx + x as usize].push(i);
+        }
+        field.centres = centres;
+        field
+    }
+
+    pub fn uniform(size: f64) -> SizeField {
+        SizeField::new(vec![Vec2(0.0, 0.0)], vec![size])
+    }
+
+    pub fn from_elements(mesh: &Mesh, sizes: Vec<f64>) -> Result<SizeField, String> {
+        if sizes.len() != mesh.elements.len() || sizes.is_empty() {
+            return Err("size field needs one size per element".to_string());
+        }
+        let centres = mesh.elements.iter()
+            .map(|e| shape::map(e.kind, &mesh.coords(&e.nodes), &e.kind.centre()).map(|iso| iso.x))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(SizeField::new(centres, sizes))
+    }
+
+    // Grid cell of `p`, points outside the grid are moved onto it
+    fn cell_of(&self, p: &Vec2) -> (i64, i64) {
+        let x = ((p.0 - self.origin.0) / self.cell).floor().max(0.0) as i64;
+        let y = ((p.1 - self.origin.1) / self.cell).floor().max(0.0) as i64;
+        (x.min(self.nx as i64 - 1), y.min(self.ny as i64 - 1))
+    }
+
+    // Searches rings of cells around the cell of `p` until the next ring
+    // cannot hold a closer centre
+    pub fn at(&self, p: &Vec2) -> f64 {
+        let (cx, cy) = self.cell_of(p);
+        let mut best: Option<(f64, usize)> = None;
+        for ring in 0..(self.nx + self.ny) as i64 {
+            if let Some((d, _)) = best {
+                if d < (ring - 1) as f64 * self.cell {
+                    break;
+                }
+            }
+            for y in (cy - ring).max(0)..=(cy + ring).min(self.ny as i64 - 1) {
+                for x in (cx - ring).max(0)..=(cx + ring).min(self.nx as i64 - 1) {
+                    if (x - cx).abs() != ring && (y - cy).abs() != ring {
+                        continue;
+                    }
+                    for &i in &self.cells[y as usize * self.nx + x as usize] {
+                        let d = (self.centres[i].0 - p.0).hypot(self.centres[i].1 - p.1);
+                        if best.map_or(true, |b| d < b.0) {
+                            best = Some((d, i));
+                        }
+                    }
+                }
+            }
+        }
+        self.sizes[best.unwrap().1]
+    }
+
+    pub fn sizes(&self) -> &[f64] { &self.sizes }
+
+    pub fn min(&self) -> f64 { self.sizes.iter().cloned().fold(f64::INFINITY, f64::min) }
+
+    pub fn max(&self) -> f64 { self.sizes.iter().cloned().fold(0.0, f64::max) }
+}
+
+// Element size, the square root of the area
+fn element_size(mesh: &Mesh, element: usize) -> Result<f64, String> {
+    let e = &mesh.elements[element];
+    let coords = mesh.coords(&e.nodes);
+    let mut area = 0.0;
+    for q in e.kind.quadrature(2) {
+        area += q.weight * shape::map(e.kind, &coords, &q.point)?.det_j;
+    }
+    Ok(area.sqrt())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveOptions {
+    // Relative error to reach, see `ErrorEstimate::relative_error`
+    pub target: f64,
+    pub max_iterations: usize,
+    pub min_size: f64,
+    pub max_size: f64,
+    // Size of the first mesh
+    pub initial_size: f64,
+    // Tri3 or Tri6
+    pub element: ElementKind
+}
+
+impl Default for AdaptiveOptions {
+    fn default() -> AdaptiveOptions {
+        AdaptiveOptions { target: 0.05, max_iterations: 8, min_size: 0.0, max_size: f64::INFINITY,
+                          initial_size: 1.0, element: ElementKind::Tri6 }
+    }
+}
+
+// New element sizes equidistributing the error: every element of the new
+// mesh should carry an equal share of the admissible error. The change per
+// step is limited to a factor of 4 finer or 2 coarser.
+pub fn refined_sizes(mesh: &Mesh, estimate: &ErrorEstimate, options: &AdaptiveOptions) -> Result<SizeField, String> {
+    let n = mesh.elements.len() as f64;
+    let allowed = options.target * estimate.energy_norm.hypot(estimate.error_norm) / n.sqrt();
+    let mut sizes = Vec::with_capacity(mesh.elements.len());
+    for (i, (e, error)) in mesh.elements.iter().zip(&estimate.element_error).enumerate() {
+        let h = element_size(mesh, i)?;
+        let ratio = if *error > 0.0 {
+            (allowed / error).powf(1.0 / e.kind.order() as f64).clamp(0.25, 2.0)
+        } else {
+            2.0
+        };
+        sizes.push((h * ratio).clamp(options.min_size, options.max_size));
+    }
+    SizeField::from_elements(mesh, sizes)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveStep {
+    pub nodes: usize,
+    pub elements: usize,
+    pub relative_error: f64
+}
+
+#[derive(Debug, Clone)]
+pub struct AdaptiveSolution {
+    pub mesh: Mesh,
+    pub solution: ElasticSolution,
+    pub estimate: ErrorEstimate,
+    pub history: Vec<AdaptiveStep>,
+    pub converged: bool
+}
+
+// Adaptive elastic analysis of the area enclosed by the `outline` objects of
+// `model`. Every iteration triangulates the outline for the size field of the
+// previous estimate, transfers the boundary conditions of the model to the
+// new mesh and applies them to the problem `setup` builds on it, e.g. with
+// the materials of the meshed `region`. The loop stops when the estimated
+// relative error meets the target.
+pub fn adaptive_elasticity<S>(model: &Model, outline: &[GeometryRef], region: &str, setup: S,
+                              options: &AdaptiveOptions, solver: &LinearSolver) -> Result<AdaptiveSolution, String>
+    where S: Fn(&Mesh) -> Result<ElasticityProblem<'_>, String> {
+    let mut objects: Vec<GeometryObject> = Vec::new();
+    for target in outline {
+        objects.extend(model.objects(target)?.into_iter().cloned());
+    }
+    let mut size = SizeField::uniform(options.initial_size);
+    let mut history = Vec::new();
+    loop {
+        let mesh = model.mesh_with(|_| meshing::triangulate(&objects, |p| size.at(p), options.element, region))?;
+        let (solution, estimate) = {
+            let mut problem = setup(&mesh)?;
+            model.apply_elasticity(&mut problem)?;
+            let solution = problem.solve(solver)?;
+            let estimate = estimate_error(&problem, &solution)?;
+            (solution, estimate)
+        };
+        history.push(AdaptiveStep {
+            nodes: mesh.node_count(),
+            elements: mesh.elements.len(),
+            relative_error: estimate.relative_error()
+        });
+        let converged = estimate.relative_error() <= options.target;
+        if converged || history.len() >= options.max_iterations {
+            return Ok(AdaptiveSolution { mesh, solution, estimate, history, converged });
+        }
+        size = refined_sizes(&mesh, &estimate, options)?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing;
+
+    fn cantilever(mesh: &Mesh) -> Result<ElasticityProblem<'_>, String> {
+        let mut p = ElasticityProblem::new(mesh, PlaneMode::PlaneStress, 0.1);
+        p.set_material("beam", ElasticMaterial::Isotropic { young: 1e4, poisson: 0.3 });
+        p.add_support(Support::Fixed { tag: "left".to_string() });
+        p.add_load(Load::Traction { tag: "right".to_string(), traction: Vec2(0.0, -10.0) });
+        Ok(p)
+    }
+
+    fn beam_mesh(size: f64, kind: ElementKind) -> Mesh {
+        let n = |l: f64| ((l / size).ceil() as usize).max(1);
+        meshing::rectangle(Vec2(0.0, 0.0), Vec2(8.0, 1.0), n(8.0), n(1.0), kind, "beam")
+    }
+
+    #[test]
+    fn size_field_takes_the_closest_centre() {
+        let mesh = meshing::triangulate(
+            &[GeometryObject::Circle { center: Vec2(0.0, 0.0), radius: 1.0 }], |p| 0.05 + 0.2 * p.0.abs(),
+            ElementKind::Tri3, "disc").unwrap();
+        let sizes: Vec<f64> = (0..mesh.elements.len()).map(|i| i as f64).collect();
+        let field = SizeField::from_elements(&mesh, sizes).unwrap();
+        for k in 0..200 {
+            // inside and around the disc
+            let p = Vec2::from_angle(k as f64) * (1.5 * (k as f64 * 0.37).sin().abs());
+            let nearest = field.centres.iter().map(|c| (c.clone() - p.clone()).length())
+                .enumerate().min_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+            let at = field.at(&p) as usize;
+            assert_eq!((field.centres[at].clone() - p.clone()).length(), nearest.1, "{:?}", p);
+        }
+        assert_eq!(SizeField::uniform(0.3).at(&Vec2(5.0, -2.0)), 0.3);
+    }
+
+    #[test]
+    fn exact_solutions_have_no_error() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(4.0, 1.0), 4, 2, ElementKind::Quad8, "bar");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 1.0);
+        p.set_material("bar", ElasticMaterial::Isotropic { young: 200e3, poisson: 0.3 });
+        p.add_support(Support::Roller { tag: "left".to_string(), axis: Axis::X });
+        p.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+        p.add_load(Load::Traction { tag: "right".to_string(), traction: Vec2(100.0, 0.0) });
+        let sol = p.solve(&LinearSolver::default()).unwrap();
+        let estimate = estimate_error(&p, &sol).unwrap();
+        assert!(estimate.relative_error() < 1e-9);
+        for s in &estimate.recovered_stress {
+            assert!((s[0] - 100.0).abs() < 1e-8 && s[1].abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn estimate_tracks_the_true_error() {
+        // |u - u_h|^2 = |u|^2 - |u_h|^2 for a loaded, homogeneously supported
+        // body; a fine quadratic mesh stands in for the exact solution
+        let reference = beam_mesh(0.125, ElementKind::Quad8);
+        let exact = {
+            let p = cantilever(&reference).unwrap();
+            let sol = p.solve(&LinearSolver::default()).unwrap();
+            estimate_error(&p, &sol).unwrap().energy_norm
+        };
+        for &(size, kind) in &[(0.5, ElementKind::Quad4), (0.25, ElementKind::Quad4), (0.5, ElementKind::Tri3),
+                                 (0.5, ElementKind::Quad8)] {
+            let mesh = beam_mesh(size, kind);
+            let p = cantilever(&mesh).unwrap();
+            let sol = p.solve(&LinearSolver::default()).unwrap();
+            let estimate = estimate_error(&p, &sol).unwrap();
+            let true_error = (exact * exact - estimate.energy_norm * estimate.energy_norm).sqrt();
+            let effectivity = estimate.error_norm / true_error;
+            assert!(effectivity > 0.8 && effectivity < 1.3, "{:?} {}: {}", kind, size, effectivity);
+        }
+    }
+
+    #[test]
+    fn adaptive_loop_meets_the_target() {
+        use geometry::{GeometryEntity, GeometryObject};
+        use model::Condition;
+        // the cantilever as a drawing, clamped on the left and loaded on the right
+        let segment = |a: Vec2, b: Vec2, handle: u32| GeometryEntity {
+            object: GeometryObject::Segment { beg: a, end: b },
+            layer: "BEAM".to_string(),
+            handle
+        };
+        let mut model = Model::new(vec![segment(Vec2(0.0, 0.0), Vec2(8.0, 0.0), 1),
+                                        segment(Vec2(8.0, 0.0), Vec2(8.0, 1.0), 2),
+                                        segment(Vec2(8.0, 1.0), Vec2(0.0, 1.0), 3),
+                                        segment(Vec2(0.0, 1.0), Vec2(0.0, 0.0), 4)]);
+        model.add_condition("clamp", GeometryRef::Handle(4), Condition::Displacement { ux: Some(0.0), uy: Some(0.0) })
+            .unwrap();
+        model.add_condition("tip", GeometryRef::Handle(2), Condition::Traction(Vec2(0.0, -10.0))).unwrap();
+        fn setup(mesh: &Mesh) -> Result<ElasticityProblem<'_>, String> {
+            let mut p = ElasticityProblem::new(mesh, PlaneMode::PlaneStress, 0.1);
+            p.set_material("beam", ElasticMaterial::Isotropic { young: 1e4, poisson: 0.3 });
+            Ok(p)
+        }
+
+        let options = AdaptiveOptions { target: 0.01, initial_size: 1.0, ..Default::default() };
+        let result = adaptive_elasticity(&model, &[GeometryRef::Layer("BEAM".to_string())], "beam", setup,
+                                         &options, &LinearSolver::default()).unwrap();
+        assert!(result.converged, "{:?}", result.history);
+        assert!(result.history.len() > 1, "{:?}", result.history);
+        assert!(result.history.last().unwrap().relative_error < result.history[0].relative_error);
+        assert_eq!(result.history.last().unwrap().relative_error, result.estimate.relative_error());
+
+        // refined towards the clamped corners, coarser towards the free end
+        let mesh = &result.mesh;
+        let mean_size = |range: (f64, f64)| {
+            let sizes: Vec<f64> = (0..mesh.elements.len())
+                .filter(|&i| { let x = mesh.nodes[mesh.elements[i].nodes[0]].0; x >= range.0 && x <= range.1 })
+                .map(|i| element_size(mesh, i).unwrap())
+                .collect();
+            sizes.iter().sum::<f64>() / sizes.len() as f64
+        };
+        assert!(mean_size((0.0, 0.5)) < 0.5 * mean_size((4.0, 8.0)), "{} {}",
+                mean_size((0.0, 0.5)), mean_size((4.0, 8.0)));
+    }
+}
@@ -0,0 +1,115 @@
+use std::f64::consts::PI;
+use base_types::*;
+use meshing::Mesh;
+use solvers::LinearSolver;
+
+// Planar problems are integrated per unit thickness over the mesh plane,
+// axisymmetric ones over the solid of revolution about the y axis, with x as
+// the radius r and y as the axial coordinate z.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Symmetry {
+    Planar,
+    Axisymmetric
+}
+
+impl Symmetry {
+    // Extra integration weight at a point, 2 pi r for axisymmetric problems
+    pub fn weight(&self, x: &Vec2) -> f64 {
+        match self {
+            Symmetry::Planar => 1.0,
+            Symmetry::Axisymmetric => 2.0 * PI * x.0
+        }
+    }
+
+    pub fn check(&self, mesh: &Mesh) -> Result<(), String> {
+        if *self == Symmetry::Planar {
+            return Ok(());
+        }
+        let scale = mesh.nodes.iter().fold(1.0f64, |m, p| m.max(p.0.abs()).max(p.1.abs()));
+        match mesh.nodes.iter().position(|p| p.0 < -1e-9 * scale) {
+            Some(i) => Err(format!("node {} at r = {} lies on the negative side of the axis",
+                                   i, mesh.nodes[i].0)),
+            None => Ok(())
+        }
+    }
+}
+
+// Global dofs of an element, node-major: [n0.0, n0.1, ..., n1.0, ...]
+pub fn element_dofs(nodes: &[usize], dofs_per_node: usize) -> Vec<usize> {
+    nodes.iter()
+        .flat_map(|&n| (0..dofs_per_node).map(move |d| n * dofs_per_node + d))
+        .collect()
+}
+
+// Scatters element matrices and vectors into the global system.
+pub struct Assembler {
+    dofs_per_node: usize,
+    matrix: TripletMatrix,
+    rhs: Vec<f64>
+}
+
+impl Assembler {
+    pub fn new(node_count: usize, dofs_per_node: usize) -> Assembler {
+        let n = node_count * dofs_per_node;
+        Assembler { dofs_per_node, matrix: TripletMatrix::new(n, n), rhs: vec![0.0; n] }
+    }
+
+    pub fn size(&self) -> usize { self.rhs.len() }
+
+    pub fn add_matrix(&mut self, nodes: &[usize], ke: &DMatrix) {
+        let dofs = element_dofs(nodes, self.dofs_per_node);
+        assert_eq!(ke.nrows(), dofs.len());
+        for (a, &i) in dofs.iter().enumerate() {
+            for (b, &j) in dofs.iter().enumerate() {
+                let v = ke[(a, b)];
+                if v != 0.0 {
+                    self.matrix.add(i, j, v);
+                }
+            }
+        }
+    }
+
+    pub fn add_vector(&mut self, nodes: &[usize], fe: &[f64]) {
+        let dofs = element_dofs(nodes, self.dofs_per_node);
+        assert_eq!(fe.len(), dofs.len());
+        for (&i, v) in dofs.iter().zip(fe) {
+            self.rhs[i] += v;
+        }
+    }
+
+    pub fn add_to_rhs(&mut self, dof: usize, value: f64) {
+        self.rhs[dof] += value;
+    }
+
+    pub fn finish(self) -> (CsrMatrix, Vec<f64>) {
+        (self.matrix.to_csr(), self.rhs)
+    }
+}
+
+// Assembled system K u = f together with the prescribed dofs, which are
+// only eliminated when solving so that reactions can still be recovered.
+#[derive(Debug, Clone)]
+pub struct LinearSystem {
+    pub matrix: CsrMatrix,
+    pub rhs: Vec<f64>,
+    // (dof, value)
+    pub fixed: Vec<(usize, f64)>
+}
+
+impl LinearSystem {
+    pub fn solve(&self, solver: &LinearSolver) -> Result<Vec<f64>, String> {
+        let mut k = self.matrix.clone();
+        let mut f = self.rhs.clone();
+        k.apply_dirichlet(&self.fixed, &mut f);
+        solver.solve(&k, &f)
+    }
+
+    // Sorted dofs without a prescribed value
+    pub fn free_dofs(&self) -> Vec<usize> {
+        let mut fixed = vec![false; self.rhs.len()];
+        for &(dof, _) in &self.fixed {
+            fixed[dof] = true;
+        }
+        (0..self.rhs.len()).filter(|&i| !fixed[i]).collect()
+    }
+}
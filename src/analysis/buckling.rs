@@ -0,0 +1,137 @@
+use base_types::*;
+use fem::shape;
+use solvers::{EigenOptions, LinearSolver, subspace_iteration};
+use super::assembly::Assembler;
+use super::elasticity::*;
+
+#[derive(Debug, Clone)]
+pub struct BucklingMode {
+    // Multiplier of the applied loads at which this mode becomes unstable;
+    // negative factors need the loads reversed
+    pub load_factor: f64,
+    // Scaled to a largest displacement component of 1
+    pub shape: Vec<Vec2>
+}
+
+#[derive(Debug, Clone)]
+pub struct BucklingSolution {
+    // The prebuckling state the geometric stiffness is built from
+    pub static_solution: ElasticSolution,
+    // Ascending in |load_factor|
+    pub modes: Vec<BucklingMode>
+}
+
+// Geometric stiffness int(grad N_a . S grad N_b dV) of a stress field, the
+// same for both displacement components
+pub fn geometric_stiffness(problem: &ElasticityProblem, displacement: &[Vec2]) -> Result<CsrMatrix, String> {
+    let mesh = problem.mesh();
+    let mode = problem.mode();
+    if mode == PlaneMode::Axisymmetric {
+        return Err("geometric stiffness is only available for planar models".to_string());
+    }
+    let mut asm = Assembler::new(mesh.node_count(), 2);
+    for e in &mesh.elements {
+        let d = problem.material(&e.region)?.d_matrix(mode)?;
+        let coords = mesh.coords(&e.nodes);
+        let ue = element_displacements(&e.nodes, displacement);
+        let n = e.nodes.len();
+        let mut kg = DMatrix::zeros(2 * n, 2 * n);
+        for q in e.kind.quadrature(e.kind.quadrature_degree()) {
+            let iso = shape::map(e.kind, &coords, &q.point)?;
+            let s0 = problem.thermal_stress(e, &iso)?;
+            let stress: Vec<f64> = d.mul_vec(&b_matrix(&iso, mode).mul_vec(&ue)).iter().zip(&s0)
+                .map(|(s, s0)| s - s0).collect();
+            let w = q.weight * iso.det_j * problem.weight(&iso.x);
+            for a in 0..n {
+                let ga = &iso.grad[a];
+                let sa = Vec2(stress[0] * ga.0 + stress[2] * ga.1, stress[2] * ga.0 + stress[1] * ga.1);
+                for b in 0..n {
+                    let v = sa.dot(&iso.grad[b]) * w;
+                    kg[(2 * a, 2 * b)] += v;
+                    kg[(2 * a + 1, 2 * b + 1)] += v;
+                }
+            }
+        }
+        asm.add_matrix(&e.nodes, &kg);
+    }
+    Ok(asm.finish().0)
+}
+
+// Linear buckling (K + lambda K_G) phi = 0 about the static state of the
+// applied loads.
+pub fn buckling_analysis(problem: &ElasticityProblem, options: &EigenOptions, solver: &LinearSolver)
+    -> Result<BucklingSolution, String> {
+    let static_solution = problem.solve(solver)?;
+    let kg = geometric_stiffness(problem, &static_solution.displacement)?;
+    let system = problem.assemble()?;
+    let free = system.free_dofs();
+    let k = system.matrix.principal_submatrix(&free);
+    // K phi = lambda (-K_G) phi, with K positive definite on the free dofs
+    let mut minus_kg = kg.principal_submatrix(&free);
+    minus_kg.values_mut().iter_mut().for_each(|v| *v = -*v);
+    let pairs = subspace_iteration(&k, &minus_kg, options)?;
+
+    let dofs = system.rhs.len();
+    let mut modes: Vec<BucklingMode> = pairs.values.iter().zip(&pairs.vectors).map(|(&lambda, vector)| {
+        let mut u = vec![0.0; dofs];
+        for (&dof, v) in free.iter().zip(vector) {
+            u[dof] = *v;
+        }
+        let largest = u.iter().fold(0.0f64, |m, v| if v.abs() > m.abs() { *v } else { m });
+        if largest != 0.0 {
+            u.iter_mut().for_each(|v| *v /= largest);
+        }
+        BucklingMode { load_factor: lambda, shape: u.chunks(2).map(|c| Vec2(c[0], c[1])).collect() }
+    }).collect();
+    modes.sort_by(|a, b| a.load_factor.abs().partial_cmp(&b.load_factor.abs()).unwrap());
+    Ok(BucklingSolution { static_solution, modes })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::PI;
+    use fem::shape::ElementKind;
+    use meshing;
+
+    fn column(load: f64) -> (meshing::Mesh, f64) {
+        let (h, l) = (0.05, 1.0);
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(h, l), 2, 40, ElementKind::Quad8, "steel");
+        (mesh, load / (h * 0.01))
+    }
+
+    #[test]
+    fn euler_column() {
+        // fixed-free column: P_cr = pi^2 E I / (4 L^2)
+        let (e, h, t, l) = (200e9, 0.05, 0.01, 1.0);
+        let (mesh, traction) = column(1000.0);
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, t);
+        p.set_material("steel", ElasticMaterial::Isotropic { young: e, poisson: 0.3 });
+        p.add_support(Support::Fixed { tag: "bottom".to_string() });
+        p.add_load(Load::Traction { tag: "top".to_string(), traction: Vec2(0.0, -traction) });
+        let sol = buckling_analysis(&p, &EigenOptions { count: 2, ..Default::default() },
+                                    &LinearSolver::default()).unwrap();
+
+        let p_cr = PI * PI * e * (t * h * h * h / 12.0) / (4.0 * l * l);
+        let first = &sol.modes[0];
+        assert!(((first.load_factor * 1000.0 - p_cr) / p_cr).abs() < 0.01,
+                "{} vs {}", first.load_factor * 1000.0, p_cr);
+        // second mode of the fixed-free column: 9 P_cr
+        assert!(((sol.modes[1].load_factor * 1000.0 - 9.0 * p_cr) / (9.0 * p_cr)).abs() < 0.03);
+        // sideways mode with the tip moving most
+        let tip = mesh.boundary_nodes("top")[0];
+        assert!(first.shape[tip].0.abs() > 0.99);
+    }
+
+    #[test]
+    fn tension_reverses_the_factor() {
+        let (mesh, traction) = column(1000.0);
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 0.01);
+        p.set_material("steel", ElasticMaterial::Isotropic { young: 200e9, poisson: 0.3 });
+        p.add_support(Support::Fixed { tag: "bottom".to_string() });
+        p.add_load(Load::Traction { tag: "top".to_string(), traction: Vec2(0.0, traction) });
+        let sol = buckling_analysis(&p, &EigenOptions { count: 1, ..Default::default() },
+                                    &LinearSolver::default()).unwrap();
+        assert!(sol.modes[0].load_factor < 0.0);
+    }
+}
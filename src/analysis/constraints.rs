@@ -0,0 +1,428 @@
+use std::collections::BTreeMap;
+use base_types::*;
+use fem::shape;
+use meshing::Mesh;
+use solvers::{self, LinearSolver, Reordering, SkylineCholesky};
+use super::assembly::LinearSystem;
+
+// Linear constraint equation sum(c u[dof]) = value over global dofs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mpc {
+    // (dof, c)
+    pub terms: Vec<(usize, f64)>,
+    pub value: f64
+}
+
+impl Mpc {
+    pub fn new(terms: Vec<(usize, f64)>, value: f64) -> Mpc {
+        Mpc { terms, value }
+    }
+
+    // u[a] = u[b]
+    pub fn equal(a: usize, b: usize) -> Mpc {
+        Mpc { terms: vec![(a, 1.0), (b, -1.0)], value: 0.0 }
+    }
+
+    // How far `u` violates the equation
+    pub fn residual(&self, u: &[f64]) -> f64 {
+        self.terms.iter().map(|&(dof, c)| c * u[dof]).sum::<f64>() - self.value
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConstraintMethod {
+    // Every equation expresses one dof by the others, which is then removed
+    // from the system
+    #[default]
+    Elimination,
+    // One multiplier per equation is added to the system. The saddle point
+    // system is factored with the skyline solver, multipliers last.
+    LagrangeMultipliers
+}
+
+// Ties the nodes of the boundary `slave` to the closest points of the edges
+// of the boundary `master`, e.g. where two independently meshed regions meet.
+// Slave nodes farther than `tolerance` from the master boundary are an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tie {
+    pub slave: String,
+    pub master: String,
+    pub tolerance: f64
+}
+
+impl Tie {
+    // One equation per slave node and field component, the slave value
+    // interpolated from the master edge
+    pub fn constraints(&self, mesh: &Mesh, dofs_per_node: usize) -> Result<Vec<Mpc>, String> {
+        let slave = mesh.boundary_nodes(&self.slave);
+        if slave.is_empty() {
+            return Err(format!("no boundary edges tagged '{}'", self.slave));
+        }
+        let master: Vec<_> = mesh.boundary_with_tag(&self.master).collect();
+        if master.is_empty() {
+            return Err(format!("no boundary edges tagged '{}'", self.master));
+        }
+        let master_nodes = mesh.boundary_nodes(&self.master);
+
+        let mut mpcs = Vec::new();
+        for node in slave.into_iter().filter(|n| master_nodes.binary_search(n).is_err()) {
+            let p = &mesh.nodes[node];
+            let mut best: Option<(f64, usize, Vec<f64>)> = None;
+            for (i, edge) in master.iter().enumerate() {
+                let (distance, n) = closest_edge_point(edge.kind, &mesh.coords(&edge.nodes), p)?;
+                if best.as_ref().map_or(true, |b| distance < b.0) {
+                    best = Some((distance, i, n));
+                }
+            }
+            let (distance, i, n) = best.unwrap();
+            if distance > self.tolerance {
+                return Err(format!("node {} at {:?} is {:e} away from the boundary '{}'",
+                                   node, p, distance, self.master));
+            }
+            for d in 0..dofs_per_node {
+                let mut terms = vec![(node * dofs_per_node + d, 1.0)];
+                terms.extend(master[i].nodes.iter().zip(&n)
+                    .filter(|t| t.1.abs() > 1e-12)
+                    .map(|(&m, &n)| (m * dofs_per_node + d, -n)));
+                mpcs.push(Mpc::new(terms, 0.0));
+            }
+        }
+        Ok(mpcs)
+    }
+}
+
+// Distance of `p` to a boundary edge and the shape functions at the closest
+// point of it
+pub fn closest_edge_point(kind: shape::ElementKind, coords: &[Vec2], p: &Vec2) -> Result<(f64, Vec<f64>), String> {
+    let mut xi = 0.0;
+    for _ in 0..25 {
+        let e = shape::map_edge(kind, coords, xi)?;
+        let step = (p.clone() - e.x).dot(&e.tangent) / e.det_j;
+        let next = (xi + step).clamp(-1.0, 1.0);
+        let done = (next - xi).abs() < 1e-13;
+        xi = next;
+        if done {
+            break;
+        }
+    }
+    let e = shape::map_edge(kind, coords, xi)?;
+    Ok(((e.x - p.clone()).length().sqrt(), e.n))
+}
+
+// Solves the system subject to the constraint equations. Prescribed dofs
+// may take part in them with their prescribed values.
+pub fn solve_constrained(system: &LinearSystem, constraints: &[Mpc], method: ConstraintMethod,
+                         solver: &LinearSolver) -> Result<Vec<f64>, String> {
+    if constraints.is_empty() {
+        return system.solve(solver);
+    }
+    let constraints = substitute_fixed(system, constraints)?;
+    match method {
+        ConstraintMethod::Elimination => eliminate(system, &constraints, solver),
+        ConstraintMethod::LagrangeMultipliers => lagrange(system, &constraints, solver)
+    }
+}
+
+// Moves the prescribed dofs to the right hand side and drops the equations
+// that are left without unknowns, if they hold
+fn substitute_fixed(system: &LinearSystem, constraints: &[Mpc]) -> Result<Vec<Mpc>, String> {
+    let mut fixed = vec![None; system.rhs.len()];
+    for &(dof, v) in &system.fixed {
+        fixed[dof] = Some(v);
+    }
+    let mut reduced = Vec::with_capacity(constraints.len());
+    for (k, mpc) in constraints.iter().enumerate() {
+        let mut value = mpc.value;
+        let mut scale = mpc.value.abs();
+        let mut terms = Vec::with_capacity(mpc.terms.len());
+        for &(dof, c) in &mpc.terms {
+            if dof >= fixed.len() {
+                return Err(format!("constraint {} refers to dof {} of {}", k, dof, fixed.len()));
+            }
+            match fixed[dof] {
+                Some(v) => {
+                    value -= c * v;
+                    scale += (c * v).abs();
+                },
+                None => terms.push((dof, c))
+            }
+        }
+        if !terms.is_empty() {
+            reduced.push(Mpc::new(terms, value));
+        } else if value.abs() > 1e-10 * scale.max(1.0) {
+            return Err(format!("constraint {} contradicts the prescribed values", k));
+        }
+    }
+    Ok(reduced)
+}
+
+// u = T v + g over the independent dofs v: every equation is solved for its
+// largest coefficient, the slave dofs found so far substituted first
+fn eliminate(system: &LinearSystem, constraints: &[Mpc], solver: &LinearSolver) -> Result<Vec<f64>, String> {
+    let n = system.rhs.len();
+    // u[s] = sum(a u[m]) + b for the slave dofs s
+    let mut slaves: BTreeMap<usize, (BTreeMap<usize, f64>, f64)> = BTreeMap::new();
+    for (k, mpc) in constraints.iter().enumerate() {
+        let mut terms: BTreeMap<usize, f64> = BTreeMap::new();
+        let mut value = mpc.value;
+        for &(dof, c) in &mpc.terms {
+            match slaves.get(&dof) {
+                Some((masters, b)) => {
+                    value -= c * b;
+                    for (&m, a) in masters {
+                        *terms.entry(m).or_insert(0.0) += c * a;
+                    }
+                },
+                None => *terms.entry(dof).or_insert(0.0) += c
+            }
+        }
+        let largest = terms.values().fold(0.0f64, |m, c| m.max(c.abs()));
+        terms.retain(|_, c| c.abs() > 1e-12 * largest);
+        let (s, cs) = match terms.iter().max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap()) {
+            Some((&s, &cs)) => (s, cs),
+            None if value.abs() <= 1e-10 * mpc.value.abs().max(1.0) => continue,
+            None => return Err(format!("constraint {} contradicts the others", k))
+        };
+        terms.remove(&s);
+        for a in terms.values_mut() {
+            *a /= -cs;
+        }
+        let b = value / cs;
+        // slaves depending on the new one
+        for (masters, g) in slaves.values_mut() {
+            if let Some(a) = masters.remove(&s) {
+                *g += a * b;
+                for (&m, c) in &terms {
+                    *masters.entry(m).or_insert(0.0) += a * c;
+                }
+            }
+        }
+        slaves.insert(s, (terms, b));
+    }
+
+    let mut index = vec![None; n];
+    let mut count = 0;
+    for (dof, i) in index.iter_mut().enumerate() {
+        if !slaves.contains_key(&dof) {
+            *i = Some(count);
+            count += 1;
+        }
+    }
+    let row = |dof: usize| -> Vec<(usize, f64)> {
+        match slaves.get(&dof) {
+            Some((masters, _)) => masters.iter().map(|(&m, &a)| (index[m].unwrap(), a)).collect(),
+            None => vec![(index[dof].unwrap(), 1.0)]
+        }
+    };
+    let mut g = vec![0.0; n];
+    for (&s, (_, b)) in &slaves {
+        g[s] = *b;
+    }
+
+    // T^T K T v = T^T (f - K g)
+    let rows: Vec<Vec<(usize, f64)>> = (0..n).map(row).collect();
+    let kg = system.matrix.mul_vec(&g);
+    let mut matrix = TripletMatrix::new(count, count);
+    let mut rhs = vec![0.0; count];
+    for i in 0..n {
+        for (j, v) in system.matrix.row(i) {
+            for &(p, a) in &rows[i] {
+                for &(q, b) in &rows[j] {
+                    matrix.add(p, q, a * v * b);
+                }
+            }
+        }
+        for &(p, a) in &rows[i] {
+            rhs[p] += a * (system.rhs[i] - kg[i]);
+        }
+    }
+    let fixed = system.fixed.iter().map(|&(dof, v)| (index[dof].unwrap(), v)).collect();
+    let v = LinearSystem { matrix: matrix.to_csr(), rhs, fixed }.solve(solver)?;
+    Ok(rows.iter().zip(&g).map(|(t, g)| t.iter().map(|&(p, a)| a * v[p]).sum::<f64>() + g).collect())
+}
+
+// [K C^T; C 0] [u; l] = [f; value]
+fn lagrange(system: &LinearSystem, constraints: &[Mpc], solver: &LinearSolver) -> Result<Vec<f64>, String> {
+    if !matches!(solver, LinearSolver::Skyline(_)) {
+        return Err("Lagrange multipliers need the skyline solver".to_string());
+    }
+    let n = system.rhs.len();
+    let m = constraints.len();
+    let mut matrix = TripletMatrix::with_capacity(n + m, n + m, system.matrix.nnz());
+    for i in 0..n {
+        for (j, v) in system.matrix.row(i) {
+            matrix.add(i, j, v);
+        }
+    }
+    let mut rhs = system.rhs.clone();
+    for (k, mpc) in constraints.iter().enumerate() {
+        for &(dof, c) in &mpc.terms {
+            matrix.add(n + k, dof, c);
+            matrix.add(dof, n + k, c);
+        }
+        rhs.push(mpc.value);
+    }
+    let mut matrix = matrix.to_csr();
+    matrix.apply_dirichlet(&system.fixed, &mut rhs);
+
+    // the factorization does not pivot: every multiplier directly follows
+    // the dof with the largest coefficient in its equation, which keeps the
+    // pivots regular where K alone is singular, as for parts held only by ties
+    let mut after = vec![Vec::new(); n];
+    let mut taken = vec![false; n];
+    for (k, mpc) in constraints.iter().enumerate() {
+        let largest = |untaken: bool| mpc.terms.iter().filter(|t| !untaken || !taken[t.0])
+            .max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap())
+            .map(|t| t.0);
+        let dof = largest(true).or_else(|| largest(false)).unwrap();
+        taken[dof] = true;
+        after[dof].push(n + k);
+    }
+    let order: Vec<usize> = solvers::reverse_cuthill_mckee(&system.matrix).into_iter()
+        .flat_map(|d| Some(d).into_iter().chain(after[d].iter().cloned()))
+        .collect();
+    let factor = SkylineCholesky::factor(&matrix.principal_submatrix(&order), Reordering::Natural)?;
+    let x = factor.solve(&order.iter().map(|&i| rhs[i]).collect::<Vec<_>>());
+    let mut u = vec![0.0; n];
+    for (&i, v) in order.iter().zip(x) {
+        if i < n {
+            u[i] = v;
+        }
+    }
+    Ok(u)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use analysis::elasticity::*;
+    use analysis::scalar::{ScalarProblem, ScalarBoundary};
+    use fem::shape::ElementKind;
+    use meshing::Mesh;
+    use analysis::fixtures::{stacked, stacked_problem};
+
+    const METHODS: [ConstraintMethod; 2] = [ConstraintMethod::Elimination, ConstraintMethod::LagrangeMultipliers];
+
+    // Chain of unit springs 0 - 1 - 2 - 3 with u0 fixed
+    fn springs() -> LinearSystem {
+        let mut t = TripletMatrix::new(4, 4);
+        for i in 0..3 {
+            t.add(i, i, 1.0);
+            t.add(i + 1, i + 1, 1.0);
+            t.add(i, i + 1, -1.0);
+            t.add(i + 1, i, -1.0);
+        }
+        LinearSystem { matrix: t.to_csr(), rhs: vec![0.0, 0.0, 0.0, 1.0], fixed: vec![(0, 0.0)] }
+    }
+
+    #[test]
+    fn constraint_equations() {
+        let system = springs();
+        // u3 = 2 u1 + 0.5 and a chain u2 = u1 through the fixed dof
+        let mpcs = vec![Mpc::new(vec![(3, 1.0), (1, -2.0)], 0.5), Mpc::new(vec![(2, 1.0), (1, -1.0), (0, 3.0)], 0.0)];
+        let solutions: Vec<Vec<f64>> = METHODS.iter()
+            .map(|&m| solve_constrained(&system, &mpcs, m, &LinearSolver::default()).unwrap())
+            .collect();
+        for u in &solutions {
+            assert!(mpcs.iter().all(|c| c.residual(u).abs() < 1e-12), "{:?}", u);
+            assert_eq!(u[0], 0.0);
+        }
+        for (a, b) in solutions[0].iter().zip(&solutions[1]) {
+            assert!((a - b).abs() < 1e-12);
+        }
+        // minimum of (u1^2 + (u1 + 0.5)^2) / 2 - (2 u1 + 0.5)
+        assert!((solutions[0][1] - 0.75).abs() < 1e-12, "{:?}", solutions[0]);
+
+        // redundant equations are fine, contradicting ones are not
+        let twice = vec![Mpc::equal(1, 2), Mpc::equal(2, 1)];
+        let u = solve_constrained(&system, &twice, ConstraintMethod::Elimination, &LinearSolver::default()).unwrap();
+        assert!((u[1] - u[2]).abs() < 1e-12);
+        let contradicting = vec![Mpc::equal(1, 2), Mpc::new(vec![(2, 1.0), (1, -1.0)], 1.0)];
+        assert!(solve_constrained(&system, &contradicting, ConstraintMethod::Elimination, &LinearSolver::default())
+            .is_err());
+        assert!(solve_constrained(&system, &[Mpc::new(vec![(0, 1.0)], 1.0)], ConstraintMethod::Elimination,
+                                  &LinearSolver::default()).is_err());
+    }
+
+    fn tie() -> Tie {
+        Tie { slave: "upper_bottom".to_string(), master: "top".to_string(), tolerance: 1e-9 }
+    }
+
+    fn compressed(mesh: &Mesh, method: ConstraintMethod) -> ElasticSolution {
+        let mut p = stacked_problem(mesh);
+        p.add_load(Load::Pressure { tag: "upper_top".to_string(), pressure: 50.0 });
+        p.add_tie(tie());
+        p.set_constraint_method(method);
+        p.solve(&LinearSolver::default()).unwrap()
+    }
+
+    #[test]
+    fn tied_blocks_pass_the_patch_test() {
+        // the patch test holds where the slave edges subdivide the master ones
+        for kind in [ElementKind::Quad4, ElementKind::Quad8, ElementKind::Tri6].iter() {
+            let mesh = stacked(*kind, 0.0, 4, 3);
+            for &method in METHODS.iter() {
+                let sol = compressed(&mesh, method);
+                // uniform uniaxial compression
+                for (x, u) in mesh.nodes.iter().zip(&sol.displacement) {
+                    let exact = Vec2(0.3 * 50.0 / 200e3 * x.0, -50.0 / 200e3 * x.1);
+                    assert!((u.clone() - exact).length().sqrt() < 1e-12, "{:?} {:?} {:?}", kind, x, u);
+                }
+                assert!((sol.total_reaction().1 - 50.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn non_matching_interface() {
+        let mesh = stacked(ElementKind::Quad4, 0.0, 3, 3);
+        let sol = compressed(&mesh, ConstraintMethod::Elimination);
+        let u: Vec<f64> = sol.displacement.iter().flat_map(|u| vec![u.0, u.1]).collect();
+        for mpc in tie().constraints(&mesh, 2).unwrap() {
+            assert!(mpc.residual(&u).abs() < 1e-15);
+        }
+        assert!((sol.total_reaction().1 - 50.0).abs() < 1e-9);
+        // close to uniform compression away from the interface
+        for n in mesh.boundary_nodes("upper_top") {
+            assert!((sol.displacement[n].1 / (-2.0 * 50.0 / 200e3) - 1.0).abs() < 0.02, "{:?}", sol.displacement[n]);
+        }
+    }
+
+    #[test]
+    fn tied_conduction() {
+        let mesh = stacked(ElementKind::Quad8, 0.0, 4, 3);
+        for &method in METHODS.iter() {
+            let mut p = ScalarProblem::new(&mesh);
+            p.set_coefficient("base", 2.0);
+            p.set_coefficient("upper", 1.0);
+            p.add_boundary(ScalarBoundary::Fixed { tag: "bottom".to_string(), value: 0.0 });
+            p.add_boundary(ScalarBoundary::Fixed { tag: "upper_top".to_string(), value: 3.0 });
+            p.add_tie(tie());
+            p.set_constraint_method(method);
+            let t = p.solve(&LinearSolver::default()).unwrap();
+            // the same flux through both layers: 2 T(1) = 3 - T(1)
+            for (x, t) in mesh.nodes.iter().zip(&t) {
+                let exact = if x.1 <= 1.0 { x.1 } else { 1.0 + 2.0 * (x.1 - 1.0) };
+                assert!((t - exact).abs() < 1e-12, "{:?} {}", x, t);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_ties() {
+        let mesh = stacked(ElementKind::Quad4, 0.0, 3, 3);
+        let far = Tie { master: "right".to_string(), ..tie() };
+        assert!(far.constraints(&mesh, 2).is_err());
+        let missing = Tie { master: "nowhere".to_string(), ..tie() };
+        assert!(missing.constraints(&mesh, 2).is_err());
+
+        let mut p = ScalarProblem::new(&mesh);
+        p.set_coefficient("base", 1.0);
+        p.set_coefficient("upper", 1.0);
+        p.add_boundary(ScalarBoundary::Fixed { tag: "bottom".to_string(), value: 0.0 });
+        p.add_tie(tie());
+        p.set_constraint_method(ConstraintMethod::LagrangeMultipliers);
+        let cg = LinearSolver::ConjugateGradient(solvers::SolverOptions::default());
+        assert!(p.solve(&cg).is_err());
+    }
+}
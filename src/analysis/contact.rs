@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use base_types::*;
+use solvers::LinearSolver;
+use super::assembly::Assembler;
+use super::elasticity::*;
+use super::nonlinear::*;
+
+// The nodes of the boundary `slave` may not penetrate the edges of the
+// boundary `master`. Both belong to separate parts of the mesh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactPair {
+    pub slave: String,
+    pub master: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactOptions {
+    // Penalty stiffness relative to the mean diagonal stiffness of the
+    // master nodes
+    pub penalty: f64,
+    // Augmented Lagrangian updates of the contact forces, zero for a pure
+    // penalty method
+    pub max_augmentations: usize,
+    // Allowed penetration relative to the mean length of the master segments
+    pub penetration_tolerance: f64
+}
+
+impl Default for ContactOptions {
+    fn default() -> ContactOptions {
+        ContactOptions { penalty: 10.0, max_augmentations: 10, penetration_tolerance: 1e-4 }
+    }
+}
+
+// A slave node and its closest point on the master boundary
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactPoint {
+    pub pair: usize,
+    pub node: usize,
+    // Nodes of the master segment and the position along it, 0 at the first
+    pub segment: (usize, usize),
+    pub xi: f64,
+    // Outward normal of the master boundary, interpolated between the
+    // averaged normals at the segment nodes
+    pub normal: Vec2,
+    // Negative for penetration
+    pub gap: f64,
+    // Normal force on the slave node, zero for open contacts
+    pub force: f64
+}
+
+#[derive(Debug, Clone)]
+pub struct ContactSolution {
+    pub displacement: Vec<Vec2>,
+    pub reactions: Vec<Vec2>,
+    // Slave nodes in reach of a master segment
+    pub contact: Vec<ContactPoint>,
+    // Newton-Raphson increments of the last augmentation
+    pub increments: Vec<LoadIncrement>,
+    pub augmentations: usize
+}
+
+impl ContactSolution {
+    // Total force the master boundaries of `pair` exert on the slave nodes
+    pub fn contact_force(&self, pair: usize) -> Vec2 {
+        self.contact.iter().filter(|c| c.pair == pair)
+            .fold(Vec2(0.0, 0.0), |acc, c| acc + c.normal.clone() * c.force)
+    }
+
+    pub fn max_penetration(&self) -> f64 {
+        self.contact.iter().fold(0.0, |m, c| m.max(-c.gap))
+    }
+}
+
+// Frictionless node-to-segment contact for plane stress and plane strain
+// models. Supports, loads and thermal strains come from `structure` and are
+// applied with the load factor of the Newton-Raphson driver. Every part needs
+// supports of its own or has to touch its neighbours initially, otherwise its
+// rigid body motion is undetermined until the contact closes.
+pub struct ContactProblem<'a> {
+    structure: ElasticityProblem<'a>,
+    pairs: Vec<ContactPair>
+}
+
+impl<'a> ContactProblem<'a> {
+    pub fn new(structure: ElasticityProblem<'a>) -> ContactProblem<'a> {
+        ContactProblem { structure, pairs: Vec::new() }
+    }
+
+    pub fn structure(&self) -> &ElasticityProblem<'a> { &self.structure }
+
+    pub fn add_pair(&mut self, pair: ContactPair) {
+        self.pairs.push(pair);
+    }
+
+    pub fn solve(&self, options: &ContactOptions, newton: &NewtonOptions, solver: &LinearSolver)
+        -> Result<ContactSolution, String> {
+        if self.structure.mode() == PlaneMode::Axisymmetric {
+            return Err("contact is only available for planar models".to_string());
+        }
+        if self.pairs.is_empty() {
+            return Err("no contact pairs".to_string());
+        }
+        let mesh = self.structure.mesh();
+        let system = self.structure.assemble()?;
+        let diagonal = system.matrix.diagonal();
+
+        let mut surfaces = Vec::with_capacity(self.pairs.len());
+        let (mut length, mut master_stiffness, mut count) = (0.0, 0.0, 0);
+        for pair in &self.pairs {
+            let slave = mesh.boundary_nodes(&pair.slave);
+            if slave.is_empty() {
+                return Err(format!("no boundary edges tagged '{}'", pair.slave));
+            }
+            let mut segments = Vec::new();
+            for edge in mesh.boundary_with_tag(&pair.master) {
+                // quadratic edges as two straight segments
+                let n = &edge.nodes;
+                if n.len() == 3 {
+                    segments.push((n[0], n[2]));
+                    segments.push((n[2], n[1]));
+                } else {
+                    segments.push((n[0], n[1]));
+                }
+            }
+            if segments.is_empty() {
+                return Err(format!("no boundary edges tagged '{}'", pair.master));
+            }
+            for &(a, b) in &segments {
+                length += (mesh.nodes[b].clone() - mesh.nodes[a].clone()).length().sqrt();
+                master_stiffness += diagonal[2 * a] + diagonal[2 * a + 1];
+                count += 1;
+            }
+            surfaces.push((slave, segments));
+        }
+        let tolerance = options.penetration_tolerance * length / count as f64;
+
+        let mut contact = ContactSystem {
+            multipliers: surfaces.iter().map(|s| vec![0.0; s.0.len()]).collect(),
+            surfaces,
+            nodes: &mesh.nodes,
+            penalty: options.penalty * master_stiffness / (2 * count) as f64,
+            stiffness: system.matrix,
+            forces: system.rhs,
+            fixed: system.fixed
+        };
+        let mut augmentations = 0;
+        loop {
+            let sol = newton_raphson(&mut contact, newton, solver)?;
+            let points = contact.contacts(&sol.u);
+            let penetration = points.iter().fold(0.0f64, |m, c| m.max(-c.gap));
+            if penetration <= tolerance || augmentations == options.max_augmentations {
+                let mut reactions = vec![Vec2(0.0, 0.0); mesh.node_count()];
+                for &(dof, _) in &contact.fixed {
+                    let r = sol.residual[dof];
+                    if dof % 2 == 0 { reactions[dof / 2].0 = r } else { reactions[dof / 2].1 = r }
+                }
+                return Ok(ContactSolution {
+                    displacement: sol.u.chunks(2).map(|c| Vec2(c[0], c[1])).collect(),
+                    reactions,
+                    contact: points,
+                    increments: sol.increments,
+                    augmentations
+                });
+            }
+            for (pair, (multipliers, (slave, _))) in contact.multipliers.iter_mut().zip(&contact.surfaces).enumerate() {
+                for (m, &node) in multipliers.iter_mut().zip(slave) {
+                    *m = points.iter().find(|c| c.pair == pair && c.node == node).map_or(0.0, |c| c.force);
+                }
+            }
+            augmentations += 1;
+        }
+    }
+}
+
+type Segment = (usize, usize);
+
+struct ContactSystem<'m> {
+    stiffness: CsrMatrix,
+    forces: Vec<f64>,
+    fixed: Vec<(usize, f64)>,
+    nodes: &'m [Vec2],
+    // slave nodes and master segments of every pair
+    surfaces: Vec<(Vec<usize>, Vec<Segment>)>,
+    penalty: f64,
+    // contact forces of the last augmentation per slave node
+    multipliers: Vec<Vec<f64>>
+}
+
+impl<'m> ContactSystem<'m> {
+    fn position(&self, u: &[f64], n: usize) -> Vec2 {
+        self.nodes[n].clone() + Vec2(u[2 * n], u[2 * n + 1])
+    }
+
+    // Closest master segment of every slave node in the deformed shape, for
+    // slave nodes within a segment length of the master boundary. The normals
+    // are averaged at the master nodes, so that the gap does not jump when a
+    // slave node passes from one segment to the next.
+    fn contacts(&self, u: &[f64]) -> Vec<ContactPoint> {
+        let mut points = Vec::new();
+        for (pair, ((slave, segments), multipliers)) in self.surfaces.iter().zip(&self.multipliers).enumerate() {
+            let mut normals: HashMap<usize, Vec2> = HashMap::new();
+            for &(a, b) in segments {
+                let t = self.position(u, b) - self.position(u, a);
+                let n = Vec2(t.1, -t.0) / t.dot(&t).sqrt();
+                for node in &[a, b] {
+                    let sum = normals.entry(*node).or_insert(Vec2(0.0, 0.0));
+                    *sum = sum.clone() + n.clone();
+                }
+            }
+            for (&node, multiplier) in slave.iter().zip(multipliers) {
+                let x = self.position(u, node);
+                let mut best: Option<(f64, ContactPoint)> = None;
+                for &(a, b) in segments.iter().filter(|s| s.0 != node && s.1 != node) {
+                    let xa = self.position(u, a);
+                    let t = self.position(u, b) - xa.clone();
+                    let length = t.dot(&t).sqrt();
+                    let xi = ((x.clone() - xa.clone()).dot(&t) / (length * length)).clamp(0.0, 1.0);
+                    let d = x.clone() - (xa + t.clone() * xi);
+                    let distance = d.dot(&d).sqrt();
+                    if distance > length || best.as_ref().map_or(false, |b| b.0 <= distance) {
+                        continue;
+                    }
+                    let (na, nb) = (&normals[&a], &normals[&b]);
+                    let normal = na.clone() / na.dot(na).sqrt() * (1.0 - xi) + nb.clone() / nb.dot(nb).sqrt() * xi;
+                    let normal = normal.clone() / normal.dot(&normal).sqrt();
+                    let gap = d.dot(&normal);
+                    let force = (multiplier - self.penalty * gap).max(0.0);
+                    best = Some((distance, ContactPoint { pair, node, segment: (a, b), xi, normal, gap, force }));
+                }
+                points.extend(best.map(|b| b.1));
+            }
+        }
+        points
+    }
+
+    // Gap gradient over the dofs of the slave node and the segment nodes
+    fn gap_gradient(c: &ContactPoint) -> Vec<f64> {
+        let n = &c.normal;
+        vec![n.0, n.1, -(1.0 - c.xi) * n.0, -(1.0 - c.xi) * n.1, -c.xi * n.0, -c.xi * n.1]
+    }
+
+    // Touching contacts count as closed, so that parts held only by their
+    // contact are not singular at the start
+    fn closed(c: &ContactPoint) -> bool {
+        c.force > 0.0 || c.gap <= 0.0
+    }
+}
+
+impl<'m> NonlinearProblem for ContactSystem<'m> {
+    fn dof_count(&self) -> usize { self.forces.len() }
+
+    fn constraints(&self, load_factor: f64) -> Result<Vec<(usize, f64)>, String> {
+        Ok(self.fixed.iter().map(|&(d, v)| (d, v * load_factor)).collect())
+    }
+
+    fn residual(&mut self, u: &[f64], load_factor: f64) -> Result<Vec<f64>, String> {
+        let mut r: Vec<f64> = self.stiffness.mul_vec(u).iter().zip(&self.forces)
+            .map(|(ku, f)| ku - load_factor * f)
+            .collect();
+        for c in self.contacts(u) {
+            let nodes = [c.node, c.segment.0, c.segment.1];
+            for (dof, g) in nodes.iter().flat_map(|&n| vec![2 * n, 2 * n + 1]).zip(ContactSystem::gap_gradient(&c)) {
+                r[dof] -= c.force * g;
+            }
+        }
+        Ok(r)
+    }
+
+    fn tangent(&mut self, u: &[f64], _load_factor: f64) -> Result<CsrMatrix, String> {
+        let mut asm = Assembler::new(self.nodes.len(), 2);
+        for c in self.contacts(u).iter().filter(|c| ContactSystem::closed(c)) {
+            let g = ContactSystem::gap_gradient(c);
+            let mut kc = DMatrix::zeros(6, 6);
+            for i in 0..6 {
+                for j in 0..6 {
+                    kc[(i, j)] = self.penalty * g[i] * g[j];
+                }
+            }
+            asm.add_matrix(&[c.node, c.segment.0, c.segment.1], &kc);
+        }
+        Ok(self.stiffness.scaled_sum(1.0, &asm.finish().0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing::Mesh;
+    use analysis::fixtures::{stacked, stacked_problem};
+
+    fn problem(mesh: &Mesh) -> ContactProblem<'_> {
+        let mut p = ContactProblem::new(stacked_problem(mesh));
+        p.add_pair(ContactPair { slave: "upper_bottom".to_string(), master: "top".to_string() });
+        p
+    }
+
+    #[test]
+    fn pressed_blocks() {
+        // linear elements on non-matching meshes: 3 slave edges on 2 master
+        // edges
+        for &(kind, divisions) in [(ElementKind::Quad4, 3), (ElementKind::Quad8, 2)].iter() {
+            let mesh = stacked(kind, 0.0, divisions, 2);
+            let mut p = problem(&mesh);
+            p.structure.add_load(Load::Pressure { tag: "upper_top".to_string(), pressure: 50.0 });
+            // a soft penalty, corrected by the augmentations
+            let options = ContactOptions { penalty: 1.0, max_augmentations: 20, penetration_tolerance: 1e-7 };
+            let sol = p.solve(&options, &NewtonOptions::default(), &LinearSolver::default()).unwrap();
+            let force = sol.contact_force(0);
+            assert!(force.0.abs() < 1e-3 && (force.1 - 50.0).abs() < 1e-6, "{:?}", force);
+            assert!(sol.max_penetration() <= 1e-7 * 0.5);
+            assert!(sol.augmentations > 0);
+            // both blocks compressed by 50 / E, up to the slightly uneven load
+            // transfer of node-to-segment contact on non-matching meshes
+            let expected = -2.0 * 50.0 / 200e3;
+            for n in mesh.boundary_nodes("upper_top") {
+                assert!((sol.displacement[n].1 / expected - 1.0).abs() < 0.01, "{:?}", sol.displacement[n]);
+            }
+        }
+    }
+
+    #[test]
+    fn gap_closes_under_prescribed_displacement() {
+        let mesh = stacked(ElementKind::Quad4, 0.001, 2, 2);
+        let close = |uy: f64| {
+            let mut p = problem(&mesh);
+            p.structure.add_support(Support::Displacement { tag: "upper_top".to_string(), ux: None, uy: Some(uy) });
+            let newton = NewtonOptions { initial_step: 0.25, ..Default::default() };
+            let options = ContactOptions { penetration_tolerance: 1e-7, ..Default::default() };
+            p.solve(&options, &newton, &LinearSolver::default()).unwrap()
+        };
+        let support = |sol: &ContactSolution| mesh.boundary_nodes("bottom").iter()
+            .map(|&n| sol.reactions[n].1).sum::<f64>();
+        // the gap stays open
+        let sol = close(-0.0005);
+        assert!(!sol.contact.is_empty() && sol.contact.iter().all(|c| c.force == 0.0 && c.gap > 0.0));
+        assert!(support(&sol).abs() < 1e-9);
+
+        // 0.002 shortening shared by both blocks: s = E 0.001
+        let sol = close(-0.003);
+        assert!((sol.contact_force(0).1 - 200.0).abs() < 0.1, "{:?}", sol.contact_force(0));
+        assert!((support(&sol) - 200.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn invalid_pairs() {
+        let mesh = stacked(ElementKind::Quad4, 0.0, 2, 2);
+        let mut p = problem(&mesh);
+        p.add_pair(ContactPair { slave: "upper_bottom".to_string(), master: "nowhere".to_string() });
+        assert!(p.solve(&ContactOptions::default(), &NewtonOptions::default(), &LinearSolver::default()).is_err());
+
+        let unpaired = ContactProblem::new(ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 1.0));
+        assert!(unpaired.solve(&ContactOptions::default(), &NewtonOptions::default(), &LinearSolver::default())
+            .is_err());
+
+        let axisymmetric = ContactProblem::new(ElasticityProblem::new(&mesh, PlaneMode::Axisymmetric, 1.0));
+        assert!(axisymmetric.solve(&ContactOptions::default(), &NewtonOptions::default(), &LinearSolver::default())
+            .is_err());
+    }
+}
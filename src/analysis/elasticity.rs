@@ -0,0 +1,816 @@
+use std::collections::HashMap;
+use base_types::*;
+use fem::shape::{self, ElementKind, IsoPoint};
+use meshing::{Mesh, Element, PointLocator};
+use solvers::LinearSolver;
+use super::assembly::{Assembler, LinearSystem, Symmetry};
+use super::constraints::*;
+use super::scalar::mass_element;
+
+// Axisymmetric models are r-z half sections with x as the radius; the strain
+// vector then gains the hoop component e_tt as its fourth entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaneMode {
+    PlaneStress,
+    PlaneStrain,
+    Axisymmetric
+}
+
+impl PlaneMode {
+    pub fn symmetry(&self) -> Symmetry {
+        match self {
+            PlaneMode::Axisymmetric => Symmetry::Axisymmetric,
+            _ => Symmetry::Planar
+        }
+    }
+
+    pub fn strain_components(&self) -> usize {
+        match self {
+            PlaneMode::Axisymmetric => 4,
+            _ => 3
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElasticMaterial {
+    Isotropic { young: f64, poisson: f64 },
+    // Material axis 1 is rotated by `angle` (radians) from the x axis, axis 3
+    // is out of plane (the hoop direction for axisymmetric models).
+    Orthotropic {
+        e1: f64, e2: f64, e3: f64,
+        nu12: f64, nu13: f64, nu23: f64,
+        g12: f64,
+        angle: f64
+    }
+}
+
+impl ElasticMaterial {
+    // Stress-strain matrix for [s_xx, s_yy, s_xy] = D [e_xx, e_yy, g_xy], with
+    // s_tt and e_tt appended for axisymmetric models
+    pub fn d_matrix(&self, mode: PlaneMode) -> Result<DMatrix, String> {
+        match *self {
+            ElasticMaterial::Isotropic { young: e, poisson: nu } => {
+                if e <= 0.0 || nu <= -1.0 || nu >= 0.5 {
+                    return Err(format!("invalid isotropic constants E = {}, nu = {}", e, nu));
+                }
+                Ok(match mode {
+                    PlaneMode::PlaneStress => {
+                        let c = e / (1.0 - nu * nu);
+                        DMatrix::from_rows(&[&[c, c * nu, 0.0],
+                                             &[c * nu, c, 0.0],
+                                             &[0.0, 0.0, c * (1.0 - nu) / 2.0]])
+                    },
+                    PlaneMode::PlaneStrain => {
+                        let c = e / ((1.0 + nu) * (1.0 - 2.0 * nu));
+                        DMatrix::from_rows(&[&[c * (1.0 - nu), c * nu, 0.0],
+                                             &[c * nu, c * (1.0 - nu), 0.0],
+                                             &[0.0, 0.0, c * (1.0 - 2.0 * nu) / 2.0]])
+                    },
+                    PlaneMode::Axisymmetric => {
+                        let c = e / ((1.0 + nu) * (1.0 - 2.0 * nu));
+                        DMatrix::from_rows(&[&[c * (1.0 - nu), c * nu, 0.0, c * nu],
+                                             &[c * nu, c * (1.0 - nu), 0.0, c * nu],
+                                             &[0.0, 0.0, c * (1.0 - 2.0 * nu) / 2.0, 0.0],
+                                             &[c * nu, c * nu, 0.0, c * (1.0 - nu)]])
+                    }
+                })
+            },
+            ElasticMaterial::Orthotropic { e1, e2, e3, nu12, nu13, nu23, g12, angle } => {
+                // compliance in the material axes
+                let (mut s11, mut s22, mut s12) = (1.0 / e1, 1.0 / e2, -nu12 / e1);
+                let (s13, s23, s33) = (-nu13 / e1, -nu23 / e2, 1.0 / e3);
+                let compliance = match mode {
+                    PlaneMode::Axisymmetric =>
+                        DMatrix::from_rows(&[&[s11, s12, 0.0, s13],
+                                             &[s12, s22, 0.0, s23],
+                                             &[0.0, 0.0, 1.0 / g12, 0.0],
+                                             &[s13, s23, 0.0, s33]]),
+                    _ => {
+                        if mode == PlaneMode::PlaneStrain {
+                            // condense out s_33 using e_33 = 0
+                            s11 -= s13 * s13 / s33;
+                            s22 -= s23 * s23 / s33;
+                            s12 -= s13 * s23 / s33;
+                        }
+                        DMatrix::from_rows(&[&[s11, s12, 0.0],
+                                             &[s12, s22, 0.0],
+                                             &[0.0, 0.0, 1.0 / g12]])
+                    }
+                };
+                let local = compliance.inverse()
+                    .map_err(|_| "orthotropic constants give a singular compliance".to_string())?;
+                if local.cholesky().is_err() {
+                    return Err("orthotropic constants are not positive definite".to_string());
+                }
+
+                // strain transformation, e_local = T e_global; the out of
+                // plane component is unaffected by the rotation
+                let (c, s) = (angle.cos(), angle.sin());
+                let mut t = DMatrix::identity(mode.strain_components());
+                t[(0, 0)] = c * c;
+                t[(0, 1)] = s * s;
+                t[(0, 2)] = c * s;
+                t[(1, 0)] = s * s;
+                t[(1, 1)] = c * c;
+                t[(1, 2)] = -c * s;
+                t[(2, 0)] = -2.0 * c * s;
+                t[(2, 1)] = 2.0 * c * s;
+                t[(2, 2)] = c * c - s * s;
+                Ok(t.tr_matmul(&local.matmul(&t)))
+            }
+        }
+    }
+
+    // Stress of a fully restrained isotropic expansion `strain`, so that
+    // s = D e - s0 for the total strain e. Plane strain models restrain the
+    // out of plane expansion as well.
+    pub fn thermal_stress(&self, mode: PlaneMode, strain: f64) -> Result<Vec<f64>, String> {
+        let mut s0 = match mode {
+            PlaneMode::PlaneStress => self.d_matrix(mode)?.mul_vec(&[strain, strain, 0.0]),
+            _ => self.d_matrix(PlaneMode::Axisymmetric)?.mul_vec(&[strain, strain, 0.0, strain])
+        };
+        s0.truncate(mode.strain_components());
+        Ok(s0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y
+}
+
+impl Axis {
+    pub fn index(&self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Support {
+    // Both displacement components fixed
+    Fixed { tag: String },
+    // Displacement along `axis` fixed, free to slide in the other direction
+    Roller { tag: String, axis: Axis },
+    // Prescribed displacement, `None` leaves the component free
+    Displacement { tag: String, ux: Option<f64>, uy: Option<f64> }
+}
+
+#[derive(Debug, Clone)]
+pub enum Load {
+    // Force per unit volume on a region (e.g. gravity)
+    BodyForce { region: String, force: Vec2 },
+    // Concentrated force applied to the node closest to `position`, which must
+    // lie within `tolerance` of it; for axisymmetric models this is the total
+    // force of a ring load
+    Point { position: Vec2, force: Vec2, tolerance: f64 },
+    // Normal pressure on the boundary, positive pushing into the part
+    Pressure { tag: String, pressure: f64 },
+    // Traction vector on the boundary
+    Traction { tag: String, traction: Vec2 }
+}
+
+#[derive(Debug, Clone)]
+pub struct ElasticSolution {
+    pub displacement: Vec<Vec2>,
+    // Support reactions, zero at unconstrained nodes
+    pub reactions: Vec<Vec2>
+}
+
+impl ElasticSolution {
+    pub fn total_reaction(&self) -> Vec2 {
+        self.reactions.iter().fold(Vec2(0.0, 0.0), |acc, r| acc + r.clone())
+    }
+}
+
+// Strain-displacement matrix of an integration point, rows (e_xx, e_yy, g_xy)
+// plus the hoop strain u_r / r for axisymmetric models
+pub fn b_matrix(iso: &IsoPoint, mode: PlaneMode) -> DMatrix {
+    let n = iso.grad.len();
+    let mut b = DMatrix::zeros(mode.strain_components(), 2 * n);
+    for (a, g) in iso.grad.iter().enumerate() {
+        b[(0, 2 * a)] = g.0;
+        b[(1, 2 * a + 1)] = g.1;
+        b[(2, 2 * a)] = g.1;
+        b[(2, 2 * a + 1)] = g.0;
+        if mode == PlaneMode::Axisymmetric {
+            b[(3, 2 * a)] = iso.n[a] / iso.x.0;
+        }
+    }
+    b
+}
+
+// Linear elastic analysis of a 2D mesh. `thickness` only applies to plane
+// stress and plane strain models.
+pub struct ElasticityProblem<'a> {
+    mesh: &'a Mesh,
+    mode: PlaneMode,
+    thickness: f64,
+    materials: HashMap<String, ElasticMaterial>,
+    densities: HashMap<String, f64>,
+    supports: Vec<Support>,
+    loads: Vec<Load>,
+    expansion: HashMap<String, f64>,
+    // nodal temperatures and the stress free temperature
+    temperature: Option<Vec<f64>>,
+    reference_temperature: f64,
+    mpcs: Vec<Mpc>,
+    ties: Vec<Tie>,
+    constraint_method: ConstraintMethod
+}
+
+impl<'a> ElasticityProblem<'a> {
+    pub fn new(mesh: &'a Mesh, mode: PlaneMode, thickness: f64) -> ElasticityProblem<'a> {
+        ElasticityProblem {
+            mesh, mode, thickness,
+            materials: HashMap::new(),
+            densities: HashMap::new(),
+            supports: Vec::new(),
+            loads: Vec::new(),
+            expansion: HashMap::new(),
+            temperature: None,
+            reference_temperature: 0.0,
+            mpcs: Vec::new(),
+            ties: Vec::new(),
+            constraint_method: ConstraintMethod::default()
+        }
+    }
+
+    pub fn mesh(&self) -> &'a Mesh { self.mesh }
+    pub fn mode(&self) -> PlaneMode { self.mode }
+    pub fn thickness(&self) -> f64 { self.thickness }
+
+    // Integration weight besides the quadrature weight: the thickness for
+    // planar models, 2 pi r for axisymmetric ones
+    pub fn weight(&self, x: &Vec2) -> f64 {
+        match self.mode {
+            PlaneMode::Axisymmetric => self.mode.symmetry().weight(x),
+            _ => self.thickness
+        }
+    }
+
+    pub fn set_material(&mut self, region: &str, material: ElasticMaterial) {
+        self.materials.insert(region.to_string(), material);
+    }
+
+    pub fn material(&self, region: &str) -> Result<&ElasticMaterial, String> {
+        self.materials.get(region)
+            .ok_or_else(|| format!("no material assigned to region '{}'", region))
+    }
+
+    // Mass density, only needed for dynamic analyses
+    pub fn set_density(&mut self, region: &str, density: f64) {
+        self.densities.insert(region.to_string(), density);
+    }
+
+    pub fn density(&self, region: &str) -> Result<f64, String> {
+        self.densities.get(region).cloned()
+            .ok_or_else(|| format!("no density given for region '{}'", region))
+    }
+
+    // Consistent mass matrix, the same for both displacement components
+    pub fn mass_matrix(&self) -> Result<CsrMatrix, String> {
+        let mesh = self.mesh;
+        let symmetry = self.mode.symmetry();
+        symmetry.check(mesh)?;
+        let scale = if symmetry == Symmetry::Planar { self.thickness } else { 1.0 };
+        let mut asm = Assembler::new(mesh.node_count(), 2);
+        for e in &mesh.elements {
+            let rho = self.density(&e.region)? * scale;
+            let me = mass_element(e.kind, &mesh.coords(&e.nodes), rho, symmetry)?;
+            let n = e.nodes.len();
+            let mut me2 = DMatrix::zeros(2 * n, 2 * n);
+            for a in 0..n {
+                for b in 0..n {
+                    me2[(2 * a, 2 * b)] = me[(a, b)];
+                    me2[(2 * a + 1, 2 * b + 1)] = me[(a, b)];
+                }
+            }
+            asm.add_matrix(&e.nodes, &me2);
+        }
+        Ok(asm.finish().0)
+    }
+
+    // Linear thermal expansion coefficient, only needed with a temperature
+    // field
+    pub fn set_thermal_expansion(&mut self, region: &str, alpha: f64) {
+        self.expansion.insert(region.to_string(), alpha);
+    }
+
+    // Nodal temperatures (e.g. of a heat solution on the same mesh); the
+    // part is stress free at the uniform `reference` temperature
+    pub fn set_temperature(&mut self, temperature: &[f64], reference: f64) -> Result<(), String> {
+        if temperature.len() != self.mesh.node_count() {
+            return Err(format!("{} temperatures for a mesh of {} nodes", temperature.len(),
+                               self.mesh.node_count()));
+        }
+        self.temperature = Some(temperature.to_vec());
+        self.reference_temperature = reference;
+        Ok(())
+    }
+
+    // Temperatures of a solution on another mesh of the same part,
+    // interpolated at the nodes. Nodes outside the source mesh by more than
+    // `tolerance`, as on differently meshed curved boundaries, are an error.
+    pub fn set_temperature_from(&mut self, source: &Mesh, temperature: &[f64], reference: f64, tolerance: f64)
+        -> Result<(), String> {
+        if temperature.len() != source.node_count() {
+            return Err(format!("{} temperatures for a mesh of {} nodes", temperature.len(), source.node_count()));
+        }
+        let mapped = PointLocator::new(source).transfer(temperature, self.mesh, tolerance)?;
+        self.set_temperature(&mapped, reference)
+    }
+
+    // Free thermal strain at an integration point of `element`
+    pub fn thermal_strain(&self, element: &Element, iso: &IsoPoint) -> Result<f64, String> {
+        let temperature = match self.temperature {
+            Some(ref t) => t,
+            None => return Ok(0.0)
+        };
+        let alpha = self.expansion.get(&element.region)
+            .ok_or_else(|| format!("no thermal expansion coefficient for region '{}'", element.region))?;
+        let t: f64 = iso.n.iter().zip(&element.nodes).map(|(n, &a)| n * temperature[a]).sum();
+        Ok(alpha * (t - self.reference_temperature))
+    }
+
+    // Initial stress s0 of the thermal strain at an integration point
+    pub fn thermal_stress(&self, element: &Element, iso: &IsoPoint) -> Result<Vec<f64>, String> {
+        let strain = self.thermal_strain(element, iso)?;
+        if strain == 0.0 {
+            return Ok(vec![0.0; self.mode.strain_components()]);
+        }
+        self.material(&element.region)?.thermal_stress(self.mode, strain)
+    }
+
+    pub fn add_support(&mut self, support: Support) {
+        self.supports.push(support);
+    }
+
+    pub fn add_load(&mut self, load: Load) {
+        self.loads.push(load);
+    }
+
+    // Constraint equation over the dofs 2 n (u_x) and 2 n + 1 (u_y)
+    pub fn add_mpc(&mut self, mpc: Mpc) {
+        self.mpcs.push(mpc);
+    }
+
+    pub fn add_tie(&mut self, tie: Tie) {
+        self.ties.push(tie);
+    }
+
+    pub fn set_constraint_method(&mut self, method: ConstraintMethod) {
+        self.constraint_method = method;
+    }
+
+    // Constraint equations of all MPCs and ties
+    pub fn linear_constraints(&self) -> Result<Vec<Mpc>, String> {
+        let mut mpcs = self.mpcs.clone();
+        for tie in &self.ties {
+            mpcs.extend(tie.constraints(self.mesh, 2)?);
+        }
+        Ok(mpcs)
+    }
+
+    pub fn element_stiffness(&self, kind: ElementKind, coords: &[Vec2], d: &DMatrix)
+        -> Result<DMatrix, String> {
+        let n = 2 * kind.node_count();
+        let mut ke = DMatrix::zeros(n, n);
+        for q in kind.quadrature(kind.quadrature_degree()) {
+            let iso = shape::map(kind, coords, &q.point)?;
+            ke.add_btdb(&b_matrix(&iso, self.mode), d, q.weight * iso.det_j * self.weight(&iso.x));
+        }
+        Ok(ke)
+    }
+
+    fn body_force(&self, kind: ElementKind, coords: &[Vec2], force: &Vec2) -> Result<Vec<f64>, String> {
+        let mut fe = vec![0.0; 2 * kind.node_count()];
+        for q in kind.quadrature(kind.quadrature_degree()) {
+            let iso = shape::map(kind, coords, &q.point)?;
+            let w = q.weight * iso.det_j * self.weight(&iso.x);
+            for (a, n) in iso.n.iter().enumerate() {
+                fe[2 * a] += force.0 * n * w;
+                fe[2 * a + 1] += force.1 * n * w;
+            }
+        }
+        Ok(fe)
+    }
+
+    // Edge load from a traction given as a function of the outward normal
+    fn edge_traction<F>(&self, kind: ElementKind, coords: &[Vec2], traction: F) -> Result<Vec<f64>, String>
+        where F: Fn(&Vec2) -> Vec2 {
+        let mut fe = vec![0.0; 2 * kind.node_count()];
+        for q in kind.quadrature(kind.quadrature_degree()) {
+            let e = shape::map_edge(kind, coords, q.point.0)?;
+            let t = traction(&e.normal);
+            let w = q.weight * e.det_j * self.weight(&e.x);
+            for (a, n) in e.n.iter().enumerate() {
+                fe[2 * a] += t.0 * n * w;
+                fe[2 * a + 1] += t.1 * n * w;
+            }
+        }
+        Ok(fe)
+    }
+
+    fn nearest_node(&self, p: &Vec2, tolerance: f64) -> Result<usize, String> {
+        let (node, distance) = self.mesh.nodes.iter().enumerate()
+            .map(|(i, n)| (i, (n.clone() - p.clone()).length().sqrt()))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .ok_or_else(|| "cannot apply a point load to an empty mesh".to_string())?;
+        if distance > tolerance {
+            return Err(format!("point load at {:?} is {} away from the closest node", p, distance));
+        }
+        Ok(node)
+    }
+
+    pub fn assemble(&self) -> Result<LinearSystem, String> {
+        let mesh = self.mesh;
+        self.mode.symmetry().check(mesh)?;
+        let mut asm = Assembler::new(mesh.node_count(), 2);
+        let mut d_cache: HashMap<&str, DMatrix> = HashMap::new();
+        for e in &mesh.elements {
+            if !d_cache.contains_key(e.region.as_str()) {
+                let d = self.material(&e.region)?.d_matrix(self.mode)?;
+                d_cache.insert(&e.region, d);
+            }
+            let coords = mesh.coords(&e.nodes);
+            let ke = self.element_stiffness(e.kind, &coords, &d_cache[e.region.as_str()])?;
+            asm.add_matrix(&e.nodes, &ke);
+        }
+        let matrix = asm.finish().0;
+        let rhs = self.external_forces()?.iter().zip(self.thermal_forces()?).map(|(f, t)| f + t).collect();
+        Ok(LinearSystem { matrix, rhs, fixed: self.constraints()? })
+    }
+
+    // Equivalent forces int(B^T s0 dV) of the thermal strains
+    pub fn thermal_forces(&self) -> Result<Vec<f64>, String> {
+        let mesh = self.mesh;
+        let mut asm = Assembler::new(mesh.node_count(), 2);
+        if self.temperature.is_some() {
+            for e in &mesh.elements {
+                let coords = mesh.coords(&e.nodes);
+                let mut fe = vec![0.0; 2 * e.nodes.len()];
+                for q in e.kind.quadrature(e.kind.quadrature_degree()) {
+                    let iso = shape::map(e.kind, &coords, &q.point)?;
+                    let w = q.weight * iso.det_j * self.weight(&iso.x);
+                    let s0 = self.thermal_stress(e, &iso)?;
+                    for (f, v) in fe.iter_mut().zip(b_matrix(&iso, self.mode).tr_mul_vec(&s0)) {
+                        *f += v * w;
+                    }
+                }
+                asm.add_vector(&e.nodes, &fe);
+            }
+        }
+        Ok(asm.finish().1)
+    }
+
+    // Load vector of all loads
+    pub fn external_forces(&self) -> Result<Vec<f64>, String> {
+        let mesh = self.mesh;
+        let mut asm = Assembler::new(mesh.node_count(), 2);
+        for load in &self.loads {
+            match load {
+                Load::BodyForce { region, force } => {
+                    if !mesh.elements.iter().any(|e| e.region == *region) {
+                        return Err(format!("no elements in region '{}'", region));
+                    }
+                    for e in mesh.elements.iter().filter(|e| e.region == *region) {
+                        let fe = self.body_force(e.kind, &mesh.coords(&e.nodes), force)?;
+                        asm.add_vector(&e.nodes, &fe);
+                    }
+                },
+                Load::Point { position, force, tolerance } => {
+                    let n = self.nearest_node(position, *tolerance)?;
+                    asm.add_to_rhs(2 * n, force.0);
+                    asm.add_to_rhs(2 * n + 1, force.1);
+                },
+                Load::Pressure { tag, pressure } => {
+                    mesh.check_tag(tag)?;
+                    for edge in mesh.boundary_with_tag(tag) {
+                        let fe = self.edge_traction(edge.kind, &mesh.coords(&edge.nodes),
+                                                    |normal| normal.clone() * -pressure)?;
+                        asm.add_vector(&edge.nodes, &fe);
+                    }
+                },
+                Load::Traction { tag, traction } => {
+                    mesh.check_tag(tag)?;
+                    for edge in mesh.boundary_with_tag(tag) {
+                        let fe = self.edge_traction(edge.kind, &mesh.coords(&edge.nodes),
+                                                    |_| traction.clone())?;
+                        asm.add_vector(&edge.nodes, &fe);
+                    }
+                }
+            }
+        }
+        Ok(asm.finish().1)
+    }
+
+    // Prescribed dofs of all supports as (dof, value)
+    pub fn constraints(&self) -> Result<Vec<(usize, f64)>, String> {
+        let mut fixed = Vec::new();
+        for support in &self.supports {
+            let (tag, ux, uy) = match support {
+                Support::Fixed { tag } => (tag, Some(0.0), Some(0.0)),
+                Support::Roller { tag, axis: Axis::X } => (tag, Some(0.0), None),
+                Support::Roller { tag, axis: Axis::Y } => (tag, None, Some(0.0)),
+                Support::Displacement { tag, ux, uy } => (tag, *ux, *uy)
+            };
+            self.mesh.check_tag(tag)?;
+            for n in self.mesh.boundary_nodes(tag) {
+                if let Some(v) = ux {
+                    fixed.push((2 * n, v));
+                }
+                if let Some(v) = uy {
+                    fixed.push((2 * n + 1, v));
+                }
+            }
+        }
+        Ok(fixed)
+    }
+
+    pub fn solve(&self, solver: &LinearSolver) -> Result<ElasticSolution, String> {
+        let system = self.assemble()?;
+        let u = solve_constrained(&system, &self.linear_constraints()?, self.constraint_method, solver)?;
+        Ok(ElasticSolution {
+            displacement: u.chunks(2).map(|c| Vec2(c[0], c[1])).collect(),
+            reactions: reactions(&system, &u)
+        })
+    }
+}
+
+// Element displacement vector [u0, v0, u1, v1, ...]
+pub fn element_displacements(nodes: &[usize], displacement: &[Vec2]) -> Vec<f64> {
+    nodes.iter().flat_map(|&n| vec![displacement[n].0, displacement[n].1]).collect()
+}
+
+// R = K u - f on the constrained dofs
+pub fn reactions(system: &LinearSystem, u: &[f64]) -> Vec<Vec2> {
+    let ku = system.matrix.mul_vec(u);
+    let mut r = vec![0.0; u.len()];
+    for &(dof, _) in &system.fixed {
+        r[dof] = ku[dof] - system.rhs[dof];
+    }
+    r.chunks(2).map(|c| Vec2(c[0], c[1])).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use meshing;
+    use analysis::heat::*;
+    use analysis::stress::recover_stresses;
+
+    fn steel() -> ElasticMaterial {
+        ElasticMaterial::Isotropic { young: 200e3, poisson: 0.3 }
+    }
+
+    fn uniaxial(kind: ElementKind, mode: PlaneMode) -> (Mesh, ElasticSolution) {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(4.0, 1.0), 4, 2, kind, "steel");
+        let sol = {
+            let mut p = ElasticityProblem::new(&mesh, mode, 2.0);
+            p.set_material("steel", steel());
+            p.add_support(Support::Roller { tag: "left".to_string(), axis: Axis::X });
+            p.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+            p.add_load(Load::Traction { tag: "right".to_string(), traction: Vec2(100.0, 0.0) });
+            p.solve(&LinearSolver::default()).unwrap()
+        };
+        (mesh, sol)
+    }
+
+    #[test]
+    fn uniaxial_tension() {
+        for kind in [ElementKind::Tri3, ElementKind::Tri6, ElementKind::Quad4,
+                     ElementKind::Quad8, ElementKind::Quad9].iter() {
+            let (mesh, sol) = uniaxial(*kind, PlaneMode::PlaneStress);
+            for (p, u) in mesh.nodes.iter().zip(&sol.displacement) {
+                assert!((u.0 - 100.0 * p.0 / 200e3).abs() < 1e-12, "{:?}", kind);
+                assert!((u.1 + 0.3 * 100.0 * p.1 / 200e3).abs() < 1e-12, "{:?}", kind);
+            }
+            // sigma * height * thickness
+            let r = sol.total_reaction();
+            assert!((r.0 + 200.0).abs() < 1e-8 && r.1.abs() < 1e-8, "{:?}", r);
+        }
+    }
+
+    #[test]
+    fn plane_strain_is_stiffer() {
+        let (mesh, sol) = uniaxial(ElementKind::Quad4, PlaneMode::PlaneStrain);
+        let (e, nu) = (200e3, 0.3);
+        for (p, u) in mesh.nodes.iter().zip(&sol.displacement) {
+            assert!((u.0 - (1.0 - nu * nu) * 100.0 * p.0 / e).abs() < 1e-12);
+            assert!((u.1 + nu * (1.0 + nu) * 100.0 * p.1 / e).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn orthotropic_reduces_to_isotropic() {
+        let (e, nu) = (70e3, 0.33);
+        let ortho = ElasticMaterial::Orthotropic {
+            e1: e, e2: e, e3: e, nu12: nu, nu13: nu, nu23: nu,
+            g12: e / (2.0 * (1.0 + nu)), angle: 0.7 };
+        let iso = ElasticMaterial::Isotropic { young: e, poisson: nu };
+        for mode in [PlaneMode::PlaneStress, PlaneMode::PlaneStrain].iter() {
+            let a = ortho.d_matrix(*mode).unwrap();
+            let b = iso.d_matrix(*mode).unwrap();
+            assert!(a.data().iter().zip(b.data()).all(|(x, y)| (x - y).abs() < 1e-8 * e));
+        }
+    }
+
+    #[test]
+    fn rotated_orthotropic() {
+        // a 90 degree rotation swaps the stiff and soft axes
+        let m = |angle| ElasticMaterial::Orthotropic {
+            e1: 140e3, e2: 10e3, e3: 10e3, nu12: 0.3, nu13: 0.3, nu23: 0.45,
+            g12: 5e3, angle };
+        let d0 = m(0.0).d_matrix(PlaneMode::PlaneStress).unwrap();
+        let d90 = m(::std::f64::consts::FRAC_PI_2).d_matrix(PlaneMode::PlaneStress).unwrap();
+        assert!((d0[(0, 0)] - d90[(1, 1)]).abs() < 1e-6);
+        assert!((d0[(2, 2)] - d90[(2, 2)]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cantilever_tip_deflection() {
+        let (l, h, t, e, f) = (10.0, 1.0, 0.1, 1e4, -1.0);
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(l, h), 20, 4, ElementKind::Quad8, "beam");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, t);
+        p.set_material("beam", ElasticMaterial::Isotropic { young: e, poisson: 0.0 });
+        p.add_support(Support::Fixed { tag: "left".to_string() });
+        p.add_load(Load::Traction { tag: "right".to_string(), traction: Vec2(0.0, f / (h * t)) });
+        let sol = p.solve(&LinearSolver::default()).unwrap();
+
+        // Timoshenko beam: bending plus shear deflection
+        let i = t * h * h * h / 12.0;
+        let g = e / 2.0;
+        let expected = f * l * l * l / (3.0 * e * i) + f * l / (5.0 / 6.0 * g * h * t);
+        let tip = mesh.boundary_nodes("right").iter()
+            .map(|&n| sol.displacement[n].1)
+            .sum::<f64>() / mesh.boundary_nodes("right").len() as f64;
+        assert!(((tip - expected) / expected).abs() < 0.02, "{} vs {}", tip, expected);
+        assert!((sol.total_reaction().1 + f).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gravity_and_pressure_reactions() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 3.0), 2, 6, ElementKind::Tri6, "col");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 0.5);
+        p.set_material("col", steel());
+        p.add_support(Support::Fixed { tag: "bottom".to_string() });
+        p.add_load(Load::BodyForce { region: "col".to_string(), force: Vec2(0.0, -7.85e-5) });
+        p.add_load(Load::Pressure { tag: "top".to_string(), pressure: 10.0 });
+        p.add_load(Load::Point { position: Vec2(1.0, 3.1), force: Vec2(2.0, 0.0), tolerance: 0.2 });
+        let sol = p.solve(&LinearSolver::default()).unwrap();
+        let r = sol.total_reaction();
+        let weight = 7.85e-5 * 3.0 * 0.5;
+        assert!((r.1 - (weight + 10.0 * 0.5)).abs() < 1e-9, "{:?}", r);
+        assert!((r.0 + 2.0).abs() < 1e-9);
+
+        // loads that miss the mesh
+        let mut far = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 0.5);
+        far.set_material("col", steel());
+        far.add_load(Load::Point { position: Vec2(1.0, 3.5), force: Vec2(2.0, 0.0), tolerance: 0.2 });
+        assert!(far.external_forces().is_err());
+        for load in [Load::BodyForce { region: "beam".to_string(), force: Vec2(0.0, 1.0) },
+                         Load::Pressure { tag: "rigth".to_string(), pressure: 1.0 },
+                         Load::Traction { tag: "rigth".to_string(), traction: Vec2(1.0, 0.0) }] {
+            let mut typo = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 0.5);
+            typo.add_load(load);
+            assert!(typo.external_forces().is_err());
+        }
+    }
+
+    #[test]
+    fn axisymmetric_axial_tension() {
+        // solid bar touching the axis: u_z = s z / E, u_r = -nu s r / E
+        for kind in [ElementKind::Tri3, ElementKind::Quad4, ElementKind::Quad8].iter() {
+            let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 3.0), 2, 3, *kind, "steel");
+            let mut p = ElasticityProblem::new(&mesh, PlaneMode::Axisymmetric, 1.0);
+            p.set_material("steel", steel());
+            p.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+            p.add_support(Support::Roller { tag: "left".to_string(), axis: Axis::X });
+            p.add_load(Load::Traction { tag: "top".to_string(), traction: Vec2(0.0, 50.0) });
+            let sol = p.solve(&LinearSolver::default()).unwrap();
+            for (x, u) in mesh.nodes.iter().zip(&sol.displacement) {
+                assert!((u.1 - 50.0 * x.1 / 200e3).abs() < 1e-12, "{:?}", kind);
+                assert!((u.0 + 0.3 * 50.0 * x.0 / 200e3).abs() < 1e-12, "{:?}", kind);
+            }
+            let area = ::std::f64::consts::PI;
+            assert!((sol.total_reaction().1 + 50.0 * area).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn thick_cylinder_internal_pressure() {
+        // Lame solution with the axial strain suppressed
+        let (a, b, pressure, e, nu) = (1.0, 2.0, 100.0, 200e3, 0.3);
+        let mesh = meshing::rectangle(Vec2(a, 0.0), Vec2(b - a, 0.5), 8, 2, ElementKind::Quad8, "tube");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::Axisymmetric, 1.0);
+        p.set_material("tube", steel());
+        p.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+        p.add_support(Support::Roller { tag: "top".to_string(), axis: Axis::Y });
+        p.add_load(Load::Pressure { tag: "left".to_string(), pressure });
+        let sol = p.solve(&LinearSolver::default()).unwrap();
+
+        let ca = pressure * a * a / (b * b - a * a);
+        let cb = ca * b * b;
+        for (x, u) in mesh.nodes.iter().zip(&sol.displacement) {
+            let r = x.0;
+            let exact = r / e * (ca * (1.0 - nu - 2.0 * nu * nu) + cb * (1.0 + nu) / (r * r));
+            assert!(((u.0 - exact) / exact).abs() < 1e-4, "{} vs {}", u.0, exact);
+        }
+    }
+
+    #[test]
+    fn axisymmetric_rejects_negative_radius() {
+        let mesh = meshing::rectangle(Vec2(-1.0, 0.0), Vec2(2.0, 1.0), 2, 1, ElementKind::Quad4, "steel");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::Axisymmetric, 1.0);
+        p.set_material("steel", steel());
+        assert!(p.assemble().is_err());
+    }
+
+    #[test]
+    fn free_thermal_expansion() {
+        // uniform heating by 100 K: no stresses apart from s_zz in plane strain
+        let alpha = 1.2e-5;
+        let cases = [(PlaneMode::PlaneStress, 1.0), (PlaneMode::PlaneStrain, 1.3), (PlaneMode::Axisymmetric, 1.0)];
+        for &(mode, factor) in cases.iter() {
+            let mesh = meshing::rectangle(Vec2(1.0, 0.0), Vec2(3.0, 1.0), 4, 2, ElementKind::Quad8, "steel");
+            let mut p = ElasticityProblem::new(&mesh, mode, 1.0);
+            p.set_material("steel", steel());
+            p.set_thermal_expansion("steel", alpha);
+            p.set_temperature(&vec![120.0; mesh.node_count()], 20.0).unwrap();
+            p.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+            if mode != PlaneMode::Axisymmetric {
+                p.add_support(Support::Roller { tag: "left".to_string(), axis: Axis::X });
+            }
+            let sol = p.solve(&LinearSolver::default()).unwrap();
+            let x0 = if mode == PlaneMode::Axisymmetric { 0.0 } else { 1.0 };
+            for (x, u) in mesh.nodes.iter().zip(&sol.displacement) {
+                let expected = Vec2(x.0 - x0, x.1) * (factor * alpha * 100.0);
+                assert!((u.clone() - expected).length().sqrt() < 1e-12, "{:?} {:?}", mode, u);
+            }
+            let stress = recover_stresses(&p, &sol).unwrap();
+            let szz = if mode == PlaneMode::PlaneStrain { -200e3 * alpha * 100.0 } else { 0.0 };
+            for s in &stress.nodal_stress {
+                assert!(s.xx.abs() < 1e-9 && s.yy.abs() < 1e-9 && s.xy.abs() < 1e-9, "{:?} {:?}", mode, s);
+                assert!((s.zz - szz).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn restrained_thermal_expansion() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 2, 1, ElementKind::Quad4, "steel");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 1.0);
+        p.set_material("steel", steel());
+        p.set_temperature(&vec![70.0; mesh.node_count()], 20.0).unwrap();
+        for tag in &["left", "right", "bottom", "top"] {
+            p.add_support(Support::Fixed { tag: tag.to_string() });
+        }
+        // the expansion coefficient is required once there is a temperature
+        assert!(p.solve(&LinearSolver::default()).is_err());
+        p.set_thermal_expansion("steel", 1e-5);
+        let sol = p.solve(&LinearSolver::default()).unwrap();
+        let stress = recover_stresses(&p, &sol).unwrap();
+        let expected = -200e3 * 1e-5 * 50.0 / 0.7;
+        assert!(stress.nodal_stress.iter().all(|s| (s.xx - expected).abs() < 1e-9 && (s.yy - expected).abs() < 1e-9));
+        assert!(p.set_temperature(&[0.0; 3], 0.0).is_err());
+    }
+
+    #[test]
+    fn temperature_from_a_heat_solution() {
+        let heat_mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(4.0, 1.0), 7, 3, ElementKind::Tri3, "steel");
+        let mut heat = HeatProblem::new(&heat_mesh);
+        heat.set_conductivity("steel", 45.0);
+        heat.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 20.0 });
+        heat.add_boundary(HeatBoundary::Temperature { tag: "right".to_string(), value: 100.0 });
+        let temperature = heat.solve(&LinearSolver::default()).unwrap().temperature;
+
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(4.0, 1.0), 4, 2, ElementKind::Quad8, "steel");
+        let solve = |mapped: bool| {
+            let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStrain, 1.0);
+            p.set_material("steel", steel());
+            p.set_thermal_expansion("steel", 1.2e-5);
+            p.add_support(Support::Fixed { tag: "left".to_string() });
+            if mapped {
+                p.set_temperature_from(&heat_mesh, &temperature, 20.0, 1e-9).unwrap();
+            } else {
+                let exact: Vec<f64> = mesh.nodes.iter().map(|x| 20.0 + 20.0 * x.0).collect();
+                p.set_temperature(&exact, 20.0).unwrap();
+            }
+            p.solve(&LinearSolver::default()).unwrap().displacement
+        };
+        let (mapped, exact) = (solve(true), solve(false));
+        assert!(exact.iter().any(|u| u.0 > 1e-3));
+        for (a, b) in mapped.iter().zip(&exact) {
+            assert!((a.clone() - b.clone()).length().sqrt() < 1e-12);
+        }
+
+        let larger = meshing::rectangle(Vec2(0.0, 0.0), Vec2(4.1, 1.0), 4, 2, ElementKind::Quad8, "steel");
+        let mut p = ElasticityProblem::new(&larger, PlaneMode::PlaneStrain, 1.0);
+        assert!(p.set_temperature_from(&heat_mesh, &temperature, 20.0, 1e-3).is_err());
+    }
+}
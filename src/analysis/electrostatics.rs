@@ -0,0 +1,122 @@
+use base_types::*;
+use meshing::Mesh;
+use solvers::LinearSolver;
+use super::assembly::Symmetry;
+use super::scalar::{ScalarProblem, ScalarBoundary, element_gradients};
+
+pub const VACUUM_PERMITTIVITY: f64 = 8.854_187_812_8e-12;
+
+#[derive(Debug, Clone)]
+pub enum ElectrostaticBoundary {
+    // Prescribed electric potential (an electrode)
+    Potential { tag: String, value: f64 },
+    // Surface charge density, D . n = -density with n the outward normal
+    SurfaceCharge { tag: String, density: f64 }
+}
+
+impl ElectrostaticBoundary {
+    pub fn to_scalar(&self) -> ScalarBoundary {
+        match self.clone() {
+            ElectrostaticBoundary::Potential { tag, value } => ScalarBoundary::Fixed { tag, value },
+            ElectrostaticBoundary::SurfaceCharge { tag, density } =>
+                ScalarBoundary::Flux { tag, value: density }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ElectrostaticSolution {
+    // Nodal potential
+    pub potential: Vec<f64>,
+    // E = -grad V at the centre of every element
+    pub electric_field: Vec<Vec2>
+}
+
+// Electrostatics -div(eps grad V) = rho. Boundaries without a condition are
+// symmetry planes where the field is tangential.
+pub struct ElectrostaticProblem<'a> {
+    field: ScalarProblem<'a>
+}
+
+impl<'a> ElectrostaticProblem<'a> {
+    pub fn new(mesh: &'a Mesh) -> ElectrostaticProblem<'a> {
+        ElectrostaticProblem { field: ScalarProblem::new(mesh) }
+    }
+
+    pub fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.field.set_symmetry(symmetry);
+    }
+
+    pub fn set_relative_permittivity(&mut self, region: &str, eps_r: f64) {
+        self.field.set_coefficient(region, eps_r * VACUUM_PERMITTIVITY);
+    }
+
+    // Volumetric free charge density
+    pub fn set_charge_density(&mut self, region: &str, rho: f64) {
+        self.field.set_source(region, rho);
+    }
+
+    pub fn add_boundary(&mut self, bc: ElectrostaticBoundary) {
+        self.field.add_boundary(bc.to_scalar());
+    }
+
+    pub fn field(&self) -> &ScalarProblem<'a> { &self.field }
+
+    pub fn solve(&self, solver: &LinearSolver) -> Result<ElectrostaticSolution, String> {
+        let potential = self.field.solve(solver)?;
+        let electric_field = element_gradients(self.field.mesh(), &potential)?
+            .into_iter().map(|g| -g).collect();
+        Ok(ElectrostaticSolution { potential, electric_field })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing;
+
+    #[test]
+    fn layered_capacitor() {
+        // two dielectrics in series between plates at 0 and 100 V: the
+        // displacement field D = eps E is continuous
+        let mut mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(2e-3, 1e-3), 8, 2, ElementKind::Tri6, "air");
+        let nodes = mesh.nodes.clone();
+        for e in mesh.elements.iter_mut() {
+            let x = e.nodes.iter().map(|&n| nodes[n].0).sum::<f64>() / e.nodes.len() as f64;
+            if x > 1e-3 {
+                e.region = "glass".to_string();
+            }
+        }
+        let mut p = ElectrostaticProblem::new(&mesh);
+        p.set_relative_permittivity("air", 1.0);
+        p.set_relative_permittivity("glass", 4.0);
+        p.add_boundary(ElectrostaticBoundary::Potential { tag: "left".to_string(), value: 100.0 });
+        p.add_boundary(ElectrostaticBoundary::Potential { tag: "right".to_string(), value: 0.0 });
+        let sol = p.solve(&LinearSolver::default()).unwrap();
+
+        // E_air = 4 E_glass and E_air d + E_glass d = 100 V
+        let e_air = 100.0 / (1e-3 * 1.25);
+        for (e, field) in mesh.elements.iter().zip(&sol.electric_field) {
+            let expected = if e.region == "air" { e_air } else { e_air / 4.0 };
+            assert!((field.0 - expected).abs() < 1e-6 * e_air && field.1.abs() < 1e-6 * e_air);
+        }
+    }
+
+    #[test]
+    fn uniform_space_charge() {
+        // V = rho x (L - x) / (2 eps) between grounded plates
+        let (rho, l) = (1e-6, 0.01);
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(l, l / 4.0), 4, 1, ElementKind::Quad9, "gas");
+        let mut p = ElectrostaticProblem::new(&mesh);
+        p.set_relative_permittivity("gas", 1.0);
+        p.set_charge_density("gas", rho);
+        p.add_boundary(ElectrostaticBoundary::Potential { tag: "left".to_string(), value: 0.0 });
+        p.add_boundary(ElectrostaticBoundary::Potential { tag: "right".to_string(), value: 0.0 });
+        let sol = p.solve(&LinearSolver::default()).unwrap();
+        for (x, v) in mesh.nodes.iter().zip(&sol.potential) {
+            let exact = rho * x.0 * (l - x.0) / (2.0 * VACUUM_PERMITTIVITY);
+            assert!((v - exact).abs() < 1e-8 * exact.abs().max(1.0));
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// Meshes and problems shared by the tests of several analyses
+use base_types::*;
+use fem::shape::ElementKind;
+use meshing::{self, Mesh};
+use super::elasticity::*;
+
+// Rectangle given by its corner, size, divisions along x and y and region
+pub type Block<'a> = (Vec2, Vec2, usize, usize, &'a str);
+
+// Two separately meshed rectangles. The tags of the second start with its
+// region, e.g. "upper_bottom".
+pub fn two_blocks(kind: ElementKind, first: Block, second: Block) -> Mesh {
+    let mut mesh = meshing::rectangle(first.0, first.1, first.2, first.3, kind, first.4);
+    let other = meshing::rectangle(second.0, second.1, second.2, second.3, kind, second.4);
+    mesh.append(&other, &format!("{}_", second.4));
+    mesh
+}
+
+// A unit block "upper" meshed `divisions` x `rows`, `gap` above the unit
+// block "base" meshed 2 x 2
+pub fn stacked(kind: ElementKind, gap: f64, divisions: usize, rows: usize) -> Mesh {
+    two_blocks(kind, (Vec2(0.0, 0.0), Vec2(1.0, 1.0), 2, 2, "base"),
+               (Vec2(0.0, 1.0 + gap), Vec2(1.0, 1.0), divisions, rows, "upper"))
+}
+
+// Steel blocks of `stacked` in plane stress, on rollers at the bottom and on
+// the left
+pub fn stacked_problem(mesh: &Mesh) -> ElasticityProblem<'_> {
+    let mut p = ElasticityProblem::new(mesh, PlaneMode::PlaneStress, 1.0);
+    for region in &["base", "upper"] {
+        p.set_material(region, ElasticMaterial::Isotropic { young: 200e3, poisson: 0.3 });
+    }
+    p.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+    for tag in &["left", "upper_left"] {
+        p.add_support(Support::Roller { tag: tag.to_string(), axis: Axis::X });
+    }
+    p
+}
@@ -0,0 +1,192 @@
+use meshing::Mesh;
+use solvers::LinearSolver;
+use super::assembly::Symmetry;
+use super::scalar::{ScalarProblem, ScalarBoundary};
+
+#[derive(Debug, Clone)]
+pub enum HeatBoundary {
+    // Prescribed temperature
+    Temperature { tag: String, value: f64 },
+    // Prescribed heat flux per unit length, positive into the part
+    HeatFlux { tag: String, flux: f64 },
+    // Convection to an ambient temperature, q = h (T_amb - T)
+    Convection { tag: String, film_coefficient: f64, ambient: f64 }
+}
+
+impl HeatBoundary {
+    pub fn to_scalar(&self) -> ScalarBoundary {
+        match self.clone() {
+            HeatBoundary::Temperature { tag, value } => ScalarBoundary::Fixed { tag, value },
+            HeatBoundary::HeatFlux { tag, flux } => ScalarBoundary::Flux { tag, value: flux },
+            HeatBoundary::Convection { tag, film_coefficient, ambient } =>
+                ScalarBoundary::Robin { tag, coefficient: film_coefficient, ambient }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HeatSolution {
+    // Nodal temperatures
+    pub temperature: Vec<f64>
+}
+
+// Steady-state heat conduction -div(k grad T) = q.
+pub struct HeatProblem<'a> {
+    field: ScalarProblem<'a>
+}
+
+impl<'a> HeatProblem<'a> {
+    pub fn new(mesh: &'a Mesh) -> HeatProblem<'a> {
+        HeatProblem { field: ScalarProblem::new(mesh) }
+    }
+
+    pub fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.field.set_symmetry(symmetry);
+    }
+
+    pub fn set_conductivity(&mut self, region: &str, k: f64) {
+        self.field.set_coefficient(region, k);
+    }
+
+    // Volumetric heat generation of a region
+    pub fn set_heat_source(&mut self, region: &str, q: f64) {
+        self.field.set_source(region, q);
+    }
+
+    pub fn add_boundary(&mut self, bc: HeatBoundary) {
+        self.field.add_boundary(bc.to_scalar());
+    }
+
+    pub fn field(&self) -> &ScalarProblem<'a> { &self.field }
+
+    pub fn solve(&self, solver: &LinearSolver) -> Result<HeatSolution, String> {
+        Ok(HeatSolution { temperature: self.field.solve(solver)? })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base_types::Vec2;
+    use fem::shape::ElementKind;
+    use meshing;
+    use solvers::*;
+
+    const KINDS: [ElementKind; 5] = [ElementKind::Tri3, ElementKind::Tri6, ElementKind::Quad4,
+                                     ElementKind::Quad8, ElementKind::Quad9];
+
+    fn bar(kind: ElementKind) -> Mesh {
+        meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 0.5), 4, 2, kind, "steel")
+    }
+
+    #[test]
+    fn linear_profile() {
+        for kind in KINDS.iter() {
+            let mesh = bar(*kind);
+            let mut heat = HeatProblem::new(&mesh);
+            heat.set_conductivity("steel", 45.0);
+            heat.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 20.0 });
+            heat.add_boundary(HeatBoundary::Temperature { tag: "right".to_string(), value: 100.0 });
+            let sol = heat.solve(&LinearSolver::default()).unwrap();
+            for (p, t) in mesh.nodes.iter().zip(&sol.temperature) {
+                assert!((t - (20.0 + 40.0 * p.0)).abs() < 1e-9, "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn heat_source_gives_parabola() {
+        // T = q x (L - x) / 2k, exact at the nodes of quadratic elements
+        let (q, k) = (1000.0, 2.0);
+        for kind in [ElementKind::Tri6, ElementKind::Quad8, ElementKind::Quad9].iter() {
+            let mesh = bar(*kind);
+            let mut heat = HeatProblem::new(&mesh);
+            heat.set_conductivity("steel", k);
+            heat.set_heat_source("steel", q);
+            heat.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 0.0 });
+            heat.add_boundary(HeatBoundary::Temperature { tag: "right".to_string(), value: 0.0 });
+            let solver = LinearSolver::ConjugateGradient(SolverOptions::default());
+            let sol = heat.solve(&solver).unwrap();
+            for (p, t) in mesh.nodes.iter().zip(&sol.temperature) {
+                let exact = q * p.0 * (2.0 - p.0) / (2.0 * k);
+                assert!((t - exact).abs() < 1e-6, "{:?}: {} vs {}", kind, t, exact);
+            }
+        }
+    }
+
+    #[test]
+    fn flux_and_convection() {
+        // 1D slab: T(0) = 100, convection to 0 degrees at x = L
+        // T(L) = 100 k / (k + h L)
+        let (k, h) = (10.0, 25.0);
+        for kind in KINDS.iter() {
+            let mesh = bar(*kind);
+            let mut heat = HeatProblem::new(&mesh);
+            heat.set_conductivity("steel", k);
+            heat.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 100.0 });
+            heat.add_boundary(HeatBoundary::Convection {
+                tag: "right".to_string(), film_coefficient: h, ambient: 0.0 });
+            let sol = heat.solve(&LinearSolver::default()).unwrap();
+            let t_right = 100.0 * k / (k + h * 2.0);
+            for n in mesh.boundary_nodes("right") {
+                assert!((sol.temperature[n] - t_right).abs() < 1e-9, "{:?}", kind);
+            }
+
+            // the same gradient from a prescribed flux: q = -k dT/dx leaves at the right
+            let flux = -k * (t_right - 100.0) / 2.0;
+            let mut heat = HeatProblem::new(&mesh);
+            heat.set_conductivity("steel", k);
+            heat.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 100.0 });
+            heat.add_boundary(HeatBoundary::HeatFlux { tag: "right".to_string(), flux: -flux });
+            let sol = heat.solve(&LinearSolver::default()).unwrap();
+            for n in mesh.boundary_nodes("right") {
+                assert!((sol.temperature[n] - t_right).abs() < 1e-9, "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn missing_conductivity() {
+        let mesh = bar(ElementKind::Quad4);
+        let mut heat = HeatProblem::new(&mesh);
+        heat.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 0.0 });
+        assert!(heat.solve(&LinearSolver::default()).is_err());
+    }
+
+    #[test]
+    fn unknown_boundary_tags() {
+        let mesh = bar(ElementKind::Quad4);
+        for bc in [HeatBoundary::Temperature { tag: "lfet".to_string(), value: 0.0 },
+                   HeatBoundary::HeatFlux { tag: "lfet".to_string(), flux: 1.0 },
+                   HeatBoundary::Convection { tag: "lfet".to_string(), film_coefficient: 1.0, ambient: 0.0 }] {
+            let mut heat = HeatProblem::new(&mesh);
+            heat.set_conductivity("steel", 1.0);
+            heat.add_boundary(HeatBoundary::Temperature { tag: "right".to_string(), value: 0.0 });
+            heat.add_boundary(bc);
+            assert!(heat.solve(&LinearSolver::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn axisymmetric_hollow_cylinder() {
+        // T = T_i + (T_o - T_i) ln(r / r_i) / ln(r_o / r_i)
+        let (ri, ro) = (1.0, 3.0);
+        let mesh = meshing::rectangle(Vec2(ri, 0.0), Vec2(ro - ri, 0.5), 16, 1, ElementKind::Quad8, "pipe");
+        let mut heat = HeatProblem::new(&mesh);
+        heat.set_symmetry(Symmetry::Axisymmetric);
+        heat.set_conductivity("pipe", 15.0);
+        heat.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 200.0 });
+        heat.add_boundary(HeatBoundary::Temperature { tag: "right".to_string(), value: 20.0 });
+        let sol = heat.solve(&LinearSolver::default()).unwrap();
+        for (p, t) in mesh.nodes.iter().zip(&sol.temperature) {
+            let exact = 200.0 - 180.0 * (p.0 / ri).ln() / (ro / ri).ln();
+            assert!((t - exact).abs() < 1e-3, "{} vs {}", t, exact);
+        }
+
+        let shifted = meshing::rectangle(Vec2(-0.5, 0.0), Vec2(1.0, 1.0), 2, 1, ElementKind::Quad4, "pipe");
+        let mut heat = HeatProblem::new(&shifted);
+        heat.set_symmetry(Symmetry::Axisymmetric);
+        heat.set_conductivity("pipe", 15.0);
+        assert!(heat.solve(&LinearSolver::default()).is_err());
+    }
+}
@@ -0,0 +1,283 @@
+use base_types::*;
+use fem::shape;
+use meshing::Mesh;
+use solvers::LinearSolver;
+use super::assembly::{LinearSystem, Symmetry};
+use super::constraints::*;
+use super::elasticity::*;
+use super::scalar::ScalarProblem;
+
+// Rectangular unit cell of a periodic microstructure, the bounding box of
+// the mesh. Holes and inclusions may touch the cell edges, as long as they
+// continue on the opposite edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitCell {
+    pub min: Vec2,
+    pub max: Vec2
+}
+
+impl UnitCell {
+    pub fn of(mesh: &Mesh) -> Result<UnitCell, String> {
+        if mesh.nodes.is_empty() {
+            return Err("the unit cell mesh is empty".to_string());
+        }
+        let min = mesh.nodes.iter().fold(Vec2(f64::MAX, f64::MAX), |m, p| Vec2(m.0.min(p.0), m.1.min(p.1)));
+        let max = mesh.nodes.iter().fold(Vec2(f64::MIN, f64::MIN), |m, p| Vec2(m.0.max(p.0), m.1.max(p.1)));
+        Ok(UnitCell { min, max })
+    }
+
+    pub fn size(&self) -> Vec2 { self.max.clone() - self.min.clone() }
+
+    pub fn area(&self) -> f64 {
+        let s = self.size();
+        s.0 * s.1
+    }
+
+    // Periodic fluctuations about the macroscopic gradient, one row per field
+    // component: u(x + L) = u(x) + gradient L for the cell periods L. The
+    // nodes of the right and top edges follow the opposite edges, so the mesh
+    // does not have to be periodic itself.
+    pub fn periodic_constraints(&self, mesh: &Mesh, gradient: &[Vec2]) -> Result<Vec<Mpc>, String> {
+        let size = self.size();
+        let tolerance = 1e-9 * size.0.max(size.1);
+        let on = |edge: &[usize], axis: usize, value: f64| {
+            edge.iter().all(|&n| (if axis == 0 { mesh.nodes[n].0 } else { mesh.nodes[n].1 } - value).abs() <= tolerance)
+        };
+        let dofs = gradient.len();
+        let right: Vec<usize> = mesh.boundary.iter().filter(|e| on(&e.nodes, 0, self.max.0))
+            .flat_map(|e| e.nodes.iter().cloned()).collect();
+
+        let mut mpcs = Vec::new();
+        for (axis, shift) in [(0, Vec2(size.0, 0.0)), (1, Vec2(0.0, size.1))].iter() {
+            let (low, high) = if *axis == 0 { (self.min.0, self.max.0) } else { (self.min.1, self.max.1) };
+            let master: Vec<_> = mesh.boundary.iter().filter(|e| on(&e.nodes, *axis, low)).collect();
+            let mut slave: Vec<usize> = mesh.boundary.iter().filter(|e| on(&e.nodes, *axis, high))
+                .flat_map(|e| e.nodes.iter().cloned()).collect();
+            slave.sort();
+            slave.dedup();
+            // the top right corner already follows the top left one
+            if *axis == 1 {
+                slave.retain(|n| !right.contains(n));
+            }
+            for node in slave {
+                let p = mesh.nodes[node].clone() - shift.clone();
+                let mut best: Option<(f64, usize, Vec<f64>)> = None;
+                for (i, edge) in master.iter().enumerate() {
+                    let (distance, n) = closest_edge_point(edge.kind, &mesh.coords(&edge.nodes), &p)?;
+                    if best.as_ref().map_or(true, |b| distance < b.0) {
+                        best = Some((distance, i, n));
+                    }
+                }
+                let (i, n) = match best {
+                    Some((distance, i, n)) if distance <= 1e-6 * size.0.max(size.1) => (i, n),
+                    _ => return Err(format!("node {} at {:?} has no counterpart on the opposite cell edge",
+                                            node, mesh.nodes[node]))
+                };
+                for (d, g) in gradient.iter().enumerate() {
+                    let mut terms = vec![(node * dofs + d, 1.0)];
+                    terms.extend(master[i].nodes.iter().zip(&n)
+                        .filter(|t| t.1.abs() > 1e-12)
+                        .map(|(&m, &n)| (m * dofs + d, -n)));
+                    mpcs.push(Mpc::new(terms, g.dot(shift)));
+                }
+            }
+        }
+        Ok(mpcs)
+    }
+
+    // Node closest to the lower left corner, held to remove the rigid motion
+    fn anchor(&self, mesh: &Mesh) -> usize {
+        let distance = |p: &Vec2| (p.clone() - self.min.clone()).length();
+        (0..mesh.node_count())
+            .min_by(|&a, &b| distance(&mesh.nodes[a]).partial_cmp(&distance(&mesh.nodes[b])).unwrap())
+            .unwrap()
+    }
+}
+
+// Solves for the periodic field of one macroscopic gradient
+fn solve_cell(cell: &UnitCell, mesh: &Mesh, matrix: CsrMatrix, mut constraints: Vec<Mpc>, gradient: &[Vec2],
+              solver: &LinearSolver) -> Result<Vec<f64>, String> {
+    let dofs = gradient.len();
+    let anchor = cell.anchor(mesh);
+    constraints.extend(cell.periodic_constraints(mesh, gradient)?);
+    let system = LinearSystem {
+        rhs: vec![0.0; matrix.nrows()],
+        matrix,
+        fixed: (0..dofs).map(|d| (anchor * dofs + d, 0.0)).collect()
+    };
+    solve_constrained(&system, &constraints, ConstraintMethod::Elimination, solver)
+}
+
+// Effective stiffness of a periodic unit cell relating the mean stresses
+// (s_xx, s_yy, s_xy) to the mean strains (e_xx, e_yy, g_xy), from the three
+// unit strain load cases. Only the materials, MPCs and ties of `problem`
+// are used; supports and loads are ignored.
+pub fn effective_stiffness(problem: &ElasticityProblem, solver: &LinearSolver) -> Result<DMatrix, String> {
+    if problem.mode() == PlaneMode::Axisymmetric {
+        return Err("homogenization is only available for planar models".to_string());
+    }
+    let mesh = problem.mesh();
+    let cell = UnitCell::of(mesh)?;
+    let matrix = problem.assemble()?.matrix;
+    let constraints = problem.linear_constraints()?;
+
+    let mut stiffness = DMatrix::zeros(3, 3);
+    for j in 0..3 {
+        // rows du_x/dx, du_y/dx
+        let gradient = match j {
+            0 => [Vec2(1.0, 0.0), Vec2(0.0, 0.0)],
+            1 => [Vec2(0.0, 0.0), Vec2(0.0, 1.0)],
+            _ => [Vec2(0.0, 0.5), Vec2(0.5, 0.0)]
+        };
+        let u = solve_cell(&cell, mesh, matrix.clone(), constraints.clone(), &gradient, solver)?;
+        let displacement: Vec<Vec2> = u.chunks(2).map(|c| Vec2(c[0], c[1])).collect();
+        let mut mean = [0.0; 3];
+        for e in &mesh.elements {
+            let d = problem.material(&e.region)?.d_matrix(problem.mode())?;
+            let coords = mesh.coords(&e.nodes);
+            let ue = element_displacements(&e.nodes, &displacement);
+            for q in e.kind.quadrature(e.kind.quadrature_degree()) {
+                let iso = shape::map(e.kind, &coords, &q.point)?;
+                let stress = d.mul_vec(&b_matrix(&iso, problem.mode()).mul_vec(&ue));
+                for (m, s) in mean.iter_mut().zip(stress) {
+                    *m += s * q.weight * iso.det_j;
+                }
+            }
+        }
+        for (i, m) in mean.iter().enumerate() {
+            stiffness[(i, j)] = m / cell.area();
+        }
+    }
+    Ok(stiffness)
+}
+
+// Effective conductivity tensor of a periodic unit cell relating the mean
+// flux to the mean gradient, q = -K grad u, from the two unit gradient load
+// cases. Only the coefficients, MPCs and ties of `problem` are used.
+pub fn effective_conductivity(problem: &ScalarProblem, solver: &LinearSolver) -> Result<DMatrix, String> {
+    if problem.symmetry() != Symmetry::Planar {
+        return Err("homogenization is only available for planar models".to_string());
+    }
+    let mesh = problem.mesh();
+    let cell = UnitCell::of(mesh)?;
+    let matrix = problem.assemble()?.matrix;
+    let constraints = problem.linear_constraints()?;
+
+    let mut conductivity = DMatrix::zeros(2, 2);
+    for j in 0..2 {
+        let gradient = if j == 0 { [Vec2(1.0, 0.0)] } else { [Vec2(0.0, 1.0)] };
+        let u = solve_cell(&cell, mesh, matrix.clone(), constraints.clone(), &gradient, solver)?;
+        let mut mean = Vec2(0.0, 0.0);
+        for e in &mesh.elements {
+            let k = problem.coefficient(&e.region)?;
+            let coords = mesh.coords(&e.nodes);
+            for q in e.kind.quadrature(e.kind.quadrature_degree()) {
+                let iso = shape::map(e.kind, &coords, &q.point)?;
+                let grad = iso.grad.iter().zip(&e.nodes).fold(Vec2(0.0, 0.0), |g, (dn, &n)| g + dn.clone() * u[n]);
+                mean += grad * (k * q.weight * iso.det_j);
+            }
+        }
+        conductivity[(0, j)] = mean.0 / cell.area();
+        conductivity[(1, j)] = mean.1 / cell.area();
+    }
+    Ok(conductivity)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing;
+    use analysis::fixtures::two_blocks;
+
+    fn assert_close(a: &DMatrix, b: &DMatrix, tolerance: f64) {
+        for i in 0..a.nrows() {
+            for j in 0..a.ncols() {
+                assert!((a[(i, j)] - b[(i, j)]).abs() <= tolerance, "{:?}\n{:?}", a, b);
+            }
+        }
+    }
+
+    // Two 1 x 2 stripes side by side, the right one meshed twice as fine
+    // along the height and tied to the left one
+    fn stripes(kind: ElementKind) -> Mesh {
+        two_blocks(kind, (Vec2(0.0, 0.0), Vec2(1.0, 2.0), 2, 2, "left"),
+                   (Vec2(1.0, 0.0), Vec2(1.0, 2.0), 2, 4, "right"))
+    }
+
+    fn tie() -> Tie {
+        Tie { slave: "right_left".to_string(), master: "right".to_string(), tolerance: 1e-9 }
+    }
+
+    #[test]
+    fn homogeneous_cell() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 4, 3, ElementKind::Quad8, "matrix");
+        let material = ElasticMaterial::Isotropic { young: 70e3, poisson: 0.33 };
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStrain, 1.0);
+        p.set_material("matrix", material.clone());
+        let c = effective_stiffness(&p, &LinearSolver::default()).unwrap();
+        assert_close(&c, &material.d_matrix(PlaneMode::PlaneStrain).unwrap(), 1e-7);
+
+        let mut p = ScalarProblem::new(&mesh);
+        p.set_coefficient("matrix", 3.0);
+        let k = effective_conductivity(&p, &LinearSolver::default()).unwrap();
+        assert_close(&k, &DMatrix::from_diagonal(&[3.0, 3.0]), 1e-12);
+    }
+
+    #[test]
+    fn laminate_conductivity() {
+        for kind in [ElementKind::Quad4, ElementKind::Tri6].iter() {
+            let mesh = stripes(*kind);
+            let mut p = ScalarProblem::new(&mesh);
+            p.set_coefficient("left", 1.0);
+            p.set_coefficient("right", 4.0);
+            p.add_tie(tie());
+            // harmonic mean across the layers, arithmetic mean along them
+            let k = effective_conductivity(&p, &LinearSolver::default()).unwrap();
+            assert_close(&k, &DMatrix::from_diagonal(&[1.6, 2.5]), 1e-12);
+        }
+    }
+
+    #[test]
+    fn cells_must_be_periodic() {
+        // a notch in the left edge without a counterpart on the right one
+        let mut mesh = stripes(ElementKind::Quad4);
+        let low: Vec<bool> = mesh.nodes.iter().map(|p| p.1 < 0.9).collect();
+        mesh.boundary.retain(|b| b.tag != "left" || b.nodes.iter().all(|&n| !low[n]));
+        let mut p = ScalarProblem::new(&mesh);
+        p.set_coefficient("left", 1.0);
+        p.set_coefficient("right", 1.0);
+        p.add_tie(tie());
+        assert!(effective_conductivity(&p, &LinearSolver::default()).is_err());
+    }
+
+    #[test]
+    fn laminate_stiffness() {
+        let mesh = stripes(ElementKind::Quad4);
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 1.0);
+        let (soft, stiff) = (ElasticMaterial::Isotropic { young: 1.0, poisson: 0.3 },
+                             ElasticMaterial::Isotropic { young: 10.0, poisson: 0.3 });
+        p.set_material("left", soft.clone());
+        p.set_material("right", stiff.clone());
+        p.add_tie(tie());
+        let c = effective_stiffness(&p, &LinearSolver::default()).unwrap();
+        assert_close(&c, &c.transpose(), 1e-12);
+        // for equal Poisson ratios the layers carry uniaxial stress along
+        // them, and all of them the same shear stress
+        let compliance = c.inverse().unwrap();
+        assert!((1.0 / compliance[(1, 1)] - 5.5).abs() < 1e-9, "{:?}", c);
+        let (d1, d2) = (soft.d_matrix(PlaneMode::PlaneStress).unwrap(), stiff.d_matrix(PlaneMode::PlaneStress).unwrap());
+        let shear = 1.0 / (0.5 / d1[(2, 2)] + 0.5 / d2[(2, 2)]);
+        assert!((c[(2, 2)] - shear).abs() < 1e-9, "{:?}", c);
+        // across the layers between the Reuss and Voigt bounds
+        let across = 1.0 / compliance[(0, 0)];
+        assert!(across > 1.0 / (0.5 / 1.0 + 0.5 / 10.0) && across < 5.5, "{:?}", c);
+    }
+
+    #[test]
+    fn axisymmetric_cells_are_rejected() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 1.0), 1, 1, ElementKind::Quad4, "matrix");
+        let p = ElasticityProblem::new(&mesh, PlaneMode::Axisymmetric, 1.0);
+        assert!(effective_stiffness(&p, &LinearSolver::default()).is_err());
+    }
+}
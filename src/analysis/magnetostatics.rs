@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use base_types::*;
+use meshing::Mesh;
+use solvers::LinearSolver;
+use fem::shape::{self, ElementKind};
+use super::assembly::Assembler;
+use super::nonlinear::{NonlinearProblem, NewtonOptions, newton_raphson};
+use super::scalar::element_gradients;
+
+pub const VACUUM_PERMEABILITY: f64 = 4e-7 * PI;
+
+// Magnetization curve of a soft magnetic material as (H, B) points. Beyond the
+// last point the material is taken as saturated, dB/dH = mu_0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BhCurve {
+    points: Vec<(f64, f64)>
+}
+
+impl BhCurve {
+    pub fn new(mut points: Vec<(f64, f64)>) -> Result<BhCurve, String> {
+        if points.first() != Some(&(0.0, 0.0)) {
+            points.insert(0, (0.0, 0.0));
+        }
+        if points.len() < 2 {
+            return Err("B-H curve needs at least one point besides the origin".to_string());
+        }
+        if points.windows(2).any(|w| w[1].0 <= w[0].0 || w[1].1 <= w[0].1) {
+            return Err("B-H curve must be strictly increasing in H and B".to_string());
+        }
+        Ok(BhCurve { points })
+    }
+
+    pub fn points(&self) -> &[(f64, f64)] { &self.points }
+
+    // H(B), piecewise linear
+    pub fn field_strength(&self, b: f64) -> f64 {
+        let b = b.abs();
+        match self.points.iter().position(|p| p.1 > b) {
+            Some(i) => {
+                let (p0, p1) = (self.points[i - 1], self.points[i]);
+                p0.0 + (p1.0 - p0.0) * (b - p0.1) / (p1.1 - p0.1)
+            },
+            None => {
+                let last = self.points[self.points.len() - 1];
+                last.0 + (b - last.1) / VACUUM_PERMEABILITY
+            }
+        }
+    }
+
+    // dH/dB
+    pub fn slope(&self, b: f64) -> f64 {
+        let b = b.abs();
+        match self.points.iter().position(|p| p.1 > b) {
+            Some(i) => {
+                let (p0, p1) = (self.points[i - 1], self.points[i]);
+                (p1.0 - p0.0) / (p1.1 - p0.1)
+            },
+            None => 1.0 / VACUUM_PERMEABILITY
+        }
+    }
+
+    // nu = H / B, the initial slope at B = 0
+    pub fn reluctivity(&self, b: f64) -> f64 {
+        if b.abs() < 1e-12 {
+            self.points[1].0 / self.points[1].1
+        } else {
+            self.field_strength(b) / b.abs()
+        }
+    }
+
+    // d nu / d(B^2) = (H' B - H) / (2 B^3)
+    pub fn reluctivity_derivative(&self, b: f64) -> f64 {
+        let b = b.abs();
+        if b < 1e-12 {
+            0.0
+        } else {
+            (self.slope(b) * b - self.field_strength(b)) / (2.0 * b * b * b)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Permeability {
+    Relative(f64),
+    Curve(BhCurve)
+}
+
+impl Permeability {
+    pub fn reluctivity(&self, b: f64) -> f64 {
+        match self {
+            Permeability::Relative(mu_r) => 1.0 / (mu_r * VACUUM_PERMEABILITY),
+            Permeability::Curve(curve) => curve.reluctivity(b)
+        }
+    }
+
+    pub fn reluctivity_derivative(&self, b: f64) -> f64 {
+        match self {
+            Permeability::Relative(_) => 0.0,
+            Permeability::Curve(curve) => curve.reluctivity_derivative(b)
+        }
+    }
+
+    pub fn is_linear(&self) -> bool {
+        matches!(self, Permeability::Relative(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MagneticBoundary {
+    // Prescribed A_z; a constant value makes the boundary a flux line.
+    // Boundaries without a condition are crossed normally by the flux.
+    VectorPotential { tag: String, value: f64 }
+}
+
+#[derive(Debug, Clone)]
+pub struct MagnetostaticSolution {
+    // Nodal A_z
+    pub vector_potential: Vec<f64>,
+    // B = curl A = (dA/dy, -dA/dx) at the centre of every element
+    pub flux_density: Vec<Vec2>,
+    // Nonlinear iterations used, 1 for linear problems
+    pub iterations: usize
+}
+
+// Tangent matrix and residual int(nu grad N . grad A - N J) of an element for
+// the nodal potentials `a`
+fn element_tangent(kind: ElementKind, coords: &[Vec2], a: &[f64], material: &Permeability, j: f64)
+    -> Result<(DMatrix, Vec<f64>), String> {
+    let n = kind.node_count();
+    let mut kt = DMatrix::zeros(n, n);
+    let mut r = vec![0.0; n];
+    for q in kind.quadrature(kind.quadrature_degree()) {
+        let iso = shape::map(kind, coords, &q.point)?;
+        let w = q.weight * iso.det_j;
+        let g = iso.grad.iter().zip(a).fold(Vec2(0.0, 0.0), |g, (dn, ai)| g + dn.clone() * *ai);
+        let b = g.dot(&g).sqrt();
+        let nu = material.reluctivity(b);
+        let dnu = material.reluctivity_derivative(b);
+        for i in 0..n {
+            let gi = iso.grad[i].dot(&g);
+            for k in 0..n {
+                kt[(i, k)] += (nu * iso.grad[i].dot(&iso.grad[k]) + 2.0 * dnu * gi * iso.grad[k].dot(&g)) * w;
+            }
+            r[i] += (nu * gi - iso.n[i] * j) * w;
+        }
+    }
+    Ok((kt, r))
+}
+
+// 2D magnetostatics -div(nu grad A_z) = J_z. Nonlinear materials are solved
+// with `newton_raphson`.
+pub struct MagnetostaticProblem<'a> {
+    mesh: &'a Mesh,
+    permeability: HashMap<String, Permeability>,
+    current_density: HashMap<String, f64>,
+    boundaries: Vec<MagneticBoundary>,
+    tolerance: f64,
+    max_iterations: usize
+}
+
+impl<'a> MagnetostaticProblem<'a> {
+    pub fn new(mesh: &'a Mesh) -> MagnetostaticProblem<'a> {
+        MagnetostaticProblem {
+            mesh,
+            permeability: HashMap::new(),
+            current_density: HashMap::new(),
+            boundaries: Vec::new(),
+            tolerance: 1e-6,
+            max_iterations: 200
+        }
+    }
+
+    pub fn mesh(&self) -> &'a Mesh { self.mesh }
+
+    pub fn set_permeability(&mut self, region: &str, permeability: Permeability) {
+        self.permeability.insert(region.to_string(), permeability);
+    }
+
+    // Current density normal to the plane
+    pub fn set_current_density(&mut self, region: &str, j: f64) {
+        self.current_density.insert(region.to_string(), j);
+    }
+
+    pub fn add_boundary(&mut self, bc: MagneticBoundary) {
+        self.boundaries.push(bc);
+    }
+
+    // Relative residual and Newton update ending the nonlinear iterations,
+    // and the iterations allowed per load increment
+    pub fn set_tolerance(&mut self, tolerance: f64, max_iterations: usize) {
+        self.tolerance = tolerance;
+        self.max_iterations = max_iterations;
+    }
+
+    fn permeability(&self, region: &str) -> Result<&Permeability, String> {
+        self.permeability.get(region)
+            .ok_or_else(|| format!("no permeability given for region '{}'", region))
+    }
+
+    fn fixed(&self) -> Result<Vec<(usize, f64)>, String> {
+        let mut fixed = Vec::new();
+        for bc in &self.boundaries {
+            let MagneticBoundary::VectorPotential { tag, value } = bc;
+            self.mesh.check_tag(tag)?;
+            fixed.extend(self.mesh.boundary_nodes(tag).into_iter().map(|n| (n, *value)));
+        }
+        Ok(fixed)
+    }
+
+    pub fn solve(&self, solver: &LinearSolver) -> Result<MagnetostaticSolution, String> {
+        let materials = self.mesh.elements.iter()
+            .map(|e| self.permeability(&e.region))
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut system = MagnetostaticSystem { problem: self, materials, fixed: self.fixed()? };
+        let options = NewtonOptions {
+            residual_tolerance: self.tolerance,
+            increment_tolerance: self.tolerance,
+            max_iterations: self.max_iterations,
+            ..Default::default()
+        };
+        let sol = newton_raphson(&mut system, &options, solver)
+            .map_err(|e| format!("magnetostatics: {}", e))?;
+        let flux_density = element_gradients(self.mesh, &sol.u)?
+            .into_iter().map(|g| Vec2(g.1, -g.0)).collect();
+        Ok(MagnetostaticSolution { iterations: sol.iterations(), vector_potential: sol.u, flux_density })
+    }
+}
+
+// The discretized problem with the currents and the prescribed potentials
+// scaled by the load factor
+struct MagnetostaticSystem<'p, 'a: 'p> {
+    problem: &'p MagnetostaticProblem<'a>,
+    materials: Vec<&'p Permeability>,
+    fixed: Vec<(usize, f64)>
+}
+
+impl<'p, 'a> MagnetostaticSystem<'p, 'a> {
+    fn assemble(&self, a: &[f64], load_factor: f64) -> Result<(CsrMatrix, Vec<f64>), String> {
+        let mesh = self.problem.mesh;
+        let mut asm = Assembler::new(mesh.node_count(), 1);
+        for (e, material) in mesh.elements.iter().zip(&self.materials) {
+            let j = self.problem.current_density.get(&e.region).cloned().unwrap_or(0.0) * load_factor;
+            let ae: Vec<f64> = e.nodes.iter().map(|&n| a[n]).collect();
+            let (kt, r) = element_tangent(e.kind, &mesh.coords(&e.nodes), &ae, material, j)?;
+            asm.add_matrix(&e.nodes, &kt);
+            asm.add_vector(&e.nodes, &r);
+        }
+        Ok(asm.finish())
+    }
+}
+
+impl<'p, 'a> NonlinearProblem for MagnetostaticSystem<'p, 'a> {
+    fn dof_count(&self) -> usize { self.problem.mesh.node_count() }
+
+    fn constraints(&self, load_factor: f64) -> Result<Vec<(usize, f64)>, String> {
+        Ok(self.fixed.iter().map(|&(n, v)| (n, v * load_factor)).collect())
+    }
+
+    fn residual(&mut self, a: &[f64], load_factor: f64) -> Result<Vec<f64>, String> {
+        Ok(self.assemble(a, load_factor)?.1)
+    }
+
+    fn tangent(&mut self, a: &[f64], load_factor: f64) -> Result<CsrMatrix, String> {
+        Ok(self.assemble(a, load_factor)?.0)
+    }
+
+    fn is_linear(&self) -> bool {
+        self.materials.iter().all(|m| m.is_linear())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing;
+
+    fn steel() -> BhCurve {
+        BhCurve::new(vec![(100.0, 0.6), (200.0, 1.0), (500.0, 1.4), (2000.0, 1.7), (10000.0, 1.95)]).unwrap()
+    }
+
+    #[test]
+    fn bh_curve() {
+        let c = steel();
+        assert_eq!(c.points().len(), 6);
+        assert!((c.field_strength(0.3) - 50.0).abs() < 1e-12);
+        assert!((c.field_strength(1.2) - 350.0).abs() < 1e-9);
+        assert!((c.field_strength(2.0) - (10000.0 + 0.05 / VACUUM_PERMEABILITY)).abs() < 1e-6);
+        assert!((c.reluctivity(0.0) - 100.0 / 0.6).abs() < 1e-12);
+        assert!(BhCurve::new(vec![(100.0, 1.0), (50.0, 1.2)]).is_err());
+    }
+
+    // Current sheet in a slab between two flux lines A = 0: by symmetry
+    // H_y = J (L / 2 - x) whatever the material.
+    fn slab(permeability: Permeability) -> (Mesh, MagnetostaticSolution) {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(0.1, 0.01), 40, 1, ElementKind::Quad8, "core");
+        let sol = {
+            let mut p = MagnetostaticProblem::new(&mesh);
+            p.set_permeability("core", permeability);
+            p.set_current_density("core", 1e5);
+            p.add_boundary(MagneticBoundary::VectorPotential { tag: "left".to_string(), value: 0.0 });
+            p.add_boundary(MagneticBoundary::VectorPotential { tag: "right".to_string(), value: 0.0 });
+            p.solve(&LinearSolver::default()).unwrap()
+        };
+        (mesh, sol)
+    }
+
+    fn centre_x(mesh: &Mesh, nodes: &[usize]) -> f64 {
+        nodes.iter().map(|&n| mesh.nodes[n].0).sum::<f64>() / nodes.len() as f64
+    }
+
+    #[test]
+    fn linear_slab() {
+        let (mesh, sol) = slab(Permeability::Relative(1000.0));
+        assert_eq!(sol.iterations, 1);
+        let mu = 1000.0 * VACUUM_PERMEABILITY;
+        for (x, a) in mesh.nodes.iter().zip(&sol.vector_potential) {
+            let exact = mu * 1e5 * x.0 * (0.1 - x.0) / 2.0;
+            assert!((a - exact).abs() < 1e-9 * exact.abs().max(1e-3));
+        }
+        for (e, b) in mesh.elements.iter().zip(&sol.flux_density) {
+            let h = 1e5 * (0.05 - centre_x(&mesh, &e.nodes));
+            assert!((b.1 + mu * h).abs() < 1e-9 && b.0.abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn saturating_slab() {
+        let curve = steel();
+        let (mesh, sol) = slab(Permeability::Curve(curve.clone()));
+        assert!(sol.iterations > 1);
+        for (e, b) in mesh.elements.iter().zip(&sol.flux_density) {
+            let h = 1e5 * (0.05 - centre_x(&mesh, &e.nodes));
+            let h_fe = -curve.field_strength(b.1) * b.1.signum();
+            assert!((h_fe - h).abs() < 0.01 * 5000.0, "{} vs {}", h_fe, h);
+        }
+        // the edges of the slab run into saturation
+        assert!(sol.flux_density[0].1.abs() > 1.7);
+    }
+}
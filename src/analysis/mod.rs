@@ -0,0 +1,24 @@
+pub mod assembly;
+pub mod scalar;
+pub mod heat;
+pub mod elasticity;
+pub mod transient;
+pub mod modal;
+pub mod buckling;
+pub mod electrostatics;
+pub mod magnetostatics;
+pub mod stress;
+pub mod adaptivity;
+pub mod nonlinear;
+pub mod plasticity;
+pub mod contact;
+pub mod constraints;
+pub mod homogenization;
+#[cfg(test)]
+mod fixtures;
+
+pub use self::heat::{HeatProblem, HeatBoundary};
+pub use self::elasticity::{ElasticityProblem, ElasticMaterial, Support, Load};
+pub use self::transient::{TransientHeatProblem, Amplitude};
+pub use self::electrostatics::{ElectrostaticProblem, ElectrostaticBoundary};
+pub use self::magnetostatics::{MagnetostaticProblem, MagneticBoundary, Permeability, BhCurve};
@@ -0,0 +1,103 @@
+use std::f64::consts::PI;
+use base_types::*;
+use solvers::{EigenOptions, subspace_iteration};
+use super::elasticity::ElasticityProblem;
+
+#[derive(Debug, Clone)]
+pub struct Mode {
+    // Natural frequency in Hz
+    pub frequency: f64,
+    pub angular_frequency: f64,
+    // Mass-normalized nodal displacements, phi^T M phi = 1
+    pub shape: Vec<Vec2>
+}
+
+#[derive(Debug, Clone)]
+pub struct ModalSolution {
+    // Ascending in frequency
+    pub modes: Vec<Mode>,
+    pub iterations: usize
+}
+
+// Natural vibrations K phi = omega^2 M phi of an elasticity model. Its supports
+// are applied as homogeneous constraints, loads are ignored.
+pub fn modal_analysis(problem: &ElasticityProblem, options: &EigenOptions)
+    -> Result<ModalSolution, String> {
+    let system = problem.assemble()?;
+    let mass = problem.mass_matrix()?;
+    let free = system.free_dofs();
+    let k = system.matrix.principal_submatrix(&free);
+    let m = mass.principal_submatrix(&free);
+    let pairs = subspace_iteration(&k, &m, options)?;
+
+    let dofs = system.rhs.len();
+    let modes = pairs.values.iter().zip(&pairs.vectors).map(|(&lambda, vector)| {
+        let mut u = vec![0.0; dofs];
+        for (&dof, v) in free.iter().zip(vector) {
+            u[dof] = *v;
+        }
+        // round-off can leave rigid body modes slightly negative
+        let omega = lambda.max(0.0).sqrt();
+        Mode {
+            frequency: omega / (2.0 * PI),
+            angular_frequency: omega,
+            shape: u.chunks(2).map(|c| Vec2(c[0], c[1])).collect()
+        }
+    }).collect();
+    Ok(ModalSolution { modes, iterations: pairs.iterations })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing;
+    use super::super::elasticity::*;
+
+    #[test]
+    fn cantilever_frequencies() {
+        let (l, h, e, rho) = (1.0, 0.05, 200e9, 7850.0);
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(l, h), 40, 2, ElementKind::Quad8, "steel");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 0.01);
+        p.set_material("steel", ElasticMaterial::Isotropic { young: e, poisson: 0.3 });
+        p.set_density("steel", rho);
+        p.add_support(Support::Fixed { tag: "left".to_string() });
+        let sol = modal_analysis(&p, &EigenOptions { count: 3, ..Default::default() }).unwrap();
+        assert_eq!(sol.modes.len(), 3);
+
+        // Euler-Bernoulli bending, omega = (beta L)^2 sqrt(E I / (rho A L^4))
+        let base = (e * h * h / 12.0 / (rho * l * l * l * l)).sqrt();
+        let first = 1.875_104_07f64.powi(2) * base;
+        let second = 4.694_091_13f64.powi(2) * base;
+        let w = &sol.modes;
+        assert!(((w[0].angular_frequency - first) / first).abs() < 0.01,
+                "{} vs {}", w[0].angular_frequency, first);
+        assert!(((w[1].angular_frequency - second) / second).abs() < 0.03);
+        assert!(w[0].frequency < w[1].frequency && w[1].frequency < w[2].frequency);
+
+        // supported nodes do not move, shapes are mass-normalized
+        for n in mesh.boundary_nodes("left") {
+            assert_eq!(w[0].shape[n], Vec2(0.0, 0.0));
+        }
+        let m = p.mass_matrix().unwrap();
+        let u: Vec<f64> = w[1].shape.iter().flat_map(|v| vec![v.0, v.1]).collect();
+        let norm: f64 = m.mul_vec(&u).iter().zip(&u).map(|(a, b)| a * b).sum();
+        assert!((norm - 1.0).abs() < 1e-8);
+    }
+
+    #[test]
+    fn mass_matrix_total() {
+        let mesh = meshing::rectangle(Vec2(1.0, 0.0), Vec2(2.0, 1.0), 3, 2, ElementKind::Tri6, "al");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 0.1);
+        p.set_density("al", 2700.0);
+        let m = p.mass_matrix().unwrap();
+        let total: f64 = m.values().iter().sum::<f64>() / 2.0;
+        assert!((total - 2700.0 * 2.0 * 0.1).abs() < 1e-8);
+
+        // ring of revolution: rho pi (r_o^2 - r_i^2) h
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::Axisymmetric, 1.0);
+        p.set_density("al", 2700.0);
+        let total: f64 = p.mass_matrix().unwrap().values().iter().sum::<f64>() / 2.0;
+        assert!((total - 2700.0 * PI * 8.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,248 @@
+use base_types::*;
+use solvers::{LinearSolver, norm};
+use super::assembly::LinearSystem;
+
+// A discretized nonlinear problem R(u, lambda) = 0 for the load factor
+// lambda in [0, 1]. `residual` and `tangent` evaluate trial states and must
+// leave the converged state alone; path dependent problems update their
+// history in `commit` once an increment has converged.
+pub trait NonlinearProblem {
+    fn dof_count(&self) -> usize;
+
+    // Prescribed dofs and their values at the load factor
+    fn constraints(&self, load_factor: f64) -> Result<Vec<(usize, f64)>, String>;
+
+    // Out of balance force f_int(u) - f_ext(lambda); at the prescribed dofs
+    // this is the reaction
+    fn residual(&mut self, u: &[f64], load_factor: f64) -> Result<Vec<f64>, String>;
+
+    // dR/du
+    fn tangent(&mut self, u: &[f64], load_factor: f64) -> Result<CsrMatrix, String>;
+
+    fn commit(&mut self, _u: &[f64], _load_factor: f64) {}
+
+    // Linear problems converge after the first solve
+    fn is_linear(&self) -> bool { false }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewtonOptions {
+    // Residual norm relative to the reference force (the larger of the
+    // reactions and the out of balance force at the start of the increment)
+    pub residual_tolerance: f64,
+    // Norm of the correction relative to the norm of u
+    pub increment_tolerance: f64,
+    // Iterations per increment before it is cut back
+    pub max_iterations: usize,
+    pub line_search: bool,
+    // Load factor increments; the increment is halved on divergence and
+    // doubled again after increments that converge easily
+    pub initial_step: f64,
+    pub min_step: f64,
+    pub max_step: f64
+}
+
+impl Default for NewtonOptions {
+    fn default() -> NewtonOptions {
+        NewtonOptions {
+            residual_tolerance: 1e-8,
+            increment_tolerance: 1e-8,
+            max_iterations: 25,
+            line_search: true,
+            initial_step: 1.0,
+            min_step: 1e-4,
+            max_step: 1.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadIncrement {
+    pub load_factor: f64,
+    pub iterations: usize,
+    pub residual_norm: f64
+}
+
+#[derive(Debug, Clone)]
+pub struct NonlinearSolution {
+    pub u: Vec<f64>,
+    // Final residual, the reactions at the prescribed dofs
+    pub residual: Vec<f64>,
+    pub increments: Vec<LoadIncrement>,
+    // Increments abandoned and retried with half the step
+    pub cutbacks: usize
+}
+
+impl NonlinearSolution {
+    pub fn iterations(&self) -> usize {
+        self.increments.iter().map(|i| i.iterations).sum()
+    }
+}
+
+fn free_norm(r: &[f64], fixed: &[bool]) -> f64 {
+    r.iter().zip(fixed).filter(|p| !*p.1).map(|p| p.0 * p.0).sum::<f64>().sqrt()
+}
+
+struct Converged {
+    u: Vec<f64>,
+    residual: Vec<f64>,
+    iterations: usize,
+    residual_norm: f64
+}
+
+// Newton-Raphson iterations for one increment starting from the converged
+// state `u`
+fn increment<P: NonlinearProblem>(problem: &mut P, u: &[f64], load_factor: f64, options: &NewtonOptions,
+                                  solver: &LinearSolver) -> Result<Converged, String> {
+    let constraints = problem.constraints(load_factor)?;
+    let mut fixed = vec![false; u.len()];
+    constraints.iter().for_each(|&(dof, _)| fixed[dof] = true);
+    let fixed_norm = |r: &[f64]| constraints.iter().map(|&(d, _)| r[d] * r[d]).sum::<f64>().sqrt();
+
+    let mut u = u.to_vec();
+    let mut r = problem.residual(&u, load_factor)?;
+    let mut reference = free_norm(&r, &fixed).max(fixed_norm(&r));
+    let mut first_norm = None;
+    for iteration in 1..options.max_iterations + 1 {
+        let k = problem.tangent(&u, load_factor)?;
+        let jumps: Vec<(usize, f64)> = constraints.iter().map(|&(d, v)| (d, v - u[d])).collect();
+        let system = LinearSystem { matrix: k, rhs: r.iter().map(|v| -v).collect(), fixed: jumps.clone() };
+        let du = system.solve(solver)?;
+
+        // the residual before the prescribed values are imposed is no
+        // measure for the line search
+        let norm0 = free_norm(&r, &fixed);
+        let search = options.line_search && jumps.iter().all(|j| j.1 == 0.0);
+        let mut alpha = 1.0;
+        let mut trial: Vec<f64>;
+        loop {
+            trial = u.iter().zip(&du).map(|(ui, d)| ui + alpha * d).collect();
+            r = problem.residual(&trial, load_factor)?;
+            if !search || free_norm(&r, &fixed) < norm0 || alpha < 0.1 {
+                break;
+            }
+            alpha /= 2.0;
+        }
+        u = trial;
+
+        let residual_norm = free_norm(&r, &fixed);
+        let size = norm(&u).max(f64::MIN_POSITIVE);
+        if !residual_norm.is_finite() || !size.is_finite() {
+            return Err(format!("residual is not finite at load factor {}", load_factor));
+        }
+        reference = reference.max(fixed_norm(&r)).max(f64::MIN_POSITIVE);
+        // the first correction sets the scale where the reference is still
+        // zero, e.g. before a contact closes
+        let scale = *first_norm.get_or_insert(residual_norm);
+        if residual_norm > 1e8 * reference.max(scale) {
+            return Err(format!("diverged at load factor {}", load_factor));
+        }
+        let step = alpha * norm(&du);
+        // corrections at round-off level cannot reduce the residual any more,
+        // as for force free rigid body motions where the reference is zero
+        let converged = problem.is_linear() || step <= 1e-14 * size
+            || (residual_norm <= options.residual_tolerance * reference && step <= options.increment_tolerance * size);
+        if converged {
+            return Ok(Converged { u, residual: r, iterations: iteration, residual_norm });
+        }
+    }
+    Err(format!("no convergence in {} iterations at load factor {}", options.max_iterations, load_factor))
+}
+
+// Solves a nonlinear problem from u = 0 up to the full load (load factor 1)
+// in increments, cutting the increment back whenever Newton-Raphson fails
+pub fn newton_raphson<P: NonlinearProblem>(problem: &mut P, options: &NewtonOptions, solver: &LinearSolver)
+    -> Result<NonlinearSolution, String> {
+    let mut u = vec![0.0; problem.dof_count()];
+    let mut residual = vec![0.0; u.len()];
+    let mut load_factor = 0.0;
+    let mut step = options.initial_step.clamp(options.min_step, options.max_step);
+    let mut increments = Vec::new();
+    let mut cutbacks = 0;
+    while load_factor < 1.0 - 1e-12 {
+        let target = (load_factor + step).min(1.0);
+        match increment(problem, &u, target, options, solver) {
+            Ok(converged) => {
+                problem.commit(&converged.u, target);
+                u = converged.u;
+                residual = converged.residual;
+                load_factor = target;
+                if converged.iterations <= options.max_iterations / 4 {
+                    step = (2.0 * step).min(options.max_step);
+                }
+                increments.push(LoadIncrement {
+                    load_factor,
+                    iterations: converged.iterations,
+                    residual_norm: converged.residual_norm
+                });
+            },
+            Err(e) => {
+                cutbacks += 1;
+                step /= 2.0;
+                if step < options.min_step {
+                    return Err(format!("Newton-Raphson: {}, step below the minimum", e));
+                }
+            }
+        }
+    }
+    Ok(NonlinearSolution { u, residual, increments, cutbacks })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use analysis::assembly::Assembler;
+
+    // atan(u - root lambda) = 0: the plain Newton iteration overshoots and
+    // diverges unless it starts close to the root
+    struct Arctangent {
+        root: f64,
+        committed: Vec<f64>
+    }
+
+    impl NonlinearProblem for Arctangent {
+        fn dof_count(&self) -> usize { 1 }
+
+        fn constraints(&self, _load_factor: f64) -> Result<Vec<(usize, f64)>, String> { Ok(Vec::new()) }
+
+        fn residual(&mut self, u: &[f64], load_factor: f64) -> Result<Vec<f64>, String> {
+            Ok(vec![(u[0] - self.root * load_factor).atan()])
+        }
+
+        fn tangent(&mut self, u: &[f64], load_factor: f64) -> Result<CsrMatrix, String> {
+            let x = u[0] - self.root * load_factor;
+            let mut asm = Assembler::new(1, 1);
+            asm.add_matrix(&[0], &DMatrix::from_rows(&[&[1.0 / (1.0 + x * x)]]));
+            Ok(asm.finish().0)
+        }
+
+        fn commit(&mut self, u: &[f64], _load_factor: f64) {
+            self.committed.push(u[0]);
+        }
+    }
+
+    #[test]
+    fn line_search_saves_the_full_step() {
+        let mut p = Arctangent { root: 5.8, committed: Vec::new() };
+        let sol = newton_raphson(&mut p, &NewtonOptions::default(), &LinearSolver::default()).unwrap();
+        assert!((sol.u[0] - 5.8).abs() < 1e-8);
+        assert_eq!((sol.cutbacks, sol.increments.len()), (0, 1));
+        assert_eq!(p.committed.len(), 1);
+    }
+
+    #[test]
+    fn cutback_without_line_search() {
+        let mut p = Arctangent { root: 5.8, committed: Vec::new() };
+        let options = NewtonOptions { line_search: false, max_iterations: 8, ..Default::default() };
+        let sol = newton_raphson(&mut p, &options, &LinearSolver::default()).unwrap();
+        assert!((sol.u[0] - 5.8).abs() < 1e-8);
+        assert!(sol.cutbacks > 0);
+        assert_eq!(sol.increments.last().unwrap().load_factor, 1.0);
+        // only converged increments are committed, in order
+        assert_eq!(p.committed.len(), sol.increments.len());
+        assert!(p.committed.windows(2).all(|w| w[1] > w[0]));
+
+        let options = NewtonOptions { min_step: 0.5, ..options };
+        assert!(newton_raphson(&mut p, &options, &LinearSolver::default()).is_err());
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use base_types::*;
+use fem::shape;
+use meshing::Mesh;
+use solvers::LinearSolver;
+use super::assembly::Assembler;
+use super::elasticity::*;
+use super::nonlinear::*;
+use super::stress::{SymTensor, recovery_points, average_to_nodes};
+
+// Von Mises plasticity with linear isotropic and kinematic (Prager) hardening
+#[derive(Debug, Clone, PartialEq)]
+pub struct J2Material {
+    pub young: f64,
+    pub poisson: f64,
+    pub yield_stress: f64,
+    // Slope of the yield stress over the equivalent plastic strain
+    pub isotropic_hardening: f64,
+    // Back stress rate over the plastic strain rate, times 3/2
+    pub kinematic_hardening: f64
+}
+
+// History of an integration point
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlasticState {
+    pub stress: SymTensor,
+    pub plastic_strain: SymTensor,
+    pub back_stress: SymTensor,
+    pub equivalent_plastic_strain: f64
+}
+
+impl J2Material {
+    // Perfectly plastic material
+    pub fn new(young: f64, poisson: f64, yield_stress: f64) -> J2Material {
+        J2Material { young, poisson, yield_stress, isotropic_hardening: 0.0, kinematic_hardening: 0.0 }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.young <= 0.0 || self.poisson <= -1.0 || self.poisson >= 0.5 || self.yield_stress <= 0.0
+            || self.isotropic_hardening < 0.0 || self.kinematic_hardening < 0.0 {
+            return Err(format!("invalid J2 material {:?}", self));
+        }
+        Ok(())
+    }
+
+    // Bulk and shear modulus
+    fn moduli(&self) -> (f64, f64) {
+        (self.young / (3.0 * (1.0 - 2.0 * self.poisson)), self.young / (2.0 * (1.0 + self.poisson)))
+    }
+
+    // Radial return from the converged state `state` for the total strain
+    // (tensor shear). Returns the new state and the consistent tangent for
+    // [xx, yy, zz, xy] stresses over [xx, yy, zz, gamma_xy] strains.
+    pub fn return_map(&self, strain: &SymTensor, state: &PlasticState) -> (PlasticState, DMatrix) {
+        let (kappa, mu) = self.moduli();
+        let elastic = *strain - state.plastic_strain;
+        let hydrostatic = kappa * elastic.trace();
+        let trial = elastic.deviator() * (2.0 * mu);
+        let xi = trial - state.back_stress;
+        let radius = (2.0f64 / 3.0).sqrt()
+            * (self.yield_stress + self.isotropic_hardening * state.equivalent_plastic_strain);
+        let f = xi.norm() - radius;
+
+        let mut tangent = DMatrix::zeros(4, 4);
+        let (dgamma, theta, theta_bar, n) = if f <= 0.0 {
+            (0.0, 1.0, 0.0, SymTensor::default())
+        } else {
+            let hardening = self.isotropic_hardening + self.kinematic_hardening;
+            let dgamma = f / (2.0 * mu + 2.0 / 3.0 * hardening);
+            let n = xi * (1.0 / xi.norm());
+            let theta = 1.0 - 2.0 * mu * dgamma / xi.norm();
+            (dgamma, theta, 1.0 / (1.0 + hardening / (3.0 * mu)) - (1.0 - theta), n)
+        };
+        for i in 0..3 {
+            for j in 0..3 {
+                let dev = if i == j { 2.0 / 3.0 } else { -1.0 / 3.0 };
+                tangent[(i, j)] = kappa + 2.0 * mu * theta * dev;
+            }
+        }
+        tangent[(3, 3)] = mu * theta;
+        let nc = n.components();
+        for i in 0..4 {
+            for j in 0..4 {
+                tangent[(i, j)] -= 2.0 * mu * theta_bar * nc[i] * nc[j];
+            }
+        }
+
+        if f <= 0.0 {
+            let stress = trial + SymTensor::new(hydrostatic, hydrostatic, hydrostatic, 0.0);
+            return (PlasticState { stress, ..*state }, tangent);
+        }
+        let stress = trial - n * (2.0 * mu * dgamma) + SymTensor::new(hydrostatic, hydrostatic, hydrostatic, 0.0);
+        let updated = PlasticState {
+            stress,
+            plastic_strain: state.plastic_strain + n * dgamma,
+            back_stress: state.back_stress + n * (2.0 / 3.0 * self.kinematic_hardening * dgamma),
+            equivalent_plastic_strain: state.equivalent_plastic_strain + (2.0f64 / 3.0).sqrt() * dgamma
+        };
+        (updated, tangent)
+    }
+}
+
+// Positions of the analysis strain components in [xx, yy, zz, xy]
+fn components(mode: PlaneMode) -> &'static [usize] {
+    match mode {
+        PlaneMode::Axisymmetric => &[0, 1, 3, 2],
+        _ => &[0, 1, 3]
+    }
+}
+
+// Strain tensor from the engineering strain vector of the analysis
+fn strain_tensor(eps: &[f64], mode: PlaneMode) -> SymTensor {
+    let hoop = if mode == PlaneMode::Axisymmetric { eps[3] } else { 0.0 };
+    SymTensor::new(eps[0], eps[1], hoop, eps[2] / 2.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlasticQuantity {
+    Stress(usize),
+    PlasticStrain(usize),
+    EquivalentPlasticStrain,
+    VonMises
+}
+
+impl PlasticQuantity {
+    fn evaluate(&self, state: &PlasticState) -> f64 {
+        match self {
+            PlasticQuantity::Stress(i) => state.stress.components()[*i],
+            PlasticQuantity::PlasticStrain(i) => state.plastic_strain.components()[*i],
+            PlasticQuantity::EquivalentPlasticStrain => state.equivalent_plastic_strain,
+            PlasticQuantity::VonMises => state.stress.von_mises()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlasticSolution {
+    pub displacement: Vec<Vec2>,
+    pub reactions: Vec<Vec2>,
+    // History at the integration points (`recovery_points`) of every element
+    pub states: Vec<Vec<PlasticState>>,
+    pub increments: Vec<LoadIncrement>,
+    pub cutbacks: usize
+}
+
+impl PlasticSolution {
+    pub fn element(&self, quantity: PlasticQuantity) -> Vec<Vec<f64>> {
+        self.states.iter().map(|points| points.iter().map(|s| quantity.evaluate(s)).collect()).collect()
+    }
+
+    // Extrapolated to the nodes and averaged
+    pub fn nodal(&self, mesh: &Mesh, quantity: PlasticQuantity) -> Result<Vec<f64>, String> {
+        average_to_nodes(mesh, &self.element(quantity))
+    }
+}
+
+// Elastoplastic analysis of a plane strain or axisymmetric model. Supports,
+// loads and the elastic materials come from `structure`; regions with a J2
+// material behave elastoplastically. Loads, prescribed displacements and
+// thermal strains are applied proportionally with the load factor of the
+// Newton-Raphson driver.
+pub struct ElastoplasticProblem<'a> {
+    structure: ElasticityProblem<'a>,
+    plastic: HashMap<String, J2Material>
+}
+
+impl<'a> ElastoplasticProblem<'a> {
+    pub fn new(structure: ElasticityProblem<'a>) -> ElastoplasticProblem<'a> {
+        ElastoplasticProblem { structure, plastic: HashMap::new() }
+    }
+
+    pub fn structure(&self) -> &ElasticityProblem<'a> { &self.structure }
+
+    pub fn set_plastic_material(&mut self, region: &str, material: J2Material) {
+        self.plastic.insert(region.to_string(), material);
+    }
+
+    pub fn solve(&self, options: &NewtonOptions, solver: &LinearSolver) -> Result<PlasticSolution, String> {
+        let mode = self.structure.mode();
+        if mode == PlaneMode::PlaneStress {
+            return Err("J2 plasticity needs a plane strain or axisymmetric model".to_string());
+        }
+        let mesh = self.structure.mesh();
+        mode.symmetry().check(mesh)?;
+        let mut laws = Vec::with_capacity(mesh.elements.len());
+        for e in &mesh.elements {
+            laws.push(match self.plastic.get(&e.region) {
+                Some(m) => {
+                    m.check()?;
+                    Law::Plastic(m)
+                },
+                None => Law::Elastic(self.structure.material(&e.region)?.d_matrix(mode)?)
+            });
+        }
+        let mut system = PlasticSystem {
+            structure: &self.structure,
+            laws,
+            external: self.structure.external_forces()?,
+            fixed: self.structure.constraints()?,
+            states: mesh.elements.iter()
+                .map(|e| vec![PlasticState::default(); recovery_points(e.kind).len()])
+                .collect(),
+            trial: Vec::new()
+        };
+        let sol = newton_raphson(&mut system, options, solver)?;
+
+        let mut reactions = vec![Vec2(0.0, 0.0); mesh.node_count()];
+        for &(dof, _) in &system.fixed {
+            let r = sol.residual[dof];
+            if dof % 2 == 0 { reactions[dof / 2].0 = r } else { reactions[dof / 2].1 = r }
+        }
+        Ok(PlasticSolution {
+            displacement: sol.u.chunks(2).map(|c| Vec2(c[0], c[1])).collect(),
+            reactions,
+            states: system.states,
+            increments: sol.increments,
+            cutbacks: sol.cutbacks
+        })
+    }
+}
+
+enum Law<'m> {
+    Elastic(DMatrix),
+    Plastic(&'m J2Material)
+}
+
+struct PlasticSystem<'p, 'a: 'p> {
+    structure: &'p ElasticityProblem<'a>,
+    laws: Vec<Law<'p>>,
+    external: Vec<f64>,
+    fixed: Vec<(usize, f64)>,
+    // converged history
+    states: Vec<Vec<PlasticState>>,
+    // states of the last residual evaluation
+    trial: Vec<Vec<PlasticState>>
+}
+
+impl<'p, 'a> PlasticSystem<'p, 'a> {
+    // Tangent (only assembled on request) and internal forces for the
+    // displacements `u`, with the thermal strains scaled by the load factor;
+    // keeps the trial states
+    fn evaluate(&mut self, u: &[f64], load_factor: f64, with_tangent: bool)
+        -> Result<(CsrMatrix, Vec<f64>), String> {
+        let mesh = self.structure.mesh();
+        let mode = self.structure.mode();
+        let index = components(mode);
+        let displacement: Vec<Vec2> = u.chunks(2).map(|c| Vec2(c[0], c[1])).collect();
+        let mut asm = Assembler::new(mesh.node_count(), 2);
+        let mut trial = Vec::with_capacity(mesh.elements.len());
+        for ((e, law), states) in mesh.elements.iter().zip(&self.laws).zip(&self.states) {
+            let coords = mesh.coords(&e.nodes);
+            let ue = element_displacements(&e.nodes, &displacement);
+            let dofs = ue.len();
+            let mut fe = vec![0.0; dofs];
+            let mut ke = DMatrix::zeros(dofs, dofs);
+            let mut element_states = Vec::with_capacity(states.len());
+            for (q, state) in recovery_points(e.kind).iter().zip(states) {
+                let iso = shape::map(e.kind, &coords, &q.point)?;
+                let w = q.weight * iso.det_j * self.structure.weight(&iso.x);
+                let b = b_matrix(&iso, mode);
+                let eps = b.mul_vec(&ue);
+                let thermal = load_factor * self.structure.thermal_strain(e, &iso)?;
+                let (stress, d) = match law {
+                    Law::Elastic(d) => {
+                        let s0 = self.structure.thermal_stress(e, &iso)?;
+                        let s: Vec<f64> = d.mul_vec(&eps).iter().zip(&s0)
+                            .map(|(s, s0)| s - load_factor * s0).collect();
+                        let mut full = [0.0; 4];
+                        index.iter().zip(&s).for_each(|(&i, v)| full[i] = *v);
+                        element_states.push(PlasticState { stress: SymTensor::from_components(&full), ..*state });
+                        (s, d.clone())
+                    },
+                    Law::Plastic(m) => {
+                        let mechanical = strain_tensor(&eps, mode) - SymTensor::new(thermal, thermal, thermal, 0.0);
+                        let (updated, c) = m.return_map(&mechanical, state);
+                        let full = updated.stress.components();
+                        let mut d = DMatrix::zeros(index.len(), index.len());
+                        for (a, &i) in index.iter().enumerate() {
+                            for (b, &j) in index.iter().enumerate() {
+                                d[(a, b)] = c[(i, j)];
+                            }
+                        }
+                        element_states.push(updated);
+                        (index.iter().map(|&i| full[i]).collect::<Vec<f64>>(), d)
+                    }
+                };
+                for (f, v) in fe.iter_mut().zip(b.tr_mul_vec(&stress)) {
+                    *f += v * w;
+                }
+                if with_tangent {
+                    ke.add_btdb(&b, &d, w);
+                }
+            }
+            asm.add_vector(&e.nodes, &fe);
+            if with_tangent {
+                asm.add_matrix(&e.nodes, &ke);
+            }
+            trial.push(element_states);
+        }
+        self.trial = trial;
+        Ok(asm.finish())
+    }
+}
+
+impl<'p, 'a> NonlinearProblem for PlasticSystem<'p, 'a> {
+    fn dof_count(&self) -> usize { self.external.len() }
+
+    fn constraints(&self, load_factor: f64) -> Result<Vec<(usize, f64)>, String> {
+        Ok(self.fixed.iter().map(|&(d, v)| (d, v * load_factor)).collect())
+    }
+
+    fn residual(&mut self, u: &[f64], load_factor: f64) -> Result<Vec<f64>, String> {
+        let f = self.evaluate(u, load_factor, false)?.1;
+        Ok(f.iter().zip(&self.external).map(|(fi, fe)| fi - load_factor * fe).collect())
+    }
+
+    fn tangent(&mut self, u: &[f64], load_factor: f64) -> Result<CsrMatrix, String> {
+        Ok(self.evaluate(u, load_factor, true)?.0)
+    }
+
+    fn commit(&mut self, _u: &[f64], _load_factor: f64) {
+        // the last residual was evaluated at the converged displacements
+        self.states = ::std::mem::take(&mut self.trial);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing;
+
+    fn steel() -> J2Material {
+        J2Material { isotropic_hardening: 2000.0, kinematic_hardening: 1000.0, ..J2Material::new(200e3, 0.3, 250.0) }
+    }
+
+    #[test]
+    fn pure_shear_hardening() {
+        // tau = (s_y + H sqrt(2/3) G) / sqrt(3) = mu (gamma - sqrt(2) G)
+        let m = J2Material { isotropic_hardening: 5000.0, ..J2Material::new(200e3, 0.3, 250.0) };
+        let mu = 200e3 / 2.6;
+        let mut state = PlasticState::default();
+        for step in 1..11 {
+            let gamma = 0.0005 * step as f64;
+            state = m.return_map(&SymTensor::new(0.0, 0.0, 0.0, gamma / 2.0), &state).0;
+            let g = ((mu * gamma - 250.0 / 3f64.sqrt()) / (2f64.sqrt() * (mu + 5000.0 / 3.0))).max(0.0);
+            let tau = mu * (gamma - 2f64.sqrt() * g);
+            assert!((state.stress.xy - tau).abs() < 1e-9 * tau, "{} {}", state.stress.xy, tau);
+            assert!((state.equivalent_plastic_strain - (2.0f64 / 3.0).sqrt() * g).abs() < 1e-12);
+        }
+        assert!(state.stress.xx.abs() < 1e-9 && state.stress.zz.abs() < 1e-9);
+    }
+
+    #[test]
+    fn consistent_tangent() {
+        let m = steel();
+        let start = m.return_map(&SymTensor::new(0.002, -0.001, 0.0, 0.0008), &PlasticState::default()).0;
+        let strain = SymTensor::new(0.0031, -0.0012, 0.0004, 0.0015);
+        let (_, c) = m.return_map(&strain, &start);
+        let h = 1e-8;
+        for j in 0..4 {
+            // engineering shear column
+            let mut step = [0.0; 4];
+            step[j] = if j == 3 { h / 2.0 } else { h };
+            let plus = strain + SymTensor::from_components(&step);
+            let minus = strain - SymTensor::from_components(&step);
+            let dp = m.return_map(&plus, &start).0.stress.components();
+            let dm = m.return_map(&minus, &start).0.stress.components();
+            for i in 0..4 {
+                let fd = (dp[i] - dm[i]) / (2.0 * h);
+                assert!((fd - c[(i, j)]).abs() < 1e-4 * c[(0, 0)], "{} {}: {} vs {}", i, j, fd, c[(i, j)]);
+            }
+        }
+    }
+
+    #[test]
+    fn plane_strain_limit_load() {
+        // stretched block free to contract sideways: s_yy = 0 and with plastic
+        // incompressibility s_zz -> s_xx / 2, so s_xx -> 2 s_y / sqrt(3)
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 1.0), 2, 2, ElementKind::Quad8, "block");
+        let mut structure = ElasticityProblem::new(&mesh, PlaneMode::PlaneStrain, 1.0);
+        structure.add_support(Support::Roller { tag: "left".to_string(), axis: Axis::X });
+        structure.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+        structure.add_support(Support::Displacement { tag: "right".to_string(), ux: Some(0.1), uy: None });
+        let mut p = ElastoplasticProblem::new(structure);
+        p.set_plastic_material("block", J2Material::new(200e3, 0.3, 250.0));
+        let options = NewtonOptions { initial_step: 0.05, max_step: 0.1, ..Default::default() };
+        let sol = p.solve(&options, &LinearSolver::default()).unwrap();
+
+        let force = mesh.boundary_nodes("right").iter().map(|&n| sol.reactions[n].0).sum::<f64>();
+        let limit = 2.0 * 250.0 / 3f64.sqrt();
+        assert!((force - limit).abs() < 1e-3 * limit, "{} vs {}", force, limit);
+        assert!(sol.increments.iter().all(|i| i.iterations <= 10));
+
+        // homogeneous state
+        let eq = sol.nodal(&mesh, PlasticQuantity::EquivalentPlasticStrain).unwrap();
+        assert!(eq.iter().all(|e| (e - eq[0]).abs() < 1e-9 && *e > 0.05));
+        let vm = sol.nodal(&mesh, PlasticQuantity::VonMises).unwrap();
+        assert!(vm.iter().all(|v| (v - 250.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn plane_stress_is_rejected() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 1.0), 1, 1, ElementKind::Quad4, "block");
+        let mut p = ElastoplasticProblem::new(ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 1.0));
+        p.set_plastic_material("block", steel());
+        assert!(p.solve(&NewtonOptions::default(), &LinearSolver::default()).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use base_types::*;
+use fem::shape::{self, ElementKind};
+use meshing::Mesh;
+use solvers::LinearSolver;
+use super::assembly::{Assembler, LinearSystem, Symmetry};
+use super::constraints::*;
+
+// Boundary conditions of a scalar field problem -div(k grad u) = s, applied
+// on all boundary edges carrying `tag`.
+#[derive(Debug, Clone)]
+pub enum ScalarBoundary {
+    // u = value
+    Fixed { tag: String, value: f64 },
+    // k du/dn = value, positive into the domain
+    Flux { tag: String, value: f64 },
+    // k du/dn = coefficient (ambient - u)
+    Robin { tag: String, coefficient: f64, ambient: f64 }
+}
+
+// Element matrix of -div(k grad u) = s and its load vector.
+pub fn diffusion_element(kind: ElementKind, coords: &[Vec2], k: f64, source: f64, symmetry: Symmetry)
+    -> Result<(DMatrix, Vec<f64>), String> {
+    let n = kind.node_count();
+    let mut ke = DMatrix::zeros(n, n);
+    let mut fe = vec![0.0; n];
+    for q in kind.quadrature(kind.quadrature_degree()) {
+        let iso = shape::map(kind, coords, &q.point)?;
+        let w = q.weight * iso.det_j * symmetry.weight(&iso.x);
+        for a in 0..n {
+            for b in 0..n {
+                ke[(a, b)] += k * iso.grad[a].dot(&iso.grad[b]) * w;
+            }
+            fe[a] += source * iso.n[a] * w;
+        }
+    }
+    Ok((ke, fe))
+}
+
+// Consistent mass (capacity) matrix int(rho N N dV)
+pub fn mass_element(kind: ElementKind, coords: &[Vec2], density: f64, symmetry: Symmetry)
+    -> Result<DMatrix, String> {
+    let n = kind.node_count();
+    let mut me = DMatrix::zeros(n, n);
+    for q in kind.quadrature(kind.quadrature_degree()) {
+        let iso = shape::map(kind, coords, &q.point)?;
+        let w = q.weight * iso.det_j * symmetry.weight(&iso.x) * density;
+        for a in 0..n {
+            for b in 0..n {
+                me[(a, b)] += iso.n[a] * iso.n[b] * w;
+            }
+        }
+    }
+    Ok(me)
+}
+
+// Consistent edge load of a distributed value, int(N q ds)
+pub fn edge_load(kind: ElementKind, coords: &[Vec2], value: f64, symmetry: Symmetry)
+    -> Result<Vec<f64>, String> {
+    let mut fe = vec![0.0; kind.node_count()];
+    for q in kind.quadrature(kind.quadrature_degree()) {
+        let e = shape::map_edge(kind, coords, q.point.0)?;
+        let w = q.weight * e.det_j * symmetry.weight(&e.x);
+        for (f, n) in fe.iter_mut().zip(&e.n) {
+            *f += value * n * w;
+        }
+    }
+    Ok(fe)
+}
+
+// Robin edge terms: int(h N N ds) and int(h u_inf N ds)
+pub fn edge_robin(kind: ElementKind, coords: &[Vec2], coefficient: f64, ambient: f64,
+                  symmetry: Symmetry) -> Result<(DMatrix, Vec<f64>), String> {
+    let n = kind.node_count();
+    let mut ke = DMatrix::zeros(n, n);
+    let mut fe = vec![0.0; n];
+    for q in kind.quadrature(kind.quadrature_degree()) {
+        let e = shape::map_edge(kind, coords, q.point.0)?;
+        let w = q.weight * e.det_j * symmetry.weight(&e.x) * coefficient;
+        for a in 0..n {
+            for b in 0..n {
+                ke[(a, b)] += e.n[a] * e.n[b] * w;
+            }
+            fe[a] += ambient * e.n[a] * w;
+        }
+    }
+    Ok((ke, fe))
+}
+
+// Gradient of a nodal field at the centre of every element
+pub fn element_gradients(mesh: &Mesh, field: &[f64]) -> Result<Vec<Vec2>, String> {
+    mesh.elements.iter().map(|e| {
+        let iso = shape::map(e.kind, &mesh.coords(&e.nodes), &e.kind.centre())?;
+        Ok(e.nodes.iter().zip(&iso.grad)
+            .fold(Vec2(0.0, 0.0), |g, (&n, dn)| g + dn.clone() * field[n]))
+    }).collect()
+}
+
+// Generic steady scalar field problem -div(k grad u) = s with coefficients
+// per mesh region. Heat conduction and the electromagnetic potentials are all
+// instances of it.
+pub struct ScalarProblem<'a> {
+    mesh: &'a Mesh,
+    symmetry: Symmetry,
+    coefficient: HashMap<String, f64>,
+    source: HashMap<String, f64>,
+    boundaries: Vec<ScalarBoundary>,
+    mpcs: Vec<Mpc>,
+    ties: Vec<Tie>,
+    constraint_method: ConstraintMethod
+}
+
+impl<'a> ScalarProblem<'a> {
+    pub fn new(mesh: &'a Mesh) -> ScalarProblem<'a> {
+        ScalarProblem {
+            mesh,
+            symmetry: Symmetry::Planar,
+            coefficient: HashMap::new(),
+            source: HashMap::new(),
+            boundaries: Vec::new(),
+            mpcs: Vec::new(),
+            ties: Vec::new(),
+            constraint_method: ConstraintMethod::default()
+        }
+    }
+
+    pub fn mesh(&self) -> &'a Mesh { self.mesh }
+    pub fn symmetry(&self) -> Symmetry { self.symmetry }
+
+    pub fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.symmetry = symmetry;
+    }
+
+    pub fn set_coefficient(&mut self, region: &str, k: f64) {
+        self.coefficient.insert(region.to_string(), k);
+    }
+
+    pub fn set_source(&mut self, region: &str, s: f64) {
+        self.source.insert(region.to_string(), s);
+    }
+
+    pub fn add_boundary(&mut self, bc: ScalarBoundary) {
+        self.boundaries.push(bc);
+    }
+
+    pub fn coefficient(&self, region: &str) -> Result<f64, String> {
+        self.coefficient.get(region).cloned()
+            .ok_or_else(|| format!("no coefficient given for region '{}'", region))
+    }
+
+    pub fn source(&self, region: &str) -> f64 {
+        self.source.get(region).cloned().unwrap_or(0.0)
+    }
+
+    pub fn boundaries(&self) -> &[ScalarBoundary] { &self.boundaries }
+
+    // Constraint equation over the nodal values
+    pub fn add_mpc(&mut self, mpc: Mpc) {
+        self.mpcs.push(mpc);
+    }
+
+    pub fn add_tie(&mut self, tie: Tie) {
+        self.ties.push(tie);
+    }
+
+    pub fn set_constraint_method(&mut self, method: ConstraintMethod) {
+        self.constraint_method = method;
+    }
+
+    // Constraint equations of all MPCs and ties
+    pub fn linear_constraints(&self) -> Result<Vec<Mpc>, String> {
+        let mut mpcs = self.mpcs.clone();
+        for tie in &self.ties {
+            mpcs.extend(tie.constraints(self.mesh, 1)?);
+        }
+        Ok(mpcs)
+    }
+
+    pub fn assemble(&self) -> Result<LinearSystem, String> {
+        let mesh = self.mesh;
+        self.symmetry.check(mesh)?;
+        let mut asm = Assembler::new(mesh.node_count(), 1);
+        for e in &mesh.elements {
+            let coords = mesh.coords(&e.nodes);
+            let (ke, fe) = diffusion_element(e.kind, &coords, self.coefficient(&e.region)?,
+                                             self.source(&e.region), self.symmetry)?;
+            asm.add_matrix(&e.nodes, &ke);
+            asm.add_vector(&e.nodes, &fe);
+        }
+
+        let mut fixed = Vec::new();
+        for bc in &self.boundaries {
+            match bc {
+                ScalarBoundary::Fixed { tag, value } => {
+                    mesh.check_tag(tag)?;
+                    fixed.extend(mesh.boundary_nodes(tag).into_iter().map(|n| (n, *value)));
+                },
+                ScalarBoundary::Flux { tag, value } => {
+                    mesh.check_tag(tag)?;
+                    for edge in mesh.boundary_with_tag(tag) {
+                        let fe = edge_load(edge.kind, &mesh.coords(&edge.nodes), *value,
+                                               self.symmetry)?;
+                        asm.add_vector(&edge.nodes, &fe);
+                    }
+                },
+                ScalarBoundary::Robin { tag, coefficient, ambient } => {
+                    mesh.check_tag(tag)?;
+                    for edge in mesh.boundary_with_tag(tag) {
+                        let (ke, fe) = edge_robin(edge.kind, &mesh.coords(&edge.nodes),
+                                                  *coefficient, *ambient, self.symmetry)?;
+                        asm.add_matrix(&edge.nodes, &ke);
+                        asm.add_vector(&edge.nodes, &fe);
+                    }
+                }
+            }
+        }
+
+        let (matrix, rhs) = asm.finish();
+        Ok(LinearSystem { matrix, rhs, fixed })
+    }
+
+    pub fn solve(&self, solver: &LinearSolver) -> Result<Vec<f64>, String> {
+        solve_constrained(&self.assemble()?, &self.linear_constraints()?, self.constraint_method, solver)
+    }
+}
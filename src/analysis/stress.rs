@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::ops::{Add, Sub, Mul};
+use base_types::*;
+use fem::quadrature::QuadraturePoint;
+use fem::shape::{self, ElementKind};
+use meshing::Mesh;
+use results::{Field, FieldLocation};
+use super::elasticity::*;
+
+// Symmetric tensor with the out-of-plane (hoop for axisymmetric models)
+// component. Strains use tensor shear, xy = gamma_xy / 2.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SymTensor {
+    pub xx: f64,
+    pub yy: f64,
+    pub zz: f64,
+    pub xy: f64
+}
+
+impl SymTensor {
+    pub fn new(xx: f64, yy: f64, zz: f64, xy: f64) -> SymTensor {
+        SymTensor { xx, yy, zz, xy }
+    }
+
+    pub fn components(&self) -> [f64; 4] {
+        [self.xx, self.yy, self.zz, self.xy]
+    }
+
+    pub fn from_components(c: &[f64]) -> SymTensor {
+        SymTensor::new(c[0], c[1], c[2], c[3])
+    }
+
+    pub fn trace(&self) -> f64 {
+        self.xx + self.yy + self.zz
+    }
+
+    pub fn deviator(&self) -> SymTensor {
+        let p = self.trace() / 3.0;
+        SymTensor::new(self.xx - p, self.yy - p, self.zz - p, self.xy)
+    }
+
+    // sqrt(a : a)
+    pub fn norm(&self) -> f64 {
+        self.contract(self).sqrt()
+    }
+
+    // Double contraction a : b
+    pub fn contract(&self, other: &SymTensor) -> f64 {
+        self.xx * other.xx + self.yy * other.yy + self.zz * other.zz + 2.0 * self.xy * other.xy
+    }
+
+    // Principal values, descending
+    pub fn principal(&self) -> [f64; 3] {
+        let c = (self.xx + self.yy) / 2.0;
+        let r = ((self.xx - self.yy) / 2.0).hypot(self.xy);
+        let mut p = [c + r, c - r, self.zz];
+        p.sort_by(|a, b| b.total_cmp(a));
+        p
+    }
+
+    // Angle from the x axis to the larger in-plane principal direction
+    pub fn principal_angle(&self) -> f64 {
+        0.5 * (2.0 * self.xy).atan2(self.xx - self.yy)
+    }
+
+    pub fn von_mises(&self) -> f64 {
+        let (a, b, c) = (self.xx - self.yy, self.yy - self.zz, self.zz - self.xx);
+        (0.5 * (a * a + b * b + c * c) + 3.0 * self.xy * self.xy).sqrt()
+    }
+
+    // Largest difference of principal values (twice the largest shear)
+    pub fn tresca(&self) -> f64 {
+        let p = self.principal();
+        p[0] - p[2]
+    }
+}
+
+impl Add for SymTensor {
+    type Output = SymTensor;
+    fn add(self, o: SymTensor) -> SymTensor {
+        SymTensor::new(self.xx + o.xx, self.yy + o.yy, self.zz + o.zz, self.xy + o.xy)
+    }
+}
+
+impl Sub for SymTensor {
+    type Output = SymTensor;
+    fn sub(self, o: SymTensor) -> SymTensor {
+        SymTensor::new(self.xx - o.xx, self.yy - o.yy, self.zz - o.zz, self.xy - o.xy)
+    }
+}
+
+impl Mul<f64> for SymTensor {
+    type Output = SymTensor;
+    fn mul(self, f: f64) -> SymTensor {
+        SymTensor::new(self.xx * f, self.yy * f, self.zz * f, self.xy * f)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GaussPointState {
+    pub position: Vec2,
+    // Integration weight times the volume measure (thickness or 2 pi r)
+    pub volume: f64,
+    pub strain: SymTensor,
+    pub stress: SymTensor
+}
+
+impl GaussPointState {
+    pub fn strain_energy_density(&self) -> f64 {
+        0.5 * self.stress.contract(&self.strain)
+    }
+}
+
+// Scalar results derived from a recovered stress field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StressQuantity {
+    Stress(usize),
+    Strain(usize),
+    VonMises,
+    Tresca,
+    // 0 is the largest
+    Principal(usize),
+    PrincipalAngle,
+    StrainEnergyDensity
+}
+
+impl StressQuantity {
+    // Rejects component indices out of range
+    pub fn check(&self) -> Result<(), String> {
+        let ok = match self {
+            StressQuantity::Stress(i) | StressQuantity::Strain(i) => *i < 4,
+            StressQuantity::Principal(i) => *i < 3,
+            _ => true
+        };
+        if ok { Ok(()) } else { Err(format!("{:?} has no such component", self)) }
+    }
+
+    pub fn name(&self) -> Result<String, String> {
+        const AXES: [&str; 4] = ["xx", "yy", "zz", "xy"];
+        self.check()?;
+        Ok(match self {
+            StressQuantity::Stress(i) => format!("S{}", AXES[*i]),
+            StressQuantity::Strain(i) => format!("E{}", AXES[*i]),
+            StressQuantity::VonMises => "von Mises".to_string(),
+            StressQuantity::Tresca => "Tresca".to_string(),
+            StressQuantity::Principal(i) => format!("S{}", i + 1),
+            StressQuantity::PrincipalAngle => "principal angle".to_string(),
+            StressQuantity::StrainEnergyDensity => "strain energy density".to_string()
+        })
+    }
+
+    fn evaluate(&self, strain: &SymTensor, stress: &SymTensor) -> f64 {
+        match self {
+            StressQuantity::Stress(i) => stress.components()[*i],
+            StressQuantity::Strain(i) => strain.components()[*i],
+            StressQuantity::VonMises => stress.von_mises(),
+            StressQuantity::Tresca => stress.tresca(),
+            StressQuantity::Principal(i) => stress.principal()[*i],
+            StressQuantity::PrincipalAngle => stress.principal_angle(),
+            StressQuantity::StrainEnergyDensity => 0.5 * stress.contract(strain)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StressField {
+    // Integration point states of every element
+    pub gauss: Vec<Vec<GaussPointState>>,
+    // Extrapolated to the nodes and averaged over the adjacent elements
+    pub nodal_strain: Vec<SymTensor>,
+    pub nodal_stress: Vec<SymTensor>
+}
+
+impl StressField {
+    pub fn nodal(&self, quantity: StressQuantity) -> Result<Vec<f64>, String> {
+        quantity.check()?;
+        Ok(self.nodal_strain.iter().zip(&self.nodal_stress)
+            .map(|(e, s)| quantity.evaluate(e, s))
+            .collect())
+    }
+
+    // Volume average over every element
+    pub fn element(&self, quantity: StressQuantity) -> Result<Vec<f64>, String> {
+        quantity.check()?;
+        Ok(self.gauss.iter().map(|points| {
+            let volume: f64 = points.iter().map(|g| g.volume).sum();
+            points.iter().map(|g| quantity.evaluate(&g.strain, &g.stress) * g.volume).sum::<f64>() / volume
+        }).collect())
+    }
+
+    // Nodal field with one component per quantity
+    pub fn nodal_field(&self, name: &str, quantities: &[StressQuantity]) -> Result<Field, String> {
+        let columns = quantities.iter().map(|q| self.nodal(*q)).collect::<Result<Vec<_>, _>>()?;
+        let names = quantities.iter().map(|q| q.name()).collect::<Result<Vec<_>, _>>()?;
+        let values = (0..self.nodal_stress.len()).flat_map(|n| columns.iter().map(move |c| c[n])).collect();
+        Field::new(name, FieldLocation::Nodal, &names.iter().map(|c| c.as_str()).collect::<Vec<_>>(), values)
+    }
+
+    // Result fields of the recovered solution: nodal strain, stress and the
+    // derived quantities, and the stress at the integration points of `mesh`
+    pub fn fields(&self, mesh: &Mesh) -> Result<Vec<Field>, String> {
+        use self::StressQuantity::*;
+        let mut fields = vec![
+            self.nodal_field("strain", &[Strain(0), Strain(1), Strain(2), Strain(3)])?,
+            self.nodal_field("stress", &[Stress(0), Stress(1), Stress(2), Stress(3)])?,
+            self.nodal_field("principal stress", &[Principal(0), Principal(1), Principal(2), PrincipalAngle])?,
+            self.nodal_field("von Mises", &[VonMises])?,
+            self.nodal_field("Tresca", &[Tresca])?,
+            self.nodal_field("strain energy density", &[StrainEnergyDensity])?
+        ];
+
+        if self.gauss.len() != mesh.elements.len() {
+            return Err(format!("stresses of {} elements for a mesh of {}", self.gauss.len(), mesh.elements.len()));
+        }
+        let mut points = Vec::new();
+        let mut values = Vec::new();
+        for (i, (e, states)) in mesh.elements.iter().zip(&self.gauss).enumerate() {
+            points.extend(recovery_points(e.kind).into_iter().map(|q| (i, q.point)));
+            values.extend(states.iter().flat_map(|g| g.stress.components().to_vec()));
+        }
+        let names = (0..4).map(|c| Stress(c).name()).collect::<Result<Vec<_>, _>>()?;
+        fields.push(Field::new("stress at integration points", FieldLocation::GaussPoints(points),
+                               &names.iter().map(|c| c.as_str()).collect::<Vec<_>>(), values)?);
+        Ok(fields)
+    }
+
+    pub fn strain_energy(&self) -> f64 {
+        self.gauss.iter().flat_map(|points| points.iter())
+            .map(|g| g.strain_energy_density() * g.volume)
+            .sum()
+    }
+}
+
+// Integration points stresses are recovered at, those of the stiffness
+pub fn recovery_points(kind: ElementKind) -> Vec<QuadraturePoint> {
+    kind.quadrature(kind.quadrature_degree())
+}
+
+// Maps values at the recovery points of an element to its nodes. The values
+// are fitted by least squares with the shape functions of the corner element,
+// which is exact for a field that varies linearly (bilinearly for quads).
+pub fn extrapolation_matrix(kind: ElementKind) -> Result<DMatrix, String> {
+    let corner = if kind.is_triangle() { ElementKind::Tri3 } else { ElementKind::Quad4 };
+    let points = recovery_points(kind);
+    let mut a = DMatrix::zeros(points.len(), corner.corner_count());
+    for (i, q) in points.iter().enumerate() {
+        for (j, n) in corner.shape_functions(&q.point).into_iter().enumerate() {
+            a[(i, j)] = n;
+        }
+    }
+    let fit = a.tr_matmul(&a).inverse()
+        .map_err(|_| format!("too few recovery points for {:?}", kind))?
+        .matmul(&a.transpose());
+    let nodes = kind.natural_nodes();
+    let mut at_nodes = DMatrix::zeros(nodes.len(), corner.corner_count());
+    for (i, xi) in nodes.iter().enumerate() {
+        for (j, n) in corner.shape_functions(xi).into_iter().enumerate() {
+            at_nodes[(i, j)] = n;
+        }
+    }
+    Ok(at_nodes.matmul(&fit))
+}
+
+// Extrapolates values given at the recovery points of every element to the
+// nodes and averages the contributions of the elements sharing a node
+pub fn average_to_nodes(mesh: &Mesh, values: &[Vec<f64>]) -> Result<Vec<f64>, String> {
+    let mut cache: HashMap<ElementKind, DMatrix> = HashMap::new();
+    let mut sum = vec![0.0; mesh.node_count()];
+    let mut count = vec![0usize; mesh.node_count()];
+    for (e, v) in mesh.elements.iter().zip(values) {
+        let m = match cache.entry(e.kind) {
+            Entry::Occupied(m) => m.into_mut(),
+            Entry::Vacant(m) => m.insert(extrapolation_matrix(e.kind)?)
+        };
+        for (&n, x) in e.nodes.iter().zip(m.mul_vec(v)) {
+            sum[n] += x;
+            count[n] += 1;
+        }
+    }
+    Ok(sum.iter().zip(&count).map(|(s, &c)| if c > 0 { s / c as f64 } else { 0.0 }).collect())
+}
+
+// Strain and stress of a point from the engineering strain vector of the
+// analysis and the free thermal strain, completing the out-of-plane
+// components with the 3D law
+fn point_state(eps: &[f64], mode: PlaneMode, d: &DMatrix, d3: &DMatrix, thermal: f64) -> (SymTensor, SymTensor) {
+    let (exx, eyy, gxy) = (eps[0], eps[1], eps[2]);
+    let elastic = [exx - thermal, eyy - thermal, gxy];
+    let (ezz, s) = match mode {
+        PlaneMode::PlaneStress => {
+            // s_zz = 0
+            let ezz = thermal - (d3[(3, 0)] * elastic[0] + d3[(3, 1)] * elastic[1] + d3[(3, 2)] * gxy) / d3[(3, 3)];
+            let mut s = d.mul_vec(&elastic);
+            s.push(0.0);
+            (ezz, s)
+        },
+        PlaneMode::PlaneStrain => (0.0, d3.mul_vec(&[elastic[0], elastic[1], gxy, -thermal])),
+        PlaneMode::Axisymmetric => (eps[3], d3.mul_vec(&[elastic[0], elastic[1], gxy, eps[3] - thermal]))
+    };
+    (SymTensor::new(exx, eyy, ezz, gxy / 2.0), SymTensor::new(s[0], s[1], s[3], s[2]))
+}
+
+// Strains and stresses of an elastic solution at the integration points,
+// extrapolated and averaged to the nodes
+pub fn recover_stresses(problem: &ElasticityProblem, solution: &ElasticSolution) -> Result<StressField, String> {
+    let mesh = problem.mesh();
+    let mode = problem.mode();
+    let mut gauss = Vec::with_capacity(mesh.elements.len());
+    for e in &mesh.elements {
+        let material = problem.material(&e.region)?;
+        let (d, d3) = (material.d_matrix(mode)?, material.d_matrix(PlaneMode::Axisymmetric)?);
+        let coords = mesh.coords(&e.nodes);
+        let ue = element_displacements(&e.nodes, &solution.displacement);
+        let mut points = Vec::new();
+        for q in recovery_points(e.kind) {
+            let iso = shape::map(e.kind, &coords, &q.point)?;
+            let eps = b_matrix(&iso, mode).mul_vec(&ue);
+            let (strain, stress) = point_state(&eps, mode, &d, &d3, problem.thermal_strain(e, &iso)?);
+            let volume = q.weight * iso.det_j * problem.weight(&iso.x);
+            points.push(GaussPointState { position: iso.x, volume, strain, stress });
+        }
+        gauss.push(points);
+    }
+
+    let mut nodal = Vec::new();
+    for select in &[|g: &GaussPointState| g.strain.components(), |g: &GaussPointState| g.stress.components()] {
+        let mut components = Vec::new();
+        for c in 0..4 {
+            let values: Vec<Vec<f64>> = gauss.iter()
+                .map(|points| points.iter().map(|g| select(g)[c]).collect())
+                .collect();
+            components.push(average_to_nodes(mesh, &values)?);
+        }
+        nodal.push((0..mesh.node_count())
+            .map(|n| SymTensor::new(components[0][n], components[1][n], components[2][n], components[3][n]))
+            .collect::<Vec<_>>());
+    }
+    let nodal_stress = nodal.pop().unwrap();
+    let nodal_strain = nodal.pop().unwrap();
+    Ok(StressField { gauss, nodal_strain, nodal_stress })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::FRAC_PI_4;
+    use meshing;
+    use results::Results;
+    use solvers::LinearSolver;
+
+    #[test]
+    fn derived_quantities() {
+        let shear = SymTensor::new(0.0, 0.0, 0.0, 50.0);
+        assert_eq!(shear.principal(), [50.0, 0.0, -50.0]);
+        assert!((shear.principal_angle() - FRAC_PI_4).abs() < 1e-15);
+        assert!((shear.von_mises() - 50.0 * 3f64.sqrt()).abs() < 1e-12);
+        assert_eq!(shear.tresca(), 100.0);
+
+        let s = SymTensor::new(120.0, -30.0, 20.0, 40.0);
+        let p = s.principal();
+        assert!((p.iter().sum::<f64>() - 110.0).abs() < 1e-12);
+        // von Mises from the principal values
+        let vm = (0.5 * ((p[0] - p[1]).powi(2) + (p[1] - p[2]).powi(2) + (p[2] - p[0]).powi(2))).sqrt();
+        assert!((vm - s.von_mises()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn uniaxial_plane_strain() {
+        let (e, nu) = (200e3, 0.3);
+        for kind in [ElementKind::Tri3, ElementKind::Tri6, ElementKind::Quad4,
+                     ElementKind::Quad8, ElementKind::Quad9].iter() {
+            let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(4.0, 1.0), 4, 2, *kind, "steel");
+            let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStrain, 1.0);
+            p.set_material("steel", ElasticMaterial::Isotropic { young: e, poisson: nu });
+            p.add_support(Support::Roller { tag: "left".to_string(), axis: Axis::X });
+            p.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+            p.add_load(Load::Traction { tag: "right".to_string(), traction: Vec2(100.0, 0.0) });
+            let sol = p.solve(&LinearSolver::default()).unwrap();
+            let field = recover_stresses(&p, &sol).unwrap();
+            for s in &field.nodal_stress {
+                assert!((s.xx - 100.0).abs() < 1e-9 && s.yy.abs() < 1e-9 && s.xy.abs() < 1e-9, "{:?}", kind);
+                assert!((s.zz - nu * 100.0).abs() < 1e-9);
+            }
+            let vm = field.nodal(StressQuantity::VonMises).unwrap();
+            let expected = SymTensor::new(100.0, 0.0, nu * 100.0, 0.0).von_mises();
+            assert!(vm.iter().all(|v| (v - expected).abs() < 1e-9));
+            assert!(field.nodal_strain.iter().all(|e| e.zz == 0.0));
+        }
+    }
+
+    #[test]
+    fn result_fields() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 2, 1, ElementKind::Quad8, "steel");
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 1.0);
+        p.set_material("steel", ElasticMaterial::Isotropic { young: 200e3, poisson: 0.3 });
+        p.add_support(Support::Roller { tag: "left".to_string(), axis: Axis::X });
+        p.add_support(Support::Roller { tag: "bottom".to_string(), axis: Axis::Y });
+        p.add_load(Load::Traction { tag: "right".to_string(), traction: Vec2(100.0, 0.0) });
+        let field = recover_stresses(&p, &p.solve(&LinearSolver::default()).unwrap()).unwrap();
+        assert!(field.nodal(StressQuantity::Stress(4)).is_err());
+        assert!(field.element(StressQuantity::Principal(3)).is_err());
+        assert!(StressQuantity::Strain(7).name().is_err());
+
+        let mut results = Results::new(&mesh);
+        let step = results.add_step(1.0).unwrap();
+        for f in field.fields(&mesh).unwrap() {
+            results.add_field(step, f).unwrap();
+        }
+        let principal = results.field(step, "principal stress").unwrap();
+        assert_eq!(principal.components, ["S1", "S2", "S3", "principal angle"]);
+        let s1 = principal.values(0).unwrap();
+        assert!(s1.iter().all(|s| (s - 100.0).abs() < 1e-9));
+        let history = results.history("stress at integration points", "Sxx", &Vec2(1.2, 0.4), 0.0).unwrap();
+        assert!((history[0].1 - 100.0).abs() < 1e-9);
+        let vm = results.field(step, "von Mises").unwrap();
+        assert_eq!(vm.entity_count(), mesh.node_count());
+    }
+
+    #[test]
+    fn pure_bending_is_extrapolated_exactly() {
+        // u = k x y, v = -k (x^2 + nu y^2) / 2 gives s_xx = E k y in plane
+        // stress; the stress is linear so the recovery is exact
+        let (e, nu, k, t) = (70e3, 0.33, 1e-3, 0.5);
+        for kind in [ElementKind::Tri6, ElementKind::Quad8, ElementKind::Quad9].iter() {
+            let mesh = meshing::rectangle(Vec2(0.0, -1.0), Vec2(6.0, 2.0), 3, 2, *kind, "al");
+            let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, t);
+            p.set_material("al", ElasticMaterial::Isotropic { young: e, poisson: nu });
+            let displacement = mesh.nodes.iter()
+                .map(|x| Vec2(k * x.0 * x.1, -k * (x.0 * x.0 + nu * x.1 * x.1) / 2.0))
+                .collect();
+            let sol = ElasticSolution { displacement, reactions: Vec::new() };
+            let field = recover_stresses(&p, &sol).unwrap();
+
+            let sxx = field.nodal(StressQuantity::Stress(0)).unwrap();
+            let ezz = field.nodal(StressQuantity::Strain(2)).unwrap();
+            for (i, x) in mesh.nodes.iter().enumerate() {
+                assert!((sxx[i] - e * k * x.1).abs() < 1e-9, "{:?}", kind);
+                assert!((ezz[i] + nu * k * x.1).abs() < 1e-12);
+            }
+            // U = E k^2 I L / 2 with I = t h^3 / 12
+            let exact = e * k * k * (t * 8.0 / 12.0) * 6.0 / 2.0;
+            assert!((field.strain_energy() - exact).abs() < 1e-9 * exact);
+        }
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+use base_types::*;
+use meshing::Mesh;
+use solvers::{LinearSolver, PreparedSolver};
+use super::assembly::{Assembler, Symmetry};
+use super::heat::HeatBoundary;
+use super::scalar::{diffusion_element, mass_element, edge_load, edge_robin};
+
+// Time history scaling a boundary value
+#[derive(Debug, Clone)]
+pub enum Amplitude {
+    Constant,
+    // Piecewise linear (time, factor) table, held constant outside its range
+    Table(Vec<(f64, f64)>),
+    Function(fn(f64) -> f64)
+}
+
+impl Amplitude {
+    pub fn at(&self, t: f64) -> f64 {
+        match self {
+            Amplitude::Constant => 1.0,
+            Amplitude::Function(f) => f(t),
+            Amplitude::Table(table) => {
+                match table.iter().position(|p| p.0 > t) {
+                    None => table.last().map_or(1.0, |p| p.1),
+                    Some(0) => table[0].1,
+                    Some(i) => {
+                        let (a, b) = (&table[i - 1], &table[i]);
+                        a.1 + (b.1 - a.1) * (t - a.0) / (b.0 - a.0)
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeStepping {
+    Fixed { step: f64 },
+    // Step doubling: each step is repeated as two half steps and the size is
+    // adapted so that their difference stays below `tolerance` (in degrees)
+    Adaptive { initial: f64, min: f64, max: f64, tolerance: f64 }
+}
+
+#[derive(Debug, Clone)]
+pub struct HeatFrame {
+    pub time: f64,
+    pub temperature: Vec<f64>
+}
+
+#[derive(Debug, Clone)]
+pub struct TransientHeatSolution {
+    // One frame per requested output time
+    pub frames: Vec<HeatFrame>,
+    pub steps: usize,
+    pub rejected_steps: usize,
+    // Number of step matrices factored
+    pub factorizations: usize
+}
+
+// Step matrix C / dt + theta K of one step size, factored with the
+// prescribed dofs eliminated
+struct StepMatrix {
+    matrix: CsrMatrix,
+    // diagonal after the elimination, which scales the prescribed values
+    diagonal: Vec<f64>,
+    solver: PreparedSolver
+}
+
+// Assembled operators of C dT/dt + K T = f(t). The load is kept as separate
+// unit contributions so it can be rebuilt for any time. The prescribed dofs
+// do not change in time, so a step matrix is factored once and reused while
+// the step size stays the same; the prescribed values enter through the
+// right-hand side only.
+struct Operators<'b> {
+    k: CsrMatrix,
+    c: CsrMatrix,
+    source: Vec<f64>,
+    loads: Vec<(Vec<f64>, &'b Amplitude)>,
+    fixed: Vec<(Vec<usize>, f64, &'b Amplitude)>,
+    // the step matrices used last with the bits of their step size, most
+    // recent first
+    step_matrices: Vec<(u64, StepMatrix)>,
+    factorizations: usize
+}
+
+// Enough for a full and a half step at the current and the previous size
+const CACHED_STEP_MATRICES: usize = 4;
+
+impl<'b> Operators<'b> {
+    fn load(&self, t: f64) -> Vec<f64> {
+        let mut f = self.source.clone();
+        for (unit, amplitude) in &self.loads {
+            let a = amplitude.at(t);
+            for (fi, ui) in f.iter_mut().zip(unit) {
+                *fi += a * ui;
+            }
+        }
+        f
+    }
+
+    fn fixed(&self, t: f64) -> Vec<(usize, f64)> {
+        self.fixed.iter()
+            .flat_map(|(nodes, value, amplitude)| {
+                let v = value * amplitude.at(t);
+                nodes.iter().map(move |&n| (n, v))
+            })
+            .collect()
+    }
+
+    fn step_matrix(&mut self, theta: f64, dt: f64, solver: &LinearSolver) -> Result<&StepMatrix, String> {
+        match self.step_matrices.iter().position(|m| m.0 == dt.to_bits()) {
+            Some(i) => {
+                let used = self.step_matrices.remove(i);
+                self.step_matrices.insert(0, used);
+            },
+            None => {
+                let matrix = self.c.scaled_sum(1.0 / dt, &self.k, theta);
+                let mut eliminated = matrix.clone();
+                let fixed: Vec<(usize, f64)> = self.fixed(0.0).into_iter().map(|(i, _)| (i, 0.0)).collect();
+                eliminated.apply_dirichlet(&fixed, &mut vec![0.0; matrix.nrows()]);
+                let step = StepMatrix { diagonal: eliminated.diagonal(), solver: solver.prepare(&eliminated)?, matrix };
+                self.step_matrices.truncate(CACHED_STEP_MATRICES - 1);
+                self.step_matrices.insert(0, (dt.to_bits(), step));
+                self.factorizations += 1;
+            }
+        }
+        Ok(&self.step_matrices[0].1)
+    }
+
+    // One theta step from t to t + dt:
+    // (C / dt + theta K) T1 = (C / dt - (1 - theta) K) T0 + theta f1 + (1 - theta) f0
+    fn step(&mut self, theta: f64, temperature: &[f64], t: f64, dt: f64, solver: &LinearSolver)
+        -> Result<Vec<f64>, String> {
+        let ct = self.c.mul_vec(temperature);
+        let kt = self.k.mul_vec(temperature);
+        let (f0, f1) = (self.load(t), self.load(t + dt));
+        let mut rhs: Vec<f64> = (0..temperature.len())
+            .map(|i| ct[i] / dt - (1.0 - theta) * kt[i] + theta * f1[i] + (1.0 - theta) * f0[i])
+            .collect();
+        let mut prescribed = vec![None; rhs.len()];
+        for (i, value) in self.fixed(t + dt) {
+            prescribed[i] = Some(value);
+        }
+        let step = self.step_matrix(theta, dt, solver)?;
+        // move the prescribed values to the right-hand side
+        let g: Vec<f64> = prescribed.iter().map(|v| v.unwrap_or(0.0)).collect();
+        let ag = step.matrix.mul_vec(&g);
+        for (i, value) in prescribed.iter().enumerate() {
+            rhs[i] = match value {
+                Some(v) => step.diagonal[i] * v,
+                None => rhs[i] - ag[i]
+            };
+        }
+        step.solver.solve(&rhs)
+    }
+}
+
+// Transient heat conduction rho c dT/dt - div(k grad T) = q, integrated with
+// the generalized trapezoidal (theta) rule starting from t = 0.
+pub struct TransientHeatProblem<'a> {
+    mesh: &'a Mesh,
+    symmetry: Symmetry,
+    conductivity: HashMap<String, f64>,
+    capacity: HashMap<String, f64>,
+    source: HashMap<String, f64>,
+    boundaries: Vec<(HeatBoundary, Amplitude)>,
+    initial: Vec<f64>,
+    theta: f64,
+    stepping: TimeStepping
+}
+
+impl<'a> TransientHeatProblem<'a> {
+    pub fn new(mesh: &'a Mesh) -> TransientHeatProblem<'a> {
+        TransientHeatProblem {
+            mesh,
+            symmetry: Symmetry::Planar,
+            conductivity: HashMap::new(),
+            capacity: HashMap::new(),
+            source: HashMap::new(),
+            boundaries: Vec::new(),
+            initial: vec![0.0; mesh.node_count()],
+            theta: 0.5,
+            stepping: TimeStepping::Fixed { step: 1.0 }
+        }
+    }
+
+    pub fn mesh(&self) -> &'a Mesh { self.mesh }
+
+    pub fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.symmetry = symmetry;
+    }
+
+    pub fn set_conductivity(&mut self, region: &str, k: f64) {
+        self.conductivity.insert(region.to_string(), k);
+    }
+
+    pub fn set_heat_capacity(&mut self, region: &str, density: f64, specific_heat: f64) {
+        self.capacity.insert(region.to_string(), density * specific_heat);
+    }
+
+    pub fn set_heat_source(&mut self, region: &str, q: f64) {
+        self.source.insert(region.to_string(), q);
+    }
+
+    // The boundary value (temperature, flux or ambient temperature) is
+    // multiplied by the amplitude at every instant
+    pub fn add_boundary(&mut self, bc: HeatBoundary, amplitude: Amplitude) {
+        self.boundaries.push((bc, amplitude));
+    }
+
+    pub fn set_initial_temperature(&mut self, temperature: Vec<f64>) {
+        self.initial = temperature;
+    }
+
+    pub fn set_uniform_initial_temperature(&mut self, t: f64) {
+        self.initial = vec![t; self.mesh.node_count()];
+    }
+
+    // 0 is forward Euler, 0.5 Crank-Nicolson and 1 backward Euler
+    pub fn set_theta(&mut self, theta: f64) {
+        self.theta = theta;
+    }
+
+    pub fn set_time_stepping(&mut self, stepping: TimeStepping) -> Result<(), String> {
+        match stepping {
+            TimeStepping::Fixed { step } if step <= 0.0 || !step.is_finite() =>
+                return Err(format!("time step must be positive, got {}", step)),
+            TimeStepping::Adaptive { initial, min, max, tolerance } => {
+                if initial <= 0.0 || min <= 0.0 || min > max {
+                    return Err(format!("adaptive steps need 0 < min <= max and a positive initial step, \
+                                        got min {}, max {} and initial {}", min, max, initial));
+                }
+                if tolerance <= 0.0 || !tolerance.is_finite() {
+                    return Err(format!("adaptive tolerance must be positive, got {}", tolerance));
+                }
+            },
+            _ => ()
+        }
+        self.stepping = stepping;
+        Ok(())
+    }
+
+    fn region_value(map: &HashMap<String, f64>, region: &str, what: &str) -> Result<f64, String> {
+        map.get(region).cloned()
+            .ok_or_else(|| format!("no {} given for region '{}'", what, region))
+    }
+
+    fn assemble(&self) -> Result<Operators<'_>, String> {
+        let mesh = self.mesh;
+        self.symmetry.check(mesh)?;
+        let n = mesh.node_count();
+        let mut stiffness = Assembler::new(n, 1);
+        let mut capacity = Assembler::new(n, 1);
+        for e in &mesh.elements {
+            let coords = mesh.coords(&e.nodes);
+            let k = Self::region_value(&self.conductivity, &e.region, "conductivity")?;
+            let rho_c = Self::region_value(&self.capacity, &e.region, "heat capacity")?;
+            let q = self.source.get(&e.region).cloned().unwrap_or(0.0);
+            let (ke, fe) = diffusion_element(e.kind, &coords, k, q, self.symmetry)?;
+            stiffness.add_matrix(&e.nodes, &ke);
+            stiffness.add_vector(&e.nodes, &fe);
+            capacity.add_matrix(&e.nodes, &mass_element(e.kind, &coords, rho_c, self.symmetry)?);
+        }
+
+        let mut loads = Vec::new();
+        let mut fixed = Vec::new();
+        for (bc, amplitude) in &self.boundaries {
+            let mut unit = vec![0.0; n];
+            match bc {
+                HeatBoundary::Temperature { tag, value } => {
+                    mesh.check_tag(tag)?;
+                    fixed.push((mesh.boundary_nodes(tag), *value, amplitude));
+                    continue;
+                },
+                HeatBoundary::HeatFlux { tag, flux } => {
+                    mesh.check_tag(tag)?;
+                    for edge in mesh.boundary_with_tag(tag) {
+                        let fe = edge_load(edge.kind, &mesh.coords(&edge.nodes), *flux, self.symmetry)?;
+                        for (&i, v) in edge.nodes.iter().zip(&fe) {
+                            unit[i] += v;
+                        }
+                    }
+                },
+                HeatBoundary::Convection { tag, film_coefficient, ambient } => {
+                    mesh.check_tag(tag)?;
+                    for edge in mesh.boundary_with_tag(tag) {
+                        let (ke, fe) = edge_robin(edge.kind, &mesh.coords(&edge.nodes),
+                                                  *film_coefficient, *ambient, self.symmetry)?;
+                        stiffness.add_matrix(&edge.nodes, &ke);
+                        for (&i, v) in edge.nodes.iter().zip(&fe) {
+                            unit[i] += v;
+                        }
+                    }
+                }
+            }
+            loads.push((unit, amplitude));
+        }
+
+        let (k, source) = stiffness.finish();
+        let (c, _) = capacity.finish();
+        Ok(Operators { k, c, source, loads, fixed, step_matrices: Vec::new(), factorizations: 0 })
+    }
+
+    // Integrates up to the last of `output_times` and returns a frame at each
+    // of them. Steps are shortened to land exactly on the output times.
+    pub fn solve(&self, output_times: &[f64], solver: &LinearSolver)
+        -> Result<TransientHeatSolution, String> {
+        if self.initial.len() != self.mesh.node_count() {
+            return Err(format!("initial temperature has {} values for {} nodes",
+                               self.initial.len(), self.mesh.node_count()));
+        }
+        if self.theta < 0.0 || self.theta > 1.0 {
+            return Err(format!("theta must lie in [0, 1], got {}", self.theta));
+        }
+        let mut outputs = output_times.to_vec();
+        if outputs.iter().any(|t| *t < 0.0 || !t.is_finite()) {
+            return Err("output times must be finite and non-negative".to_string());
+        }
+        outputs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut ops = self.assemble()?;
+        let mut temperature = self.initial.clone();
+        let mut t = 0.0;
+        let mut solution = TransientHeatSolution { frames: Vec::new(), steps: 0, rejected_steps: 0, factorizations: 0 };
+        let (mut dt, adaptive) = match self.stepping {
+            TimeStepping::Fixed { step } => (step, None),
+            TimeStepping::Adaptive { initial, min, max, tolerance } =>
+                (initial, Some((initial, min, max, tolerance)))
+        };
+        // order of accuracy, used to scale adaptive steps
+        let order = if (self.theta - 0.5).abs() < 1e-12 { 2.0 } else { 1.0 };
+
+        for target in outputs {
+            while target - t > 1e-12 * target.max(1.0) {
+                // steps within round-off of the remaining time keep their size
+                let truncated = target - t < dt * (1.0 - 1e-9);
+                let h = if truncated { target - t } else { dt };
+                match adaptive {
+                    None => {
+                        temperature = ops.step(self.theta, &temperature, t, h, solver)?;
+                        t += h;
+                        solution.steps += 1;
+                    },
+                    Some((initial, min, max, tolerance)) => {
+                        // adapted sizes are rounded down to powers of sqrt(2)
+                        // times the initial one, so that their step matrices
+                        // get reused
+                        let level = |h: f64| initial * ((2.0 * (h / initial).log2()).floor() / 2.0).exp2();
+                        let full = ops.step(self.theta, &temperature, t, h, solver)?;
+                        let half = ops.step(self.theta, &temperature, t, h / 2.0, solver)?;
+                        let half = ops.step(self.theta, &half, t + h / 2.0, h / 2.0, solver)?;
+                        let error = full.iter().zip(&half).fold(0.0f64, |m, (a, b)| m.max((a - b).abs()));
+                        let factor = if error > 0.0 {
+                            (0.9 * (tolerance / error).powf(1.0 / (order + 1.0))).clamp(0.2, 5.0)
+                        } else {
+                            5.0
+                        };
+                        if error <= tolerance || h <= min {
+                            temperature = half;
+                            t += h;
+                            solution.steps += 1;
+                            if !truncated || factor < 1.0 {
+                                dt = level(h * factor).max(min).min(max);
+                            }
+                        } else {
+                            solution.rejected_steps += 1;
+                            dt = level(h * factor).max(min).min(max);
+                        }
+                    }
+                }
+            }
+            solution.frames.push(HeatFrame { time: target, temperature: temperature.clone() });
+        }
+        solution.factorizations = ops.factorizations;
+        Ok(solution)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::PI;
+    use fem::shape::ElementKind;
+    use meshing;
+
+    #[test]
+    fn amplitude_table() {
+        let a = Amplitude::Table(vec![(0.0, 0.0), (2.0, 1.0), (4.0, 0.0)]);
+        assert_eq!(a.at(-1.0), 0.0);
+        assert_eq!(a.at(1.0), 0.5);
+        assert_eq!(a.at(3.0), 0.5);
+        assert_eq!(a.at(5.0), 0.0);
+        assert_eq!(Amplitude::Function(|t| 2.0 * t).at(3.0), 6.0);
+    }
+
+    #[test]
+    fn uniform_heating() {
+        // insulated body: T = T0 + q t / rho c for any theta
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 1.0), 2, 2, ElementKind::Tri6, "cu");
+        for theta in [0.0, 0.5, 1.0].iter() {
+            let mut p = TransientHeatProblem::new(&mesh);
+            p.set_conductivity("cu", 400.0);
+            p.set_heat_capacity("cu", 8900.0, 385.0);
+            p.set_heat_source("cu", 1e6);
+            p.set_uniform_initial_temperature(20.0);
+            p.set_theta(*theta);
+            p.set_time_stepping(TimeStepping::Fixed { step: 0.7 }).unwrap();
+            let sol = p.solve(&[0.0, 1.0, 5.0], &LinearSolver::default()).unwrap();
+            assert_eq!(sol.frames.len(), 3);
+            for frame in &sol.frames {
+                let exact = 20.0 + 1e6 * frame.time / (8900.0 * 385.0);
+                assert!(frame.temperature.iter().all(|t| (t - exact).abs() < 1e-9));
+            }
+        }
+    }
+
+    fn decay_problem(mesh: &Mesh, stepping: TimeStepping) -> TransientHeatProblem<'_> {
+        let mut p = TransientHeatProblem::new(mesh);
+        p.set_conductivity("slab", 1.0);
+        p.set_heat_capacity("slab", 1.0, 1.0);
+        p.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 0.0 },
+                       Amplitude::Constant);
+        p.add_boundary(HeatBoundary::Temperature { tag: "right".to_string(), value: 0.0 },
+                       Amplitude::Constant);
+        p.set_initial_temperature(mesh.nodes.iter().map(|x| (PI * x.0).sin()).collect());
+        p.set_time_stepping(stepping).unwrap();
+        p
+    }
+
+    #[test]
+    fn sine_decay() {
+        // T = exp(-pi^2 t) sin(pi x)
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 0.1), 10, 1, ElementKind::Quad8, "slab");
+        let fixed = decay_problem(&mesh, TimeStepping::Fixed { step: 0.005 })
+            .solve(&[0.05, 0.2], &LinearSolver::default()).unwrap();
+        let adaptive = decay_problem(&mesh, TimeStepping::Adaptive {
+            initial: 1e-3, min: 1e-6, max: 0.1, tolerance: 1e-5 })
+            .solve(&[0.05, 0.2], &LinearSolver::default()).unwrap();
+        assert_eq!(fixed.steps, 40);
+        assert_eq!(fixed.factorizations, 1);
+        assert!(adaptive.steps < fixed.steps);
+        // one or two per step size level and output time, however many steps
+        assert!(adaptive.factorizations <= 20, "{}", adaptive.factorizations);
+        let long = decay_problem(&mesh, TimeStepping::Adaptive {
+            initial: 1e-3, min: 1e-6, max: 0.01, tolerance: 1e-5 })
+            .solve(&[0.05, 0.2, 1.0], &LinearSolver::default()).unwrap();
+        assert!(long.steps > 90);
+        assert!(long.factorizations <= 20, "{}", long.factorizations);
+        for sol in [fixed, adaptive].iter() {
+            for frame in &sol.frames {
+                for (x, t) in mesh.nodes.iter().zip(&frame.temperature) {
+                    let exact = (-PI * PI * frame.time).exp() * (PI * x.0).sin();
+                    assert!((t - exact).abs() < 1e-4, "{} vs {}", t, exact);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_time_stepping() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 0.1), 2, 1, ElementKind::Quad4, "slab");
+        let mut p = TransientHeatProblem::new(&mesh);
+        assert!(p.set_time_stepping(TimeStepping::Fixed { step: 0.0 }).is_err());
+        assert!(p.set_time_stepping(TimeStepping::Adaptive { initial: 0.1, min: 0.5, max: 0.2, tolerance: 1e-3 })
+            .is_err());
+        assert!(p.set_time_stepping(TimeStepping::Adaptive { initial: 0.1, min: 0.01, max: 1.0, tolerance: 0.0 })
+            .is_err());
+        assert!(p.set_time_stepping(TimeStepping::Adaptive { initial: 0.1, min: 0.01, max: 1.0, tolerance: 1e-3 })
+            .is_ok());
+    }
+
+    #[test]
+    fn ramped_boundary_temperature() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 0.2), 4, 1, ElementKind::Quad4, "slab");
+        let mut p = TransientHeatProblem::new(&mesh);
+        p.set_conductivity("slab", 1.0);
+        p.set_heat_capacity("slab", 1.0, 1.0);
+        p.add_boundary(HeatBoundary::Temperature { tag: "left".to_string(), value: 100.0 },
+                       Amplitude::Table(vec![(0.0, 0.0), (1.0, 1.0)]));
+        p.add_boundary(HeatBoundary::Convection {
+            tag: "right".to_string(), film_coefficient: 5.0, ambient: 20.0 }, Amplitude::Constant);
+        p.set_theta(1.0);
+        p.set_time_stepping(TimeStepping::Fixed { step: 0.1 }).unwrap();
+        let sol = p.solve(&[0.25, 0.5, 2.0, 50.0], &LinearSolver::default()).unwrap();
+        let left = mesh.boundary_nodes("left");
+        for (frame, expected) in sol.frames.iter().zip(&[25.0, 50.0, 100.0, 100.0]) {
+            assert!(left.iter().all(|&n| (frame.temperature[n] - expected).abs() < 1e-9));
+        }
+        // steady state: T(1) = (100 k + h L T_amb) / (k + h L)
+        let right = mesh.boundary_nodes("right")[0];
+        let last = sol.frames.last().unwrap();
+        assert!((last.temperature[right] - (100.0 + 5.0 * 20.0) / 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn missing_capacity() {
+        let mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 1.0), 1, 1, ElementKind::Quad4, "cu");
+        let mut p = TransientHeatProblem::new(&mesh);
+        p.set_conductivity("cu", 1.0);
+        assert!(p.solve(&[1.0], &LinearSolver::default()).is_err());
+        // a misspelt tag is an error as well
+        p.set_heat_capacity("cu", 1.0, 1.0);
+        p.add_boundary(HeatBoundary::HeatFlux { tag: "rigth".to_string(), flux: 1.0 }, Amplitude::Constant);
+        assert!(p.solve(&[1.0], &LinearSolver::default()).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use std::ops::{Add, Mul, Sub};
+use std::ops::{AddAssign, SubAssign, MulAssign};
+use std::ops::{Index, IndexMut};
+
+// Dense, row-major matrix of arbitrary size (element matrices, small systems).
+#[derive(Debug, PartialEq, Clone)]
+pub struct DMatrix {
+    nrows: usize,
+    ncols: usize,
+    data: Vec<f64>
+}
+
+impl DMatrix {
+    pub fn zeros(nrows: usize, ncols: usize) -> DMatrix {
+        DMatrix { nrows, ncols, data: vec![0.0; nrows * ncols] }
+    }
+
+    pub fn identity(n: usize) -> DMatrix {
+        let mut res = DMatrix::zeros(n, n);
+        for i in 0..n {
+            res[(i, i)] = 1.0;
+        }
+        res
+    }
+
+    pub fn from_vec(nrows: usize, ncols: usize, data: Vec<f64>) -> DMatrix {
+        assert_eq!(data.len(), nrows * ncols);
+        DMatrix { nrows, ncols, data }
+    }
+
+    pub fn from_rows(rows: &[&[f64]]) -> DMatrix {
+        let ncols = rows.first().map_or(0, |r| r.len());
+        assert!(rows.iter().all(|r| r.len() == ncols), "rows of different length");
+        DMatrix { nrows: rows.len(), ncols, data: rows.concat() }
+    }
+
+    pub fn from_diagonal(diag: &[f64]) -> DMatrix {
+        let mut res = DMatrix::zeros(diag.len(), diag.len());
+        for (i, d) in diag.iter().enumerate() {
+            res[(i, i)] = *d;
+        }
+        res
+    }
+
+    pub fn nrows(&self) -> usize { self.nrows }
+    pub fn ncols(&self) -> usize { self.ncols }
+    pub fn is_square(&self) -> bool { self.nrows == self.ncols }
+    pub fn data(&self) -> &[f64] { &self.data }
+
+    pub fn row(&self, i: usize) -> &[f64] {
+        &self.data[i * self.ncols..(i + 1) * self.ncols]
+    }
+
+    pub fn column(&self, j: usize) -> Vec<f64> {
+        (0..self.nrows).map(|i| self[(i, j)]).collect()
+    }
+
+    pub fn transpose(&self) -> DMatrix {
+        let mut res = DMatrix::zeros(self.ncols, self.nrows);
+        for i in 0..self.nrows {
+            for j in 0..self.ncols {
+                res[(j, i)] = self[(i, j)];
+            }
+        }
+        res
+    }
+
+    pub fn mul_vec(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.ncols);
+        (0..self.nrows)
+            .map(|i| self.row(i).iter().zip(x).map(|(a, b)| a * b).sum())
+            .collect()
+    }
+
+    // self^T * x
+    pub fn tr_mul_vec(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.nrows);
+        let mut res = vec![0.0; self.ncols];
+        for (i, xi) in x.iter().enumerate() {
+            for (r, a) in res.iter_mut().zip(self.row(i)) {
+                *r += a * xi;
+            }
+        }
+        res
+    }
+
+    pub fn matmul(&self, other: &DMatrix) -> DMatrix {
+        assert_eq!(self.ncols, other.nrows, "incompatible matrix product");
+        let mut res = DMatrix::zeros(self.nrows, other.ncols);
+        for i in 0..self.nrows {
+            for k in 0..self.ncols {
+                let a = self[(i, k)];
+                if a == 0.0 {
+                    continue;
+                }
+                for j in 0..other.ncols {
+                    res[(i, j)] += a * other[(k, j)];
+                }
+            }
+        }
+        res
+    }
+
+    // self^T * other, without forming the transpose
+    pub fn tr_matmul(&self, other: &DMatrix) -> DMatrix {
+        assert_eq!(self.nrows, other.nrows, "incompatible matrix product");
+        let mut res = DMatrix::zeros(self.ncols, other.ncols);
+        for k in 0..self.nrows {
+            for i in 0..self.ncols {
+                let a = self[(k, i)];
+                if a == 0.0 {
+                    continue;
+                }
+                for j in 0..other.ncols {
+                    res[(i, j)] += a * other[(k, j)];
+                }
+            }
+        }
+        res
+    }
+
+    // B^T D B scaled by `weight`, accumulated into self. The typical element
+    // stiffness contribution of a single integration point.
+    pub fn add_btdb(&mut self, b: &DMatrix, d: &DMatrix, weight: f64) {
+        let db = d.matmul(b);
+        let btdb = b.tr_matmul(&db);
+        *self += btdb * weight;
+    }
+
+    pub fn trace(&self) -> f64 {
+        (0..self.nrows.min(self.ncols)).map(|i| self[(i, i)]).sum()
+    }
+
+    pub fn lu(&self) -> Result<Lu, String> {
+        Lu::new(self)
+    }
+
+    pub fn cholesky(&self) -> Result<Cholesky, String> {
+        Cholesky::new(self)
+    }
+
+    pub fn determinant(&self) -> f64 {
+        assert!(self.is_square(), "determinant of a non-square matrix");
+        self.lu().map_or(0.0, |lu| lu.determinant())
+    }
+
+    pub fn inverse(&self) -> Result<DMatrix, String> {
+        let lu = self.lu()?;
+        let n = self.nrows;
+        let mut res = DMatrix::zeros(n, n);
+        let mut e = vec![0.0; n];
+        for j in 0..n {
+            e.iter_mut().for_each(|v| *v = 0.0);
+            e[j] = 1.0;
+            for (i, v) in lu.solve(&e).into_iter().enumerate() {
+                res[(i, j)] = v;
+            }
+        }
+        Ok(res)
+    }
+
+    pub fn solve(&self, b: &[f64]) -> Result<Vec<f64>, String> {
+        Ok(self.lu()?.solve(b))
+    }
+
+    // Eigen decomposition of a symmetric matrix by cyclic Jacobi rotations.
+    // Returns the eigenvalues in ascending order and the matching eigenvectors
+    // as the columns of the matrix.
+    pub fn symmetric_eigen(&self) -> (Vec<f64>, DMatrix) {
+        assert!(self.is_square(), "eigen decomposition of a non-square matrix");
+        let n = self.nrows;
+        let mut a = self.clone();
+        let mut v = DMatrix::identity(n);
+        for _ in 0..100 {
+            let mut off = 0.0;
+            for i in 0..n {
+                for j in i + 1..n {
+                    off += a[(i, j)] * a[(i, j)];
+                }
+            }
+            let total = off + (0..n).map(|i| a[(i, i)] * a[(i, i)]).sum::<f64>();
+            if off <= 1e-30 * total {
+                break;
+            }
+            for p in 0..n {
+                for q in p + 1..n {
+                    let apq = a[(p, q)];
+                    if apq == 0.0 {
+                        continue;
+                    }
+                    let theta = (a[(q, q)] - a[(p, p)]) / (2.0 * apq);
+                    let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                    let c = 1.0 / (t * t + 1.0).sqrt();
+                    let s = t * c;
+                    for k in 0..n {
+                        let (akp, akq) = (a[(k, p)], a[(k, q)]);
+                        a[(k, p)] = c * akp - s * akq;
+                        a[(k, q)] = s * akp + c * akq;
+                    }
+                    for k in 0..n {
+                        let (apk, aqk) = (a[(p, k)], a[(q, k)]);
+                        a[(p, k)] = c * apk - s * aqk;
+                        a[(q, k)] = s * apk + c * aqk;
+                    }
+                    for k in 0..n {
+                        let (vkp, vkq) = (v[(k, p)], v[(k, q)]);
+                        v[(k, p)] = c * vkp - s * vkq;
+                        v[(k, q)] = s * vkp + c * vkq;
+                    }
+                }
+            }
+        }
+
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&i, &j| a[(i, i)].total_cmp(&a[(j, j)]));
+        let values = order.iter().map(|&i| a[(i, i)]).collect();
+        let mut vectors = DMatrix::zeros(n, n);
+        for (col, &i) in order.iter().enumerate() {
+            for k in 0..n {
+                vectors[(k, col)] = v[(k, i)];
+            }
+        }
+        (values, vectors)
+    }
+}
+
+impl Index<(usize, usize)> for DMatrix {
+    type Output = f64;
+    fn index(&self, (i, j): (usize, usize)) -> &f64 {
+        debug_assert!(i < self.nrows && j < self.ncols);
+        &self.data[i * self.ncols + j]
+    }
+}
+
+impl IndexMut<(usize, usize)> for DMatrix {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut f64 {
+        debug_assert!(i < self.nrows && j < self.ncols);
+        &mut self.data[i * self.ncols + j]
+    }
+}
+
+macro_rules! op_dmat {
+    ($tr: ident, $fn: ident, $op: tt, $tr_as: ident, $fn_as: ident, $op_as: tt) => {
+        impl $tr for DMatrix {
+            type Output = DMatrix;
+            fn $fn(mut self, other: DMatrix) -> DMatrix {
+                self $op_as other;
+                self
+            }
+        }
+
+        impl $tr_as for DMatrix {
+            fn $fn_as(&mut self, other: DMatrix) {
+                assert!(self.nrows == other.nrows && self.ncols == other.ncols,
+                        "matrix dimensions differ");
+                self.data.iter_mut().zip(other.data).for_each(|(a, b)| *a = *a $op b);
+            }
+        }
+    };
+}
+
+op_dmat!(Add, add, +, AddAssign, add_assign, +=);
+op_dmat!(Sub, sub, -, SubAssign, sub_assign, -=);
+
+impl Mul<f64> for DMatrix {
+    type Output = DMatrix;
+    fn mul(mut self, other: f64) -> DMatrix {
+        self *= other;
+        self
+    }
+}
+
+impl MulAssign<f64> for DMatrix {
+    fn mul_assign(&mut self, other: f64) {
+        self.data.iter_mut().for_each(|a| *a *= other);
+    }
+}
+
+impl Mul for &DMatrix {
+    type Output = DMatrix;
+    fn mul(self, other: &DMatrix) -> DMatrix {
+        self.matmul(other)
+    }
+}
+
+impl Mul for DMatrix {
+    type Output = DMatrix;
+    fn mul(self, other: DMatrix) -> DMatrix {
+        self.matmul(&other)
+    }
+}
+
+// LU decomposition with partial pivoting, P A = L U.
+#[derive(Debug, Clone)]
+pub struct Lu {
+    lu: DMatrix,
+    perm: Vec<usize>,
+    sign: f64
+}
+
+impl Lu {
+    pub fn new(a: &DMatrix) -> Result<Lu, String> {
+        if !a.is_square() {
+            return Err(format!("LU: matrix is not square ({}x{})", a.nrows, a.ncols));
+        }
+        if a.data.iter().any(|v| !v.is_finite()) {
+            return Err("LU: matrix has non-finite entries".to_string());
+        }
+        let n = a.nrows;
+        let mut lu = a.clone();
+        let mut perm: Vec<usize> = (0..n).collect();
+        let mut sign = 1.0;
+        let scale = a.data.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+
+        for k in 0..n {
+            let p = (k..n).max_by(|&i, &j| lu[(i, k)].abs().total_cmp(&lu[(j, k)].abs())).unwrap();
+            if lu[(p, k)].abs() <= 1e-14 * scale || scale == 0.0 {
+                return Err(format!("LU: matrix is singular (column {})", k));
+            }
+            if p != k {
+                for j in 0..n {
+                    lu.data.swap(k * n + j, p * n + j);
+                }
+                perm.swap(k, p);
+                sign = -sign;
+            }
+            let pivot = lu[(k, k)];
+            for i in k + 1..n {
+                let f = lu[(i, k)] / pivot;
+                lu[(i, k)] = f;
+                for j in k + 1..n {
+                    let v = lu[(k, j)];
+                    lu[(i, j)] -= f * v;
+                }
+            }
+        }
+        Ok(Lu { lu, perm, sign })
+    }
+
+    pub fn determinant(&self) -> f64 {
+        (0..self.lu.nrows).map(|i| self.lu[(i, i)]).product::<f64>() * self.sign
+    }
+
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.lu.nrows;
+        assert_eq!(b.len(), n);
+        let mut x: Vec<f64> = self.perm.iter().map(|&p| b[p]).collect();
+        for i in 0..n {
+            let s: f64 = (0..i).map(|j| self.lu[(i, j)] * x[j]).sum();
+            x[i] -= s;
+        }
+        for i in (0..n).rev() {
+            let s: f64 = (i + 1..n).map(|j| self.lu[(i, j)] * x[j]).sum();
+            x[i] = (x[i] - s) / self.lu[(i, i)];
+        }
+        x
+    }
+}
+
+// Cholesky decomposition A = L L^T of a symmetric positive definite matrix.
+#[derive(Debug, Clone)]
+pub struct Cholesky {
+    l: DMatrix
+}
+
+impl Cholesky {
+    pub fn new(a: &DMatrix) -> Result<Cholesky, String> {
+        if !a.is_square() {
+            return Err(format!("Cholesky: matrix is not square ({}x{})", a.nrows, a.ncols));
+        }
+        let n = a.nrows;
+        let mut l = DMatrix::zeros(n, n);
+        for j in 0..n {
+            let d = a[(j, j)] - (0..j).map(|k| l[(j, k)] * l[(j, k)]).sum::<f64>();
+            if d <= 0.0 {
+                return Err(format!("Cholesky: matrix is not positive definite (row {})", j));
+            }
+            l[(j, j)] = d.sqrt();
+            for i in j + 1..n {
+                let s: f64 = (0..j).map(|k| l[(i, k)] * l[(j, k)]).sum();
+                l[(i, j)] = (a[(i, j)] - s) / l[(j, j)];
+            }
+        }
+        Ok(Cholesky { l })
+    }
+
+    pub fn l(&self) -> &DMatrix { &self.l }
+
+    pub fn determinant(&self) -> f64 {
+        (0..self.l.nrows).map(|i| self.l[(i, i)] * self.l[(i, i)]).product()
+    }
+
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.l.nrows;
+        assert_eq!(b.len(), n);
+        let mut x = b.to_vec();
+        for i in 0..n {
+            let s: f64 = (0..i).map(|j| self.l[(i, j)] * x[j]).sum();
+            x[i] = (x[i] - s) / self.l[(i, i)];
+        }
+        for i in (0..n).rev() {
+            let s: f64 = (i + 1..n).map(|j| self.l[(j, i)] * x[j]).sum();
+            x[i] = (x[i] - s) / self.l[(i, i)];
+        }
+        x
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: &DMatrix, b: &DMatrix) -> bool {
+        a.nrows() == b.nrows() && a.ncols() == b.ncols() &&
+            a.data().iter().zip(b.data()).all(|(x, y)| (x - y).abs() < 1e-12)
+    }
+
+    #[test]
+    fn product_and_transpose() {
+        let a = DMatrix::from_rows(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]);
+        let b = DMatrix::from_rows(&[&[1.0, 0.0], &[0.0, 1.0], &[1.0, 1.0]]);
+        let ab = &a * &b;
+        assert_eq!(ab, DMatrix::from_rows(&[&[4.0, 5.0], &[10.0, 11.0]]));
+        assert_eq!(a.transpose().transpose(), a);
+        assert_eq!(a.tr_matmul(&a), a.transpose().matmul(&a));
+        assert_eq!(a.mul_vec(&[1.0, 1.0, 1.0]), vec![6.0, 15.0]);
+        assert_eq!(a.tr_mul_vec(&[1.0, 1.0]), vec![5.0, 7.0, 9.0]);
+    }
+
+    #[test]
+    fn lu_inverse_determinant() {
+        let a = DMatrix::from_rows(&[&[0.0, 2.0, 1.0], &[1.0, 1.0, 0.0], &[3.0, 0.0, 1.0]]);
+        assert!((a.determinant() - (-5.0)).abs() < 1e-12);
+        let inv = a.inverse().unwrap();
+        assert!(close(&(&a * &inv), &DMatrix::identity(3)));
+        let x = a.solve(&[3.0, 2.0, 4.0]).unwrap();
+        assert!(close(&DMatrix::from_vec(3, 1, x), &DMatrix::from_vec(3, 1, vec![1.0, 1.0, 1.0])));
+
+        let singular = DMatrix::from_rows(&[&[1.0, 2.0], &[2.0, 4.0]]);
+        assert!(singular.inverse().is_err());
+        assert_eq!(singular.determinant(), 0.0);
+        assert!(DMatrix::from_rows(&[&[1.0, f64::NAN], &[0.0, 1.0]]).lu().is_err());
+    }
+
+    #[test]
+    fn cholesky() {
+        let a = DMatrix::from_rows(&[&[4.0, 2.0, 0.0], &[2.0, 5.0, 1.0], &[0.0, 1.0, 3.0]]);
+        let c = a.cholesky().unwrap();
+        assert!(close(&c.l().matmul(&c.l().transpose()), &a));
+        assert!((c.determinant() - a.determinant()).abs() < 1e-12);
+        let x = c.solve(&[6.0, 8.0, 4.0]);
+        assert!(close(&DMatrix::from_vec(3, 1, x), &DMatrix::from_vec(3, 1, vec![1.0, 1.0, 1.0])));
+        assert!(DMatrix::from_rows(&[&[1.0, 2.0], &[2.0, 1.0]]).cholesky().is_err());
+    }
+
+    #[test]
+    fn symmetric_eigen() {
+        let a = DMatrix::from_rows(&[&[2.0, -1.0, 0.0], &[-1.0, 2.0, -1.0], &[0.0, -1.0, 2.0]]);
+        let (values, vectors) = a.symmetric_eigen();
+        let s = 2.0f64.sqrt();
+        for (v, e) in values.iter().zip(&[2.0 - s, 2.0, 2.0 + s]) {
+            assert!((v - e).abs() < 1e-12);
+        }
+        // A V = V diag(values) with orthonormal V
+        assert!(close(&(&a * &vectors), &(&vectors * &DMatrix::from_diagonal(&values))));
+        assert!(close(&vectors.tr_matmul(&vectors), &DMatrix::identity(3)));
+    }
+
+    #[test]
+    fn btdb() {
+        let b = DMatrix::from_rows(&[&[1.0, 0.0], &[0.0, 2.0]]);
+        let d = DMatrix::from_diagonal(&[3.0, 4.0]);
+        let mut k = DMatrix::zeros(2, 2);
+        k.add_btdb(&b, &d, 0.5);
+        assert_eq!(k, DMatrix::from_diagonal(&[1.5, 8.0]));
+    }
+}
@@ -5,10 +5,12 @@ use std::ops::{Index, IndexMut};
 use super::traits::*;
 use super::{Vec2, Vec3};
 
+#[derive(Debug, PartialEq, Clone)]
 pub struct Mat2 {
     data: [f64; 4]
 }
 
+#[derive(Debug, PartialEq, Clone)]
 pub struct Mat3 {
     data: [f64; 9]
 }
@@ -29,6 +31,23 @@ impl Mat2 {
     pub fn size() -> usize {
         return 4;
     }
+
+    pub fn transpose(&self) -> Mat2 {
+        Mat2 {data: [self[0], self[2], self[1], self[3]]}
+    }
+
+    pub fn determinant(&self) -> f64 {
+        self[0] * self[3] - self[1] * self[2]
+    }
+
+    pub fn inverse(&self) -> Option<Mat2> {
+        let det = self.determinant();
+        if det == 0.0 {
+            return None;
+        }
+        Some(Mat2 {data: [ self[3] / det, -self[1] / det,
+                          -self[2] / det,  self[0] / det]})
+    }
 }
 
 impl Dot<Vec2> for Mat2 {
@@ -41,8 +60,8 @@ impl Dot<Vec2> for Mat2 {
 
 
 impl Mat3 {
-    fn new(data: [f64; 9]) -> Mat3 { Mat3 {data} }
-    fn zero() -> Mat3 {
+    pub fn new(data: [f64; 9]) -> Mat3 { Mat3 {data} }
+    pub fn zero() -> Mat3 {
         Mat3 {data: [0.0; 9]}
     }
 
@@ -54,6 +73,32 @@ impl Mat3 {
     pub fn size() -> usize {
         return 9;
     }
+
+    pub fn transpose(&self) -> Mat3 {
+        Mat3 {data: [self[0], self[3], self[6],
+                     self[1], self[4], self[7],
+                     self[2], self[5], self[8]]}
+    }
+
+    pub fn determinant(&self) -> f64 {
+        self[0] * (self[4] * self[8] - self[5] * self[7])
+            - self[1] * (self[3] * self[8] - self[5] * self[6])
+            + self[2] * (self[3] * self[7] - self[4] * self[6])
+    }
+
+    pub fn inverse(&self) -> Option<Mat3> {
+        let det = self.determinant();
+        if det == 0.0 {
+            return None;
+        }
+        // adjugate (transposed cofactors) over the determinant
+        let m = |r: usize, c: usize| self[r * 3 + c];
+        let cof = |r0: usize, r1: usize, c0: usize, c1: usize|
+            m(r0, c0) * m(r1, c1) - m(r0, c1) * m(r1, c0);
+        Some(Mat3 {data: [ cof(1, 2, 1, 2), -cof(0, 2, 1, 2),  cof(0, 1, 1, 2),
+                          -cof(1, 2, 0, 2),  cof(0, 2, 0, 2), -cof(0, 1, 0, 2),
+                           cof(1, 2, 0, 1), -cof(0, 2, 0, 1),  cof(0, 1, 0, 1)]} / det)
+    }
 }
 
 impl Dot<Vec3> for Mat3 {
@@ -100,7 +145,11 @@ macro_rules! op {
                 return res;
             }
         }
+    };
+}
 
+macro_rules! op_s {
+    ($tr: ident, $fn: ident, $op: tt, $obj: ident) => {
         impl $tr<f64> for $obj {
             type Output = $obj;
             fn $fn(self, other: f64) -> $obj {
@@ -114,12 +163,110 @@ macro_rules! op {
     };
 }
 
+macro_rules! op_as {
+    ($tr: ident, $fn: ident, $op: tt, $obj: ident) => {
+        impl $tr for $obj {
+            fn $fn(&mut self, other: $obj) {
+                for i in 0..$obj::size() {
+                    self[i] $op other[i];
+                }
+            }
+        }
+    };
+}
+
+macro_rules! op_as_s {
+    ($tr: ident, $fn: ident, $op: tt, $obj: ident) => {
+        impl $tr<f64> for $obj {
+            fn $fn(&mut self, other: f64) {
+                for i in 0..$obj::size() {
+                    self[i] $op other;
+                }
+            }
+        }
+    };
+}
+
+// True matrix product, `Div` stays element-wise.
+macro_rules! matmul {
+    ($obj: ident, $n: expr) => {
+        impl Mul for $obj {
+            type Output = $obj;
+            fn mul(self, other: $obj) -> $obj {
+                let mut res = $obj::zero();
+                for r in 0..$n {
+                    for c in 0..$n {
+                        res[r * $n + c] = (0..$n).map(|k| self[r * $n + k] * other[k * $n + c]).sum();
+                    }
+                }
+                return res;
+            }
+        }
+
+        impl MulAssign for $obj {
+            fn mul_assign(&mut self, other: $obj) {
+                *self = self.clone() * other;
+            }
+        }
+    };
+}
+
 op!(Add, add, +, Mat2);
 op!(Sub, sub, -, Mat2);
-op!(Mul, mul, *, Mat2);
 op!(Div, div, /, Mat2);
+op_s!(Add, add, +, Mat2);
+op_s!(Sub, sub, -, Mat2);
+op_s!(Mul, mul, *, Mat2);
+op_s!(Div, div, /, Mat2);
+op_as!(AddAssign, add_assign, +=, Mat2);
+op_as!(SubAssign, sub_assign, -=, Mat2);
+op_as_s!(MulAssign, mul_assign, *=, Mat2);
+op_as_s!(DivAssign, div_assign, /=, Mat2);
+matmul!(Mat2, 2);
 
 op!(Add, add, +, Mat3);
 op!(Sub, sub, -, Mat3);
-op!(Mul, mul, *, Mat3);
-op!(Div, div, /, Mat3);
\ No newline at end of file
+op!(Div, div, /, Mat3);
+op_s!(Add, add, +, Mat3);
+op_s!(Sub, sub, -, Mat3);
+op_s!(Mul, mul, *, Mat3);
+op_s!(Div, div, /, Mat3);
+op_as!(AddAssign, add_assign, +=, Mat3);
+op_as!(SubAssign, sub_assign, -=, Mat3);
+op_as_s!(MulAssign, mul_assign, *=, Mat3);
+op_as_s!(DivAssign, div_assign, /=, Mat3);
+matmul!(Mat3, 3);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mat2_algebra() {
+        let a = Mat2::new([1.0, 2.0, 3.0, 4.0]);
+        let b = Mat2::new([0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(a.clone() * b.clone(), Mat2::new([2.0, 1.0, 4.0, 3.0]));
+        assert_eq!(a.clone() / Mat2::new([1.0, 2.0, 3.0, 4.0]), Mat2::new([1.0; 4]));
+        assert_eq!(a.transpose(), Mat2::new([1.0, 3.0, 2.0, 4.0]));
+        assert_eq!(a.determinant(), -2.0);
+        assert_eq!(a.clone() * a.inverse().unwrap(), Mat2::identity());
+        assert_eq!(a.dot(&Vec2(1.0, 1.0)), Vec2(3.0, 7.0));
+        assert!(Mat2::new([1.0, 2.0, 2.0, 4.0]).inverse().is_none());
+    }
+
+    #[test]
+    fn mat3_algebra() {
+        let a = Mat3::new([2.0, 0.0, 1.0,
+                           1.0, 3.0, 0.0,
+                           0.0, 1.0, 4.0]);
+        assert_eq!(a.determinant(), 25.0);
+        assert_eq!(a.transpose()[1], 1.0);
+        let prod = a.clone() * a.inverse().unwrap();
+        for i in 0..Mat3::size() {
+            assert!((prod[i] - Mat3::identity()[i]).abs() < 1e-14);
+        }
+        let mut c = Mat3::identity();
+        c *= a.clone();
+        assert_eq!(c, a);
+    }
+}
@@ -1,8 +1,12 @@
 pub mod traits;
 pub mod vec;
 pub mod mat;
+pub mod dmat;
+pub mod sparse;
 
 
 pub use self::traits::*;
 pub use self::vec::*;
-pub use self::mat::*;
\ No newline at end of file
+pub use self::mat::*;
+pub use self::dmat::*;
+pub use self::sparse::*;
\ No newline at end of file
@@ -0,0 +1,470 @@
+use std::ops::Mul;
+
+// Coordinate (triplet) storage used while assembling. Duplicate entries are
+// allowed and get summed when converting to a compressed format.
+#[derive(Debug, Clone)]
+pub struct TripletMatrix {
+    nrows: usize,
+    ncols: usize,
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+    values: Vec<f64>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsrMatrix {
+    nrows: usize,
+    ncols: usize,
+    row_ptr: Vec<usize>,
+    col_idx: Vec<usize>,
+    values: Vec<f64>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CscMatrix {
+    nrows: usize,
+    ncols: usize,
+    col_ptr: Vec<usize>,
+    row_idx: Vec<usize>,
+    values: Vec<f64>
+}
+
+impl TripletMatrix {
+    pub fn new(nrows: usize, ncols: usize) -> TripletMatrix {
+        TripletMatrix::with_capacity(nrows, ncols, 0)
+    }
+
+    pub fn with_capacity(nrows: usize, ncols: usize, capacity: usize) -> TripletMatrix {
+        TripletMatrix {
+            nrows, ncols,
+            rows: Vec::with_capacity(capacity),
+            cols: Vec::with_capacity(capacity),
+            values: Vec::with_capacity(capacity)
+        }
+    }
+
+    pub fn nrows(&self) -> usize { self.nrows }
+    pub fn ncols(&self) -> usize { self.ncols }
+    pub fn nnz(&self) -> usize { self.values.len() }
+
+    pub fn add(&mut self, row: usize, col: usize, value: f64) {
+        assert!(row < self.nrows && col < self.ncols,
+                "entry ({}, {}) outside of {}x{} matrix", row, col, self.nrows, self.ncols);
+        self.rows.push(row);
+        self.cols.push(col);
+        self.values.push(value);
+    }
+
+    pub fn to_csr(&self) -> CsrMatrix {
+        let (row_ptr, col_idx, values) =
+            compress(self.nrows, &self.rows, &self.cols, &self.values);
+        CsrMatrix { nrows: self.nrows, ncols: self.ncols, row_ptr, col_idx, values }
+    }
+
+    pub fn to_csc(&self) -> CscMatrix {
+        let (col_ptr, row_idx, values) =
+            compress(self.ncols, &self.cols, &self.rows, &self.values);
+        CscMatrix { nrows: self.nrows, ncols: self.ncols, col_ptr, row_idx, values }
+    }
+}
+
+// Compresses triplets along `major`, sorting each line by `minor` and summing
+// duplicates. Shared by the CSR and CSC conversions.
+fn compress(n_major: usize, major: &[usize], minor: &[usize], values: &[f64])
+    -> (Vec<usize>, Vec<usize>, Vec<f64>) {
+    let mut counts = vec![0; n_major + 1];
+    for &m in major {
+        counts[m + 1] += 1;
+    }
+    for i in 0..n_major {
+        counts[i + 1] += counts[i];
+    }
+
+    let mut next = counts.clone();
+    let mut tmp_minor = vec![0; values.len()];
+    let mut tmp_values = vec![0.0; values.len()];
+    for k in 0..values.len() {
+        let pos = next[major[k]];
+        tmp_minor[pos] = minor[k];
+        tmp_values[pos] = values[k];
+        next[major[k]] += 1;
+    }
+
+    let mut ptr = Vec::with_capacity(n_major + 1);
+    let mut idx = Vec::with_capacity(values.len());
+    let mut vals = Vec::with_capacity(values.len());
+    ptr.push(0);
+    let mut line: Vec<(usize, f64)> = Vec::new();
+    for i in 0..n_major {
+        line.clear();
+        line.extend((counts[i]..counts[i + 1]).map(|k| (tmp_minor[k], tmp_values[k])));
+        line.sort_by_key(|&(m, _)| m);
+        for &(m, v) in &line {
+            if idx.len() > ptr[i] && *idx.last().unwrap() == m {
+                *vals.last_mut().unwrap() += v;
+            } else {
+                idx.push(m);
+                vals.push(v);
+            }
+        }
+        ptr.push(idx.len());
+    }
+    (ptr, idx, vals)
+}
+
+// Transposes a compressed structure: CSR <-> CSC of the same matrix, or the
+// CSR of the transpose.
+fn transpose_compressed(n_major: usize, n_minor: usize,
+                        ptr: &[usize], idx: &[usize], values: &[f64])
+    -> (Vec<usize>, Vec<usize>, Vec<f64>) {
+    let mut t_ptr = vec![0; n_minor + 1];
+    for &m in idx {
+        t_ptr[m + 1] += 1;
+    }
+    for i in 0..n_minor {
+        t_ptr[i + 1] += t_ptr[i];
+    }
+
+    let mut next = t_ptr.clone();
+    let mut t_idx = vec![0; idx.len()];
+    let mut t_values = vec![0.0; idx.len()];
+    for i in 0..n_major {
+        for k in ptr[i]..ptr[i + 1] {
+            let pos = next[idx[k]];
+            t_idx[pos] = i;
+            t_values[pos] = values[k];
+            next[idx[k]] += 1;
+        }
+    }
+    (t_ptr, t_idx, t_values)
+}
+
+impl CsrMatrix {
+    pub fn from_raw(nrows: usize, ncols: usize, row_ptr: Vec<usize>,
+                    col_idx: Vec<usize>, values: Vec<f64>) -> CsrMatrix {
+        assert_eq!(row_ptr.len(), nrows + 1);
+        assert_eq!(col_idx.len(), values.len());
+        assert_eq!(row_ptr[nrows], values.len());
+        CsrMatrix { nrows, ncols, row_ptr, col_idx, values }
+    }
+
+    pub fn identity(n: usize) -> CsrMatrix {
+        CsrMatrix {
+            nrows: n, ncols: n,
+            row_ptr: (0..n + 1).collect(),
+            col_idx: (0..n).collect(),
+            values: vec![1.0; n]
+        }
+    }
+
+    pub fn nrows(&self) -> usize { self.nrows }
+    pub fn ncols(&self) -> usize { self.ncols }
+    pub fn nnz(&self) -> usize { self.values.len() }
+
+    pub fn row_ptr(&self) -> &[usize] { &self.row_ptr }
+    pub fn col_idx(&self) -> &[usize] { &self.col_idx }
+    pub fn values(&self) -> &[f64] { &self.values }
+    pub fn values_mut(&mut self) -> &mut [f64] { &mut self.values }
+
+    pub fn row(&self, i: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let range = self.row_ptr[i]..self.row_ptr[i + 1];
+        self.col_idx[range.clone()].iter().cloned()
+            .zip(self.values[range].iter().cloned())
+    }
+
+    fn find(&self, i: usize, j: usize) -> Option<usize> {
+        let beg = self.row_ptr[i];
+        let end = self.row_ptr[i + 1];
+        self.col_idx[beg..end].binary_search(&j).ok().map(|k| beg + k)
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        self.find(i, j).map_or(0.0, |k| self.values[k])
+    }
+
+    pub fn mul_vec(&self, x: &[f64]) -> Vec<f64> {
+        let mut y = vec![0.0; self.nrows];
+        self.mul_vec_into(x, &mut y);
+        y
+    }
+
+    pub fn mul_vec_into(&self, x: &[f64], y: &mut [f64]) {
+        assert_eq!(x.len(), self.ncols);
+        assert_eq!(y.len(), self.nrows);
+        for (i, yi) in y.iter_mut().enumerate() {
+            *yi = (self.row_ptr[i]..self.row_ptr[i + 1])
+                .map(|k| self.values[k] * x[self.col_idx[k]])
+                .sum();
+        }
+    }
+
+    pub fn transpose(&self) -> CsrMatrix {
+        let (row_ptr, col_idx, values) = transpose_compressed(
+            self.nrows, self.ncols, &self.row_ptr, &self.col_idx, &self.values);
+        CsrMatrix { nrows: self.ncols, ncols: self.nrows, row_ptr, col_idx, values }
+    }
+
+    pub fn to_csc(&self) -> CscMatrix {
+        let (col_ptr, row_idx, values) = transpose_compressed(
+            self.nrows, self.ncols, &self.row_ptr, &self.col_idx, &self.values);
+        CscMatrix { nrows: self.nrows, ncols: self.ncols, col_ptr, row_idx, values }
+    }
+
+    pub fn diagonal(&self) -> Vec<f64> {
+        (0..self.nrows.min(self.ncols)).map(|i| self.get(i, i)).collect()
+    }
+
+    // alpha * self + beta * other over the union of both sparsity patterns
+    pub fn scaled_sum(&self, alpha: f64, other: &CsrMatrix, beta: f64) -> CsrMatrix {
+        assert_eq!((self.nrows, self.ncols), (other.nrows, other.ncols));
+        let mut trip = TripletMatrix::with_capacity(self.nrows, self.ncols, self.nnz() + other.nnz());
+        for i in 0..self.nrows {
+            for (j, v) in self.row(i) {
+                trip.add(i, j, alpha * v);
+            }
+            for (j, v) in other.row(i) {
+                trip.add(i, j, beta * v);
+            }
+        }
+        trip.to_csr()
+    }
+
+    // Rows and columns `indices` of a square matrix, in the given order
+    pub fn principal_submatrix(&self, indices: &[usize]) -> CsrMatrix {
+        let mut map = vec![None; self.ncols];
+        for (new, &old) in indices.iter().enumerate() {
+            map[old] = Some(new);
+        }
+        let n = indices.len();
+        let mut trip = TripletMatrix::new(n, n);
+        for (new, &old) in indices.iter().enumerate() {
+            for (j, v) in self.row(old) {
+                if let Some(col) = map[j] {
+                    trip.add(new, col, v);
+                }
+            }
+        }
+        trip.to_csr()
+    }
+
+    fn with_full_diagonal(&self) -> CsrMatrix {
+        let n = self.nrows.min(self.ncols);
+        if (0..n).all(|i| self.find(i, i).is_some()) {
+            return self.clone();
+        }
+        let mut trip = TripletMatrix::with_capacity(self.nrows, self.ncols, self.nnz() + n);
+        for i in 0..self.nrows {
+            for (j, v) in self.row(i) {
+                trip.add(i, j, v);
+            }
+            if i < n {
+                trip.add(i, i, 0.0);
+            }
+        }
+        trip.to_csr()
+    }
+
+    // Imposes u[dof] = value by eliminating the row and column of every
+    // constrained dof. The known column contributions are moved to `rhs`, so a
+    // symmetric matrix stays symmetric. The original diagonal is kept (or set
+    // to 1 if it was zero) to avoid spoiling the conditioning.
+    pub fn apply_dirichlet(&mut self, constraints: &[(usize, f64)], rhs: &mut [f64]) {
+        assert_eq!(self.nrows, self.ncols, "Dirichlet elimination needs a square matrix");
+        assert_eq!(rhs.len(), self.nrows);
+
+        let mut fixed: Vec<Option<f64>> = vec![None; self.nrows];
+        for &(dof, value) in constraints {
+            fixed[dof] = Some(value);
+        }
+
+        *self = self.with_full_diagonal();
+
+        for i in 0..self.nrows {
+            for k in self.row_ptr[i]..self.row_ptr[i + 1] {
+                let j = self.col_idx[k];
+                if let Some(value) = fixed[j] {
+                    if fixed[i].is_none() {
+                        rhs[i] -= self.values[k] * value;
+                    }
+                    if i != j {
+                        self.values[k] = 0.0;
+                    }
+                }
+            }
+        }
+
+        for (i, fix) in fixed.iter().enumerate() {
+            if let Some(value) = *fix {
+                let diag = self.find(i, i).unwrap();
+                for k in self.row_ptr[i]..self.row_ptr[i + 1] {
+                    if k != diag {
+                        self.values[k] = 0.0;
+                    }
+                }
+                if self.values[diag] == 0.0 {
+                    self.values[diag] = 1.0;
+                }
+                rhs[i] = self.values[diag] * value;
+            }
+        }
+    }
+}
+
+impl<'a> Mul<&'a [f64]> for &'a CsrMatrix {
+    type Output = Vec<f64>;
+    fn mul(self, x: &[f64]) -> Vec<f64> {
+        self.mul_vec(x)
+    }
+}
+
+impl CscMatrix {
+    pub fn nrows(&self) -> usize { self.nrows }
+    pub fn ncols(&self) -> usize { self.ncols }
+    pub fn nnz(&self) -> usize { self.values.len() }
+
+    pub fn col_ptr(&self) -> &[usize] { &self.col_ptr }
+    pub fn row_idx(&self) -> &[usize] { &self.row_idx }
+    pub fn values(&self) -> &[f64] { &self.values }
+
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        let beg = self.col_ptr[j];
+        let end = self.col_ptr[j + 1];
+        self.row_idx[beg..end].binary_search(&i).ok().map_or(0.0, |k| self.values[beg + k])
+    }
+
+    pub fn mul_vec(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.ncols);
+        let mut y = vec![0.0; self.nrows];
+        for (j, xj) in x.iter().enumerate() {
+            for k in self.col_ptr[j]..self.col_ptr[j + 1] {
+                y[self.row_idx[k]] += self.values[k] * xj;
+            }
+        }
+        y
+    }
+
+    pub fn transpose(&self) -> CscMatrix {
+        let (col_ptr, row_idx, values) = transpose_compressed(
+            self.ncols, self.nrows, &self.col_ptr, &self.row_idx, &self.values);
+        CscMatrix { nrows: self.ncols, ncols: self.nrows, col_ptr, row_idx, values }
+    }
+
+    pub fn to_csr(&self) -> CsrMatrix {
+        let (row_ptr, col_idx, values) = transpose_compressed(
+            self.ncols, self.nrows, &self.col_ptr, &self.row_idx, &self.values);
+        CsrMatrix { nrows: self.nrows, ncols: self.ncols, row_ptr, col_idx, values }
+    }
+
+    pub fn diagonal(&self) -> Vec<f64> {
+        (0..self.nrows.min(self.ncols)).map(|i| self.get(i, i)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> TripletMatrix {
+        // | 4 -1  0 |
+        // |-1  4 -1 |
+        // | 0 -1  4 |   (the 4 on row 1 is assembled from two entries)
+        let mut t = TripletMatrix::new(3, 3);
+        t.add(0, 0, 4.0);
+        t.add(0, 1, -1.0);
+        t.add(1, 0, -1.0);
+        t.add(1, 1, 1.5);
+        t.add(1, 2, -1.0);
+        t.add(1, 1, 2.5);
+        t.add(2, 1, -1.0);
+        t.add(2, 2, 4.0);
+        t
+    }
+
+    #[test]
+    fn duplicates_are_summed() {
+        let a = sample().to_csr();
+        assert_eq!(a.nnz(), 7);
+        assert_eq!(a.get(1, 1), 4.0);
+        assert_eq!(a.get(0, 2), 0.0);
+        assert_eq!(a.row_ptr(), &[0, 2, 5, 7]);
+        assert_eq!(a.col_idx(), &[0, 1, 0, 1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn csr_csc_agree() {
+        let t = sample();
+        let csr = t.to_csr();
+        let csc = t.to_csc();
+        assert_eq!(csr.to_csc(), csc);
+        assert_eq!(csc.to_csr(), csr);
+        let x = [1.0, 2.0, 3.0];
+        assert_eq!(csr.mul_vec(&x), vec![2.0, 4.0, 10.0]);
+        assert_eq!(csc.mul_vec(&x), vec![2.0, 4.0, 10.0]);
+        assert_eq!(&csr * &x[..], vec![2.0, 4.0, 10.0]);
+    }
+
+    #[test]
+    fn scaled_sum() {
+        let a = sample().to_csr();
+        let b = CsrMatrix::identity(3);
+        let c = a.scaled_sum(0.5, &b, -2.0);
+        assert_eq!(c.nnz(), 7);
+        assert_eq!(c.diagonal(), vec![0.0, 0.0, 0.0]);
+        assert_eq!(c.get(1, 2), -0.5);
+    }
+
+    #[test]
+    fn principal_submatrix() {
+        let a = sample().to_csr().principal_submatrix(&[2, 0]);
+        assert_eq!(a.nrows(), 2);
+        assert_eq!(a.diagonal(), vec![4.0, 4.0]);
+        assert_eq!(a.nnz(), 2);
+    }
+
+    #[test]
+    fn transpose_rectangular() {
+        let mut t = TripletMatrix::new(2, 3);
+        t.add(0, 2, 1.0);
+        t.add(1, 0, 2.0);
+        t.add(1, 1, 3.0);
+        let a = t.to_csr();
+        let at = a.transpose();
+        assert_eq!((at.nrows(), at.ncols()), (3, 2));
+        assert_eq!(at.get(2, 0), 1.0);
+        assert_eq!(at.get(0, 1), 2.0);
+        assert_eq!(at.get(1, 1), 3.0);
+        assert_eq!(at.transpose(), a);
+        assert_eq!(a.to_csc().transpose().to_csr(), at);
+    }
+
+    #[test]
+    fn diagonal() {
+        assert_eq!(sample().to_csr().diagonal(), vec![4.0, 4.0, 4.0]);
+        assert_eq!(sample().to_csc().diagonal(), vec![4.0, 4.0, 4.0]);
+    }
+
+    #[test]
+    fn dirichlet_elimination() {
+        let mut a = sample().to_csr();
+        let mut rhs = vec![0.0, 1.0, 0.0];
+        a.apply_dirichlet(&[(0, 2.0)], &mut rhs);
+
+        assert_eq!(a.get(0, 1), 0.0);
+        assert_eq!(a.get(1, 0), 0.0);
+        assert_eq!(a.get(0, 0), 4.0);
+        assert_eq!(rhs, vec![8.0, 3.0, 0.0]);
+        assert_eq!(a.transpose(), a);
+    }
+
+    #[test]
+    fn dirichlet_on_missing_diagonal() {
+        let mut t = TripletMatrix::new(2, 2);
+        t.add(0, 1, 1.0);
+        t.add(1, 0, 1.0);
+        let mut a = t.to_csr();
+        let mut rhs = vec![0.0, 0.0];
+        a.apply_dirichlet(&[(1, 3.0)], &mut rhs);
+        assert_eq!(a.get(1, 1), 1.0);
+        assert_eq!(rhs, vec![-3.0, 3.0]);
+    }
+}
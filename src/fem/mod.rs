@@ -0,0 +1,2 @@
+pub mod quadrature;
+pub mod shape;
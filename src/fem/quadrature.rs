@@ -0,0 +1,231 @@
+use std::f64;
+use base_types::Vec2;
+
+// Integration point in natural coordinates. Line rules live on [-1, 1] and
+// only use the first coordinate, triangle rules on the unit triangle
+// (0,0)-(1,0)-(0,1) and quadrilateral rules on [-1, 1]^2.
+#[derive(Debug, Clone)]
+pub struct QuadraturePoint {
+    pub point: Vec2,
+    pub weight: f64
+}
+
+// Gauss-Legendre abscissae and weights on [-1, 1], exact up to degree 2n - 1.
+pub fn gauss_legendre(n: usize) -> Vec<(f64, f64)> {
+    assert!(n > 0, "Gauss-Legendre rule needs at least one point");
+    let mut res = vec![(0.0, 0.0); n];
+    for i in 0..(n + 1) / 2 {
+        // Newton iteration on P_n, starting from the Chebyshev approximation
+        let mut x = (f64::consts::PI * (i as f64 + 0.75) / (n as f64 + 0.5)).cos();
+        let mut dp = 0.0;
+        for _ in 0..100 {
+            let (mut p0, mut p1) = (1.0, x);
+            for k in 2..n + 1 {
+                let k = k as f64;
+                let p2 = ((2.0 * k - 1.0) * x * p1 - (k - 1.0) * p0) / k;
+                p0 = p1;
+                p1 = p2;
+            }
+            dp = n as f64 * (x * p1 - p0) / (x * x - 1.0);
+            let dx = p1 / dp;
+            x -= dx;
+            if dx.abs() < 1e-15 {
+                break;
+            }
+        }
+        let w = 2.0 / ((1.0 - x * x) * dp * dp);
+        res[i] = (-x, w);
+        res[n - 1 - i] = (x, w);
+    }
+    if n % 2 == 1 {
+        res[n / 2].0 = 0.0;
+    }
+    res
+}
+
+fn points_for_degree(degree: usize) -> usize {
+    degree / 2 + 1
+}
+
+pub fn line(degree: usize) -> Vec<QuadraturePoint> {
+    gauss_legendre(points_for_degree(degree)).into_iter()
+        .map(|(x, w)| QuadraturePoint { point: Vec2(x, 0.0), weight: w })
+        .collect()
+}
+
+pub fn quadrilateral(degree: usize) -> Vec<QuadraturePoint> {
+    let gl = gauss_legendre(points_for_degree(degree));
+    let mut res = Vec::with_capacity(gl.len() * gl.len());
+    for &(y, wy) in &gl {
+        for &(x, wx) in &gl {
+            res.push(QuadraturePoint { point: Vec2(x, y), weight: wx * wy });
+        }
+    }
+    res
+}
+
+// Symmetric orbit generators of the Dunavant rules, as barycentric
+// coordinates with a weight normalised to the triangle area.
+enum Orbit {
+    Centroid(f64),
+    // (a, b, b) and its 3 permutations
+    Three(f64, f64, f64),
+    // (a, b, c) and its 6 permutations
+    Six(f64, f64, f64, f64)
+}
+
+fn dunavant(degree: usize) -> Option<Vec<Orbit>> {
+    use self::Orbit::*;
+    let rule = match degree {
+        0 | 1 => vec![Centroid(1.0)],
+        2 => vec![Three(2.0 / 3.0, 1.0 / 6.0, 1.0 / 3.0)],
+        3 => vec![Centroid(-27.0 / 48.0),
+                  Three(0.6, 0.2, 25.0 / 48.0)],
+        4 => vec![Three(0.108103018168070, 0.445948490915965, 0.223381589678011),
+                  Three(0.816847572980459, 0.091576213509771, 0.109951743655322)],
+        5 => vec![Centroid(0.225),
+                  Three(0.059715871789770, 0.470142064105115, 0.132394152788506),
+                  Three(0.797426985353087, 0.101286507323456, 0.125939180544827)],
+        6 => vec![Three(0.501426509658179, 0.249286745170910, 0.116786275726379),
+                  Three(0.873821971016996, 0.063089014491502, 0.050844906370207),
+                  Six(0.053145049844817, 0.310352451033784, 0.636502499121399,
+                      0.082851075618374)],
+        7 => vec![Centroid(-0.149570044467682),
+                  Three(0.479308067841920, 0.260345966079040, 0.175615257433208),
+                  Three(0.869739794195568, 0.065130102902216, 0.053347235608838),
+                  Six(0.048690315425316, 0.312865496004874, 0.638444188569810,
+                      0.077113760890257)],
+        _ => return None
+    };
+    Some(rule)
+}
+
+// Collapsed (Duffy) Gauss rule for degrees without a Dunavant rule.
+fn collapsed_triangle(degree: usize) -> Vec<QuadraturePoint> {
+    // the collapse adds one to the polynomial degree in the first direction
+    let gl = gauss_legendre(points_for_degree(degree + 1));
+    let mut res = Vec::with_capacity(gl.len() * gl.len());
+    for &(u, wu) in &gl {
+        let u = (u + 1.0) / 2.0;
+        for &(v, wv) in &gl {
+            let v = (v + 1.0) / 2.0;
+            res.push(QuadraturePoint {
+                point: Vec2(u, v * (1.0 - u)),
+                weight: wu * wv * (1.0 - u) / 4.0
+            });
+        }
+    }
+    res
+}
+
+pub fn triangle(degree: usize) -> Vec<QuadraturePoint> {
+    let orbits = match dunavant(degree) {
+        Some(orbits) => orbits,
+        None => return collapsed_triangle(degree)
+    };
+    let mut res = Vec::new();
+    let mut push = |l1: f64, l2: f64, w: f64| {
+        res.push(QuadraturePoint { point: Vec2(l1, l2), weight: w / 2.0 });
+    };
+    for orbit in orbits {
+        match orbit {
+            Orbit::Centroid(w) => push(1.0 / 3.0, 1.0 / 3.0, w),
+            Orbit::Three(a, b, w) => {
+                push(a, b, w);
+                push(b, a, w);
+                push(b, b, w);
+            },
+            Orbit::Six(a, b, c, w) => {
+                push(a, b, w);
+                push(b, a, w);
+                push(a, c, w);
+                push(c, a, w);
+                push(b, c, w);
+                push(c, b, w);
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn factorial(n: u32) -> f64 {
+        (1..n + 1).map(f64::from).product()
+    }
+
+    fn monomial(p: &Vec2, a: u32, b: u32) -> f64 {
+        p.0.powi(a as i32) * p.1.powi(b as i32)
+    }
+
+    fn integrate(rule: &[QuadraturePoint], a: u32, b: u32) -> f64 {
+        rule.iter().map(|q| q.weight * monomial(&q.point, a, b)).sum()
+    }
+
+    // Integral of x^a over [-1, 1]
+    fn exact_1d(a: u32) -> f64 {
+        if a % 2 == 1 { 0.0 } else { 2.0 / (a as f64 + 1.0) }
+    }
+
+    #[test]
+    fn gauss_legendre_weights() {
+        assert_eq!(gauss_legendre(1), vec![(0.0, 2.0)]);
+        let g2 = gauss_legendre(2);
+        assert!((g2[1].0 - 1.0 / 3.0f64.sqrt()).abs() < 1e-15);
+        assert!((g2[0].1 - 1.0).abs() < 1e-15);
+        for n in 1..12 {
+            let sum: f64 = gauss_legendre(n).iter().map(|p| p.1).sum();
+            assert!((sum - 2.0).abs() < 1e-13);
+        }
+    }
+
+    #[test]
+    fn line_rules_are_exact() {
+        for degree in 0..20 {
+            let rule = line(degree);
+            for a in 0..degree as u32 + 1 {
+                let err = (integrate(&rule, a, 0) - exact_1d(a)).abs();
+                assert!(err < 1e-13, "degree {} rule fails on x^{}", degree, a);
+            }
+        }
+    }
+
+    #[test]
+    fn quadrilateral_rules_are_exact() {
+        for degree in 0..10 {
+            let rule = quadrilateral(degree);
+            for a in 0..degree as u32 + 1 {
+                for b in 0..degree as u32 + 1 {
+                    let err = (integrate(&rule, a, b) - exact_1d(a) * exact_1d(b)).abs();
+                    assert!(err < 1e-13, "degree {} rule fails on x^{} y^{}", degree, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn triangle_rules_are_exact() {
+        for degree in 0..13 {
+            let rule = triangle(degree);
+            for a in 0..degree as u32 + 1 {
+                for b in 0..degree as u32 + 1 - a {
+                    let exact = factorial(a) * factorial(b) / factorial(a + b + 2);
+                    let err = (integrate(&rule, a, b) - exact).abs();
+                    assert!(err < 1e-13, "degree {} rule fails on x^{} y^{}: {:e}",
+                            degree, a, b, err);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn triangle_points_are_inside() {
+        for degree in 0..13 {
+            assert!(triangle(degree).iter()
+                .all(|q| q.point.0 >= 0.0 && q.point.1 >= 0.0 && q.point.0 + q.point.1 <= 1.0));
+        }
+        assert_eq!(triangle(6).len(), 12);
+    }
+}
@@ -0,0 +1,445 @@
+use base_types::*;
+use super::quadrature::{self, QuadraturePoint};
+
+// Lagrange element families. Corner nodes come first (counter-clockwise),
+// followed by the mid-edge nodes (edge i joins corners i and i + 1) and the
+// centre node. Line elements are used for boundary edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementKind {
+    Line2,
+    Line3,
+    Tri3,
+    Tri6,
+    Quad4,
+    Quad8,
+    Quad9
+}
+
+impl ElementKind {
+    pub fn node_count(&self) -> usize {
+        match self {
+            ElementKind::Line2 => 2,
+            ElementKind::Line3 => 3,
+            ElementKind::Tri3 => 3,
+            ElementKind::Tri6 => 6,
+            ElementKind::Quad4 => 4,
+            ElementKind::Quad8 => 8,
+            ElementKind::Quad9 => 9
+        }
+    }
+
+    pub fn corner_count(&self) -> usize {
+        match self {
+            ElementKind::Line2 | ElementKind::Line3 => 2,
+            ElementKind::Tri3 | ElementKind::Tri6 => 3,
+            _ => 4
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        match self {
+            ElementKind::Line2 | ElementKind::Line3 => 1,
+            _ => 2
+        }
+    }
+
+    pub fn is_quadratic(&self) -> bool {
+        matches!(self, ElementKind::Line3 | ElementKind::Tri6 | ElementKind::Quad8 | ElementKind::Quad9)
+    }
+
+    pub fn is_triangle(&self) -> bool {
+        *self == ElementKind::Tri3 || *self == ElementKind::Tri6
+    }
+
+    // Polynomial order of the shape functions
+    pub fn order(&self) -> usize {
+        if self.is_quadratic() { 2 } else { 1 }
+    }
+
+    // Degree of the rule for stiffness and mass integrals
+    pub fn quadrature_degree(&self) -> usize {
+        2 * self.order()
+    }
+
+    // Kind of the element's edges
+    pub fn edge_kind(&self) -> ElementKind {
+        if self.is_quadratic() { ElementKind::Line3 } else { ElementKind::Line2 }
+    }
+
+    // Local node indices of every edge, ordered like a Line2/Line3 element
+    pub fn edges(&self) -> Vec<Vec<usize>> {
+        if self.dimension() == 1 {
+            return vec![(0..self.node_count()).collect()];
+        }
+        let nc = self.corner_count();
+        (0..nc).map(|i| {
+            let mut edge = vec![i, (i + 1) % nc];
+            if self.is_quadratic() {
+                edge.push(nc + i);
+            }
+            edge
+        }).collect()
+    }
+
+    // Node positions in natural coordinates
+    pub fn natural_nodes(&self) -> Vec<Vec2> {
+        match self {
+            ElementKind::Line2 => vec![Vec2(-1.0, 0.0), Vec2(1.0, 0.0)],
+            ElementKind::Line3 => vec![Vec2(-1.0, 0.0), Vec2(1.0, 0.0), Vec2(0.0, 0.0)],
+            ElementKind::Tri3 => vec![Vec2(0.0, 0.0), Vec2(1.0, 0.0), Vec2(0.0, 1.0)],
+            ElementKind::Tri6 => vec![Vec2(0.0, 0.0), Vec2(1.0, 0.0), Vec2(0.0, 1.0),
+                                      Vec2(0.5, 0.0), Vec2(0.5, 0.5), Vec2(0.0, 0.5)],
+            ElementKind::Quad4 => QUAD_NODES[..4].iter().map(|p| Vec2(p.0, p.1)).collect(),
+            ElementKind::Quad8 => QUAD_NODES[..8].iter().map(|p| Vec2(p.0, p.1)).collect(),
+            ElementKind::Quad9 => QUAD_NODES.iter().map(|p| Vec2(p.0, p.1)).collect()
+        }
+    }
+
+    // Centroid in natural coordinates
+    pub fn centre(&self) -> Vec2 {
+        if self.is_triangle() { Vec2(1.0 / 3.0, 1.0 / 3.0) } else { Vec2(0.0, 0.0) }
+    }
+
+    // Integration rule exact for polynomials of the given degree
+    pub fn quadrature(&self, degree: usize) -> Vec<QuadraturePoint> {
+        match self.dimension() {
+            1 => quadrature::line(degree),
+            _ if self.is_triangle() => quadrature::triangle(degree),
+            _ => quadrature::quadrilateral(degree)
+        }
+    }
+
+    // How far a natural point lies outside the reference element, zero
+    // inside it
+    pub fn outside(&self, xi: &Vec2) -> f64 {
+        if self.is_triangle() {
+            (-xi.0).max(-xi.1).max(xi.0 + xi.1 - 1.0).max(0.0)
+        } else {
+            (xi.0.abs() - 1.0).max(xi.1.abs() - 1.0).max(0.0)
+        }
+    }
+
+    // Moves a natural point onto the reference element
+    pub fn clamp(&self, xi: &Vec2) -> Vec2 {
+        if !self.is_triangle() {
+            return Vec2(xi.0.clamp(-1.0, 1.0), xi.1.clamp(-1.0, 1.0));
+        }
+        let p = Vec2(xi.0.max(0.0), xi.1.max(0.0));
+        if p.0 + p.1 <= 1.0 {
+            return p;
+        }
+        // projection onto the hypotenuse
+        let t = ((p.0 - p.1 + 1.0) / 2.0).clamp(0.0, 1.0);
+        Vec2(t, 1.0 - t)
+    }
+
+    pub fn shape_functions(&self, xi: &Vec2) -> Vec<f64> {
+        let (x, y) = (xi.0, xi.1);
+        match self {
+            ElementKind::Line2 => vec![(1.0 - x) / 2.0, (1.0 + x) / 2.0],
+            ElementKind::Line3 => vec![x * (x - 1.0) / 2.0, x * (x + 1.0) / 2.0, 1.0 - x * x],
+            ElementKind::Tri3 => vec![1.0 - x - y, x, y],
+            ElementKind::Tri6 => {
+                let l = [1.0 - x - y, x, y];
+                vec![l[0] * (2.0 * l[0] - 1.0),
+                     l[1] * (2.0 * l[1] - 1.0),
+                     l[2] * (2.0 * l[2] - 1.0),
+                     4.0 * l[0] * l[1],
+                     4.0 * l[1] * l[2],
+                     4.0 * l[2] * l[0]]
+            },
+            ElementKind::Quad4 => QUAD_NODES[..4].iter()
+                .map(|&(xn, yn)| (1.0 + x * xn) * (1.0 + y * yn) / 4.0)
+                .collect(),
+            ElementKind::Quad8 => QUAD_NODES[..8].iter()
+                .map(|&(xn, yn)| serendipity(x, y, xn, yn).0)
+                .collect(),
+            ElementKind::Quad9 => QUAD_NODES.iter()
+                .map(|&(xn, yn)| lagrange_1d(x, xn).0 * lagrange_1d(y, yn).0)
+                .collect()
+        }
+    }
+
+    // Derivatives with respect to the natural coordinates, (dN/dxi, dN/deta)
+    pub fn shape_derivatives(&self, xi: &Vec2) -> Vec<Vec2> {
+        let (x, y) = (xi.0, xi.1);
+        match self {
+            ElementKind::Line2 => vec![Vec2(-0.5, 0.0), Vec2(0.5, 0.0)],
+            ElementKind::Line3 => vec![Vec2(x - 0.5, 0.0), Vec2(x + 0.5, 0.0),
+                                       Vec2(-2.0 * x, 0.0)],
+            ElementKind::Tri3 => vec![Vec2(-1.0, -1.0), Vec2(1.0, 0.0), Vec2(0.0, 1.0)],
+            ElementKind::Tri6 => {
+                let l0 = 1.0 - x - y;
+                vec![Vec2(1.0 - 4.0 * l0, 1.0 - 4.0 * l0),
+                     Vec2(4.0 * x - 1.0, 0.0),
+                     Vec2(0.0, 4.0 * y - 1.0),
+                     Vec2(4.0 * (l0 - x), -4.0 * x),
+                     Vec2(4.0 * y, 4.0 * x),
+                     Vec2(-4.0 * y, 4.0 * (l0 - y))]
+            },
+            ElementKind::Quad4 => QUAD_NODES[..4].iter()
+                .map(|&(xn, yn)| Vec2(xn * (1.0 + y * yn) / 4.0, yn * (1.0 + x * xn) / 4.0))
+                .collect(),
+            ElementKind::Quad8 => QUAD_NODES[..8].iter()
+                .map(|&(xn, yn)| serendipity(x, y, xn, yn).1)
+                .collect(),
+            ElementKind::Quad9 => QUAD_NODES.iter()
+                .map(|&(xn, yn)| {
+                    let (lx, dlx) = lagrange_1d(x, xn);
+                    let (ly, dly) = lagrange_1d(y, yn);
+                    Vec2(dlx * ly, lx * dly)
+                })
+                .collect()
+        }
+    }
+}
+
+const QUAD_NODES: [(f64, f64); 9] = [
+    (-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0),
+    (0.0, -1.0), (1.0, 0.0), (0.0, 1.0), (-1.0, 0.0),
+    (0.0, 0.0)
+];
+
+// Quadratic 1D Lagrange polynomial for the node at `xn` in {-1, 0, 1}, and its derivative.
+fn lagrange_1d(x: f64, xn: f64) -> (f64, f64) {
+    if xn == 0.0 {
+        (1.0 - x * x, -2.0 * x)
+    } else {
+        (x * (x + xn) / 2.0, (2.0 * x + xn) / 2.0)
+    }
+}
+
+// 8-node serendipity shape function of the node at (xn, yn) and its gradient.
+fn serendipity(x: f64, y: f64, xn: f64, yn: f64) -> (f64, Vec2) {
+    if xn == 0.0 {
+        ((1.0 - x * x) * (1.0 + y * yn) / 2.0,
+         Vec2(-x * (1.0 + y * yn), yn * (1.0 - x * x) / 2.0))
+    } else if yn == 0.0 {
+        ((1.0 + x * xn) * (1.0 - y * y) / 2.0,
+         Vec2(xn * (1.0 - y * y) / 2.0, -y * (1.0 + x * xn)))
+    } else {
+        let (a, b) = (1.0 + x * xn, 1.0 + y * yn);
+        let c = x * xn + y * yn - 1.0;
+        (a * b * c / 4.0,
+         Vec2(xn * b * (c + a) / 4.0, yn * a * (c + b) / 4.0))
+    }
+}
+
+// Isoparametric map of a 2D element evaluated at one natural point.
+#[derive(Debug, Clone)]
+pub struct IsoPoint {
+    // shape function values
+    pub n: Vec<f64>,
+    // physical position
+    pub x: Vec2,
+    // J[i][j] = dx_j / dxi_i
+    pub jacobian: Mat2,
+    pub det_j: f64,
+    // physical gradients dN/dx
+    pub grad: Vec<Vec2>
+}
+
+pub fn map(kind: ElementKind, coords: &[Vec2], xi: &Vec2) -> Result<IsoPoint, String> {
+    assert_eq!(kind.dimension(), 2, "use map_edge for line elements");
+    assert_eq!(coords.len(), kind.node_count());
+    let n = kind.shape_functions(xi);
+    let dn = kind.shape_derivatives(xi);
+
+    let mut x = Vec2(0.0, 0.0);
+    let mut j = Mat2::zero();
+    for ((ni, dni), ci) in n.iter().zip(&dn).zip(coords) {
+        x += ci.clone() * *ni;
+        j[0] += dni.0 * ci.0;
+        j[1] += dni.0 * ci.1;
+        j[2] += dni.1 * ci.0;
+        j[3] += dni.1 * ci.1;
+    }
+
+    let det_j = j.determinant();
+    if det_j <= 0.0 {
+        return Err(format!("{:?} element is inverted or degenerate (det J = {:e} at {:?})",
+                           kind, det_j, x));
+    }
+    let inv = j.inverse().unwrap();
+    let grad = dn.iter().map(|d| inv.dot(d)).collect();
+    Ok(IsoPoint { n, x, jacobian: j, det_j, grad })
+}
+
+// Natural coordinates of the physical point `x` by Newton iterations on the
+// isoparametric map. The point may lie outside the element, as long as the
+// map can be continued there.
+pub fn inverse_map(kind: ElementKind, coords: &[Vec2], x: &Vec2) -> Result<Vec2, String> {
+    let mut xi = kind.centre();
+    for _ in 0..25 {
+        let iso = map(kind, coords, &xi)?;
+        // dx = J^T dxi
+        let step = iso.jacobian.transpose().inverse().unwrap().dot(&(x.clone() - iso.x));
+        xi += step.clone();
+        if step.dot(&step).sqrt() < 1e-13 {
+            return Ok(xi);
+        }
+    }
+    Err(format!("cannot find {:?} in the {:?} element", x, kind))
+}
+
+// Isoparametric map of a line element (boundary edge).
+#[derive(Debug, Clone)]
+pub struct EdgePoint {
+    pub n: Vec<f64>,
+    pub x: Vec2,
+    // ds / dxi
+    pub det_j: f64,
+    // unit tangent along the node order and the outward normal for a
+    // counter-clockwise boundary
+    pub tangent: Vec2,
+    pub normal: Vec2
+}
+
+pub fn map_edge(kind: ElementKind, coords: &[Vec2], xi: f64) -> Result<EdgePoint, String> {
+    assert_eq!(kind.dimension(), 1, "use map for 2D elements");
+    assert_eq!(coords.len(), kind.node_count());
+    let p = Vec2(xi, 0.0);
+    let n = kind.shape_functions(&p);
+    let dn = kind.shape_derivatives(&p);
+
+    let mut x = Vec2(0.0, 0.0);
+    let mut t = Vec2(0.0, 0.0);
+    for ((ni, dni), ci) in n.iter().zip(&dn).zip(coords) {
+        x += ci.clone() * *ni;
+        t += ci.clone() * dni.0;
+    }
+    let det_j = t.dot(&t).sqrt();
+    if det_j == 0.0 {
+        return Err(format!("degenerate edge at {:?}", x));
+    }
+    let tangent = t / det_j;
+    let normal = Vec2(tangent.1, -tangent.0);
+    Ok(EdgePoint { n, x, det_j, tangent, normal })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KINDS: [ElementKind; 7] = [ElementKind::Line2, ElementKind::Line3,
+                                     ElementKind::Tri3, ElementKind::Tri6,
+                                     ElementKind::Quad4, ElementKind::Quad8,
+                                     ElementKind::Quad9];
+
+    #[test]
+    fn kronecker_delta_and_partition_of_unity() {
+        for kind in KINDS.iter() {
+            let nodes = kind.natural_nodes();
+            assert_eq!(nodes.len(), kind.node_count());
+            for (i, p) in nodes.iter().enumerate() {
+                let n = kind.shape_functions(p);
+                for (j, v) in n.iter().enumerate() {
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    assert!((v - expected).abs() < 1e-14, "{:?} N{}({:?}) = {}", kind, j, p, v);
+                }
+            }
+            let p = Vec2(0.21, 0.33);
+            let sum: f64 = kind.shape_functions(&p).iter().sum();
+            assert!((sum - 1.0).abs() < 1e-14);
+            let dsum = kind.shape_derivatives(&p).into_iter()
+                .fold(Vec2(0.0, 0.0), |a, b| a + b);
+            assert!(dsum.0.abs() < 1e-14 && dsum.1.abs() < 1e-14);
+        }
+    }
+
+    #[test]
+    fn derivatives_match_finite_differences() {
+        let h = 1e-6;
+        let p = Vec2(0.17, 0.29);
+        for kind in KINDS.iter() {
+            let dn = kind.shape_derivatives(&p);
+            let fx = kind.shape_functions(&Vec2(p.0 + h, p.1));
+            let bx = kind.shape_functions(&Vec2(p.0 - h, p.1));
+            let fy = kind.shape_functions(&Vec2(p.0, p.1 + h));
+            let by = kind.shape_functions(&Vec2(p.0, p.1 - h));
+            for i in 0..kind.node_count() {
+                assert!((dn[i].0 - (fx[i] - bx[i]) / (2.0 * h)).abs() < 1e-8, "{:?} {}", kind, i);
+                if kind.dimension() == 2 {
+                    assert!((dn[i].1 - (fy[i] - by[i]) / (2.0 * h)).abs() < 1e-8, "{:?} {}", kind, i);
+                }
+            }
+        }
+    }
+
+    // Places the nodes of `kind` on a distorted (but straight-sided) geometry
+    fn distorted(kind: ElementKind) -> Vec<Vec2> {
+        let corners = if kind.is_triangle() {
+            vec![Vec2(1.0, 1.0), Vec2(4.0, 1.5), Vec2(2.0, 3.0)]
+        } else {
+            vec![Vec2(0.0, 0.0), Vec2(3.0, 0.5), Vec2(2.5, 2.0), Vec2(0.5, 3.0)]
+        };
+        // mid-edge and centre nodes from the bilinear/linear map of the corners
+        let corner_kind = if kind.is_triangle() { ElementKind::Tri3 } else { ElementKind::Quad4 };
+        kind.natural_nodes().iter().map(|xi| {
+            corner_kind.shape_functions(xi).iter().zip(&corners)
+                .fold(Vec2(0.0, 0.0), |acc, (n, c)| acc + c.clone() * *n)
+        }).collect()
+    }
+
+    #[test]
+    fn linear_fields_are_reproduced() {
+        // u = 2x - 3y + 1 must have a constant gradient (2, -3) everywhere
+        for kind in KINDS.iter().filter(|k| k.dimension() == 2) {
+            let coords = distorted(*kind);
+            let u: Vec<f64> = coords.iter().map(|c| 2.0 * c.0 - 3.0 * c.1 + 1.0).collect();
+            for q in kind.quadrature(4) {
+                let iso = map(*kind, &coords, &q.point).unwrap();
+                let g = iso.grad.iter().zip(&u).fold(Vec2(0.0, 0.0), |a, (g, ui)| a + g.clone() * *ui);
+                assert!((g.0 - 2.0).abs() < 1e-12 && (g.1 + 3.0).abs() < 1e-12, "{:?}: {:?}", kind, g);
+                let ux: f64 = iso.n.iter().zip(&u).map(|(n, ui)| n * ui).sum();
+                assert!((ux - (2.0 * iso.x.0 - 3.0 * iso.x.1 + 1.0)).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn area_from_jacobian() {
+        for kind in KINDS.iter().filter(|k| k.dimension() == 2) {
+            let coords = distorted(*kind);
+            let area: f64 = kind.quadrature(2).iter()
+                .map(|q| map(*kind, &coords, &q.point).unwrap().det_j * q.weight)
+                .sum();
+            let expected = if kind.is_triangle() { 2.75 } else { 5.625 };
+            assert!((area - expected).abs() < 1e-12, "{:?}: {}", kind, area);
+        }
+    }
+
+    #[test]
+    fn inverted_element_is_rejected() {
+        let coords = vec![Vec2(0.0, 0.0), Vec2(0.0, 1.0), Vec2(1.0, 0.0)];
+        assert!(map(ElementKind::Tri3, &coords, &Vec2(0.3, 0.3)).is_err());
+    }
+
+    #[test]
+    fn inverse_map_finds_the_natural_point() {
+        for kind in KINDS.iter().filter(|k| k.dimension() == 2) {
+            let coords = distorted(*kind);
+            for xi in &[Vec2(0.2, 0.3), Vec2(0.6, 0.1), Vec2(-0.1, 0.4)] {
+                let x = map(*kind, &coords, xi).unwrap().x;
+                let found = inverse_map(*kind, &coords, &x).unwrap();
+                assert!((found - xi.clone()).length() < 1e-20, "{:?} {:?}", kind, xi);
+            }
+            assert_eq!(kind.outside(&Vec2(0.2, 0.3)), 0.0);
+            assert!((kind.outside(&Vec2(1.2, 0.0)) - 0.2).abs() < 1e-15);
+            assert_eq!(kind.outside(&kind.clamp(&Vec2(1.5, -0.5))), 0.0);
+        }
+        assert_eq!(ElementKind::Tri3.clamp(&Vec2(1.0, 1.0)), Vec2(0.5, 0.5));
+    }
+
+    #[test]
+    fn edge_length_and_normal() {
+        let coords = vec![Vec2(0.0, 0.0), Vec2(3.0, 4.0), Vec2(1.5, 2.0)];
+        let length: f64 = quadrature::line(2).iter()
+            .map(|q| map_edge(ElementKind::Line3, &coords, q.point.0).unwrap().det_j * q.weight)
+            .sum();
+        assert!((length - 5.0).abs() < 1e-12);
+        let e = map_edge(ElementKind::Line2, &coords[..2], 0.0).unwrap();
+        assert_eq!(e.x, Vec2(1.5, 2.0));
+        assert!((e.normal.0 - 0.8).abs() < 1e-15 && (e.normal.1 + 0.6).abs() < 1e-15);
+    }
+}
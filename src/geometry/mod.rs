@@ -14,10 +14,11 @@ use dxf::Drawing;
 use dxf::entities::*;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum GeometryObject {
     Segment { beg: Vec2, end: Vec2 },
     Circle { center: Vec2, radius: f64 },
+    // counterclockwise from `start`, the sweep in (0, 2 pi]
     Arc { center: Vec2, radius: f64, start: f64, sweep: f64},
     PolyLine { points: Vec<Vec2> }
 }
@@ -57,12 +58,7 @@ impl OptionalFrom<Entity> for GeometryObject {
                 })
             },
             EntityType::Arc(ref arc) => {
-                Some(GeometryObject::Arc {
-                    center: Vec2::from(&arc.center),
-                    radius: arc.radius,
-                    start: arc.start_angle.to_radians(),
-                    sweep: (arc.end_angle - arc.start_angle).to_radians()
-                })
+                Some(GeometryObject::dxf_arc(Vec2::from(&arc.center), arc.radius, arc.start_angle, arc.end_angle))
             },
             EntityType::Polyline(ref polyline) => {
                 Some(GeometryObject::PolyLine {
@@ -76,6 +72,14 @@ impl OptionalFrom<Entity> for GeometryObject {
     }
 }
 
+// A drawing object together with the DXF layer and handle it came from
+#[derive(Debug, Clone)]
+pub struct GeometryEntity {
+    pub object: GeometryObject,
+    pub layer: String,
+    pub handle: u32
+}
+
 use itertools::Itertools;
 
 impl GeometryObject  {
@@ -215,6 +219,83 @@ impl GeometryObject  {
         return bb;
     }
 
+    // Arc from DXF angles in degrees. DXF arcs always run counterclockwise,
+    // also when the end angle is below the start angle.
+    pub fn dxf_arc(center: Vec2, radius: f64, start_angle: f64, end_angle: f64) -> GeometryObject {
+        let two_pi = 2.0 * f64::consts::PI;
+        let sweep = (end_angle - start_angle).to_radians().rem_euclid(two_pi);
+        GeometryObject::Arc {
+            center,
+            radius,
+            start: start_angle.to_radians(),
+            sweep: if sweep > 0.0 { sweep } else { two_pi }
+        }
+    }
+
+    // Shortest distance from `p` to the curve
+    pub fn distance(&self, p: &Vec2) -> f64 {
+        let dist = |a: &Vec2, b: &Vec2| (a.clone() - b.clone()).dot(&(a.clone() - b.clone())).sqrt();
+        let to_segment = |a: &Vec2, b: &Vec2| {
+            let ab = b.clone() - a.clone();
+            let len2 = ab.dot(&ab);
+            let t = if len2 > 0.0 { ((p.clone() - a.clone()).dot(&ab) / len2).clamp(0.0, 1.0) } else { 0.0 };
+            dist(p, &(a.clone() + ab * t))
+        };
+        match self {
+            GeometryObject::Segment{ beg, end } => to_segment(beg, end),
+            GeometryObject::Circle{ center, radius } => (dist(p, center) - radius).abs(),
+            GeometryObject::Arc{ center, radius, start, sweep } => {
+                let d = p.clone() - center.clone();
+                let two_pi = 2.0 * f64::consts::PI;
+                // angle from the start point, counterclockwise
+                let along = (d.1.atan2(d.0) - start).rem_euclid(two_pi);
+                if along <= *sweep {
+                    (dist(p, center) - radius).abs()
+                } else {
+                    let beg = center.clone() + Vec2::from_angle(*start) * *radius;
+                    let end = center.clone() + Vec2::from_angle(start + sweep) * *radius;
+                    dist(p, &beg).min(dist(p, &end))
+                }
+            },
+            GeometryObject::PolyLine{ points } => {
+                match points.len() {
+                    0 => f64::INFINITY,
+                    1 => dist(p, &points[0]),
+                    _ => points.iter().tuple_windows::<(_, _)>()
+                        .map(|(a, b)| to_segment(a, b))
+                        .fold(f64::INFINITY, f64::min)
+                }
+            }
+        }
+    }
+
+    // Representative point of the object: the centre of a circle or arc (load
+    // points are usually marked with small circles), the midpoint of a segment
+    // and the mean vertex of a polyline
+    pub fn anchor(&self) -> Vec2 {
+        match self {
+            GeometryObject::Segment{ beg, end } => (beg.clone() + end.clone()) * 0.5,
+            GeometryObject::Circle{ center, .. } | GeometryObject::Arc{ center, .. } => center.clone(),
+            GeometryObject::PolyLine{ points } => {
+                let sum = points.iter().fold(Vec2(0.0, 0.0), |s, p| s + p.clone());
+                sum * (1.0 / points.len().max(1) as f64)
+            }
+        }
+    }
+
+    // Objects of a DXF file with their layers and handles
+    pub fn read_entities_from_file(file_name: &Path) -> Result<Vec<GeometryEntity>, String> {
+        let file = file_name.to_str().ok_or("invalid file name")?;
+        let dxf_drawing = Drawing::load_file(file).map_err(|e| format!("{:?}", e))?;
+        Ok(dxf_drawing.entities.iter()
+            .filter_map(|e| GeometryObject::from_op(e).map(|object| GeometryEntity {
+                object,
+                layer: e.common.layer.clone(),
+                handle: e.common.handle
+            }))
+            .collect())
+    }
+
     pub fn read_from_file(file_name: &Path) -> Result<Vec<GeometryObject>, String> {
         let dxf_drawing = Drawing::load_file(file_name.to_str().unwrap()).unwrap();
         Ok(dxf_drawing.entities.iter()
@@ -0,0 +1,15 @@
+extern crate piston_window;
+extern crate dxf;
+extern crate itertools;
+extern crate toml;
+extern crate serde_json;
+
+pub mod base_types;
+pub mod geometry;
+pub mod solvers;
+pub mod fem;
+pub mod meshing;
+pub mod analysis;
+pub mod materials;
+pub mod model;
+pub mod results;
@@ -1,11 +1,8 @@
 extern crate piston_window;
-extern crate dxf;
-extern crate itertools;
+extern crate fem_test;
 
-mod base_types;
-mod geometry;
 mod drawing {
-    pub use super::geometry::*;
+    pub use fem_test::geometry::*;
     use piston_window::*;
 
     pub struct Drawing {
@@ -41,9 +38,6 @@ mod drawing {
     }
 }
 
-mod meshing {
-    // TODO
-}
 
 use piston_window::*;
 use drawing::*;
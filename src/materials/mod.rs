@@ -0,0 +1,161 @@
+pub mod parse;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use analysis::*;
+
+// Physical properties of a material. Only the properties an analysis needs
+// have to be given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub elastic: Option<ElasticMaterial>,
+    pub density: Option<f64>,
+    pub conductivity: Option<f64>,
+    pub specific_heat: Option<f64>,
+    pub thermal_expansion: Option<f64>,
+    pub relative_permittivity: Option<f64>,
+    pub permeability: Option<Permeability>
+}
+
+impl Material {
+    pub fn new(name: &str) -> Material {
+        Material {
+            name: name.to_string(),
+            elastic: None,
+            density: None,
+            conductivity: None,
+            specific_heat: None,
+            thermal_expansion: None,
+            relative_permittivity: None,
+            permeability: None
+        }
+    }
+
+    fn required<T: Clone>(&self, value: &Option<T>, what: &str) -> Result<T, String> {
+        value.clone().ok_or_else(|| format!("material '{}' has no {}", self.name, what))
+    }
+
+    pub fn elastic(&self) -> Result<ElasticMaterial, String> { self.required(&self.elastic, "elastic constants") }
+    pub fn density(&self) -> Result<f64, String> { self.required(&self.density, "density") }
+    pub fn conductivity(&self) -> Result<f64, String> { self.required(&self.conductivity, "conductivity") }
+    pub fn specific_heat(&self) -> Result<f64, String> { self.required(&self.specific_heat, "specific heat") }
+    pub fn thermal_expansion(&self) -> Result<f64, String> {
+        self.required(&self.thermal_expansion, "thermal expansion coefficient")
+    }
+    pub fn relative_permittivity(&self) -> Result<f64, String> {
+        self.required(&self.relative_permittivity, "permittivity")
+    }
+    pub fn permeability(&self) -> Result<Permeability, String> { self.required(&self.permeability, "permeability") }
+}
+
+// Named materials plus the assignment of DXF layers or mesh regions to them.
+// A region without an explicit assignment uses the material of the same name,
+// so layers named after materials need no assignment at all.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialLibrary {
+    materials: HashMap<String, Material>,
+    assignments: HashMap<String, String>
+}
+
+impl MaterialLibrary {
+    pub fn new() -> MaterialLibrary {
+        Default::default()
+    }
+
+    // Library in the TOML or JSON format described in `parse`, chosen by the
+    // file extension
+    pub fn load_file(path: &Path) -> Result<MaterialLibrary, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => MaterialLibrary::from_toml(&text),
+            Some("json") => MaterialLibrary::from_json(&text),
+            _ => Err(format!("{}: unknown material library format", path.display()))
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<MaterialLibrary, String> {
+        parse::from_toml(text)
+    }
+
+    pub fn from_json(text: &str) -> Result<MaterialLibrary, String> {
+        parse::from_json(text)
+    }
+
+    pub fn add(&mut self, material: Material) {
+        self.materials.insert(material.name.clone(), material);
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Material, String> {
+        self.materials.get(name).ok_or_else(|| format!("unknown material '{}'", name))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.materials.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // Assigns a material to a DXF layer or mesh region
+    pub fn assign(&mut self, region: &str, material: &str) {
+        self.assignments.insert(region.to_string(), material.to_string());
+    }
+
+    pub fn material_for(&self, region: &str) -> Result<&Material, String> {
+        match self.assignments.get(region) {
+            Some(name) => self.get(name),
+            None => self.materials.get(region)
+                .ok_or_else(|| format!("no material assigned to layer or region '{}'", region))
+        }
+    }
+
+    // Layers or regions of `names` that resolve to no material
+    pub fn unassigned(&self, names: &[String]) -> Vec<String> {
+        names.iter().filter(|n| self.material_for(n).is_err()).cloned().collect()
+    }
+
+    pub fn apply_elasticity(&self, problem: &mut ElasticityProblem) -> Result<(), String> {
+        for region in problem.mesh().regions() {
+            let material = self.material_for(&region)?;
+            problem.set_material(&region, material.elastic()?);
+            if let Some(rho) = material.density {
+                problem.set_density(&region, rho);
+            }
+            if let Some(alpha) = material.thermal_expansion {
+                problem.set_thermal_expansion(&region, alpha);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_heat(&self, problem: &mut HeatProblem) -> Result<(), String> {
+        for region in problem.field().mesh().regions() {
+            problem.set_conductivity(&region, self.material_for(&region)?.conductivity()?);
+        }
+        Ok(())
+    }
+
+    pub fn apply_transient_heat(&self, problem: &mut TransientHeatProblem) -> Result<(), String> {
+        for region in problem.mesh().regions() {
+            let material = self.material_for(&region)?;
+            problem.set_conductivity(&region, material.conductivity()?);
+            problem.set_heat_capacity(&region, material.density()?, material.specific_heat()?);
+        }
+        Ok(())
+    }
+
+    pub fn apply_electrostatics(&self, problem: &mut ElectrostaticProblem) -> Result<(), String> {
+        for region in problem.field().mesh().regions() {
+            problem.set_relative_permittivity(&region, self.material_for(&region)?.relative_permittivity()?);
+        }
+        Ok(())
+    }
+
+    pub fn apply_magnetostatics(&self, problem: &mut MagnetostaticProblem) -> Result<(), String> {
+        for region in problem.mesh().regions() {
+            problem.set_permeability(&region, self.material_for(&region)?.permeability()?);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+// Material library files. TOML and JSON share one layout:
+//
+//   [materials.steel]
+//   density = 7850.0
+//   conductivity = 45.0
+//   specific_heat = 460.0
+//   thermal_expansion = 1.2e-5
+//   relative_permittivity = 1.0
+//   elastic = { young = 200e9, poisson = 0.3 }
+//
+//   [materials.cfrp.elastic]
+//   e1 = 140e9
+//   e2 = 10e9
+//   nu12 = 0.3
+//   g12 = 5e9
+//   angle = 45.0            # degrees, e3, nu13 and nu23 default to e2, nu12, nu12
+//
+//   [materials.iron]
+//   bh_curve = [[100.0, 0.6], [200.0, 1.0], [500.0, 1.4]]   # (H, B)
+//   # or relative_permeability = 1000.0
+//
+//   [assignments]
+//   PLATE = "steel"         # DXF layer or mesh region -> material
+
+use serde_json::{self, Map, Value};
+use toml;
+use analysis::{ElasticMaterial, Permeability, BhCurve};
+use super::{Material, MaterialLibrary};
+
+pub fn from_toml(text: &str) -> Result<MaterialLibrary, String> {
+    let value: toml::Value = toml::from_str(text).map_err(|e| format!("material library: {}", e))?;
+    let value = serde_json::to_value(&value).map_err(|e| format!("material library: {}", e))?;
+    library(&value)
+}
+
+pub fn from_json(text: &str) -> Result<MaterialLibrary, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("material library: {}", e))?;
+    library(&value)
+}
+
+fn table<'v>(value: &'v Value, context: &str) -> Result<&'v Map<String, Value>, String> {
+    value.as_object().ok_or_else(|| format!("{}: expected a table", context))
+}
+
+fn number(value: &Value, context: &str) -> Result<f64, String> {
+    value.as_f64().ok_or_else(|| format!("{}: expected a number", context))
+}
+
+fn library(value: &Value) -> Result<MaterialLibrary, String> {
+    let mut lib = MaterialLibrary::new();
+    for (key, v) in table(value, "material library")? {
+        match key.as_str() {
+            "materials" => {
+                for (name, m) in table(v, "materials")? {
+                    lib.add(material(name, m)?);
+                }
+            },
+            "assignments" => {
+                for (region, m) in table(v, "assignments")? {
+                    let name = m.as_str()
+                        .ok_or_else(|| format!("assignments.{}: expected a material name", region))?;
+                    lib.assign(region, name);
+                }
+            },
+            _ => return Err(format!("material library: unknown section '{}'", key))
+        }
+    }
+    // catch typos in assignments right away
+    for (region, name) in &lib.assignments {
+        lib.get(name).map_err(|e| format!("assignments.{}: {}", region, e))?;
+    }
+    Ok(lib)
+}
+
+fn material(name: &str, value: &Value) -> Result<Material, String> {
+    let mut m = Material::new(name);
+    for (key, v) in table(value, name)? {
+        let context = format!("{}.{}", name, key);
+        match key.as_str() {
+            "density" => m.density = Some(number(v, &context)?),
+            "conductivity" => m.conductivity = Some(number(v, &context)?),
+            "specific_heat" => m.specific_heat = Some(number(v, &context)?),
+            "thermal_expansion" => m.thermal_expansion = Some(number(v, &context)?),
+            "relative_permittivity" => m.relative_permittivity = Some(number(v, &context)?),
+            "relative_permeability" => m.permeability = Some(Permeability::Relative(number(v, &context)?)),
+            "bh_curve" => m.permeability = Some(Permeability::Curve(bh_curve(v, &context)?)),
+            "elastic" => m.elastic = Some(elastic(v, &context)?),
+            _ => return Err(format!("{}: unknown property", context))
+        }
+    }
+    Ok(m)
+}
+
+fn elastic(value: &Value, context: &str) -> Result<ElasticMaterial, String> {
+    let t = table(value, context)?;
+    let get = |key: &str| -> Result<Option<f64>, String> {
+        match t.get(key) {
+            Some(v) => number(v, &format!("{}.{}", context, key)).map(Some),
+            None => Ok(None)
+        }
+    };
+    let need = |key: &str| -> Result<f64, String> {
+        get(key)?.ok_or_else(|| format!("{}: missing '{}'", context, key))
+    };
+
+    let known: &[&str] = if t.contains_key("young") {
+        &["young", "poisson"]
+    } else {
+        &["e1", "e2", "e3", "nu12", "nu13", "nu23", "g12", "angle"]
+    };
+    if let Some(key) = t.keys().find(|k| !known.contains(&k.as_str())) {
+        return Err(format!("{}: unexpected '{}'", context, key));
+    }
+
+    if t.contains_key("young") {
+        Ok(ElasticMaterial::Isotropic { young: need("young")?, poisson: need("poisson")? })
+    } else {
+        let (e2, nu12) = (need("e2")?, need("nu12")?);
+        Ok(ElasticMaterial::Orthotropic {
+            e1: need("e1")?,
+            e2,
+            e3: get("e3")?.unwrap_or(e2),
+            nu12,
+            nu13: get("nu13")?.unwrap_or(nu12),
+            nu23: get("nu23")?.unwrap_or(nu12),
+            g12: need("g12")?,
+            angle: get("angle")?.unwrap_or(0.0).to_radians()
+        })
+    }
+}
+
+fn bh_curve(value: &Value, context: &str) -> Result<BhCurve, String> {
+    let points = value.as_array().ok_or_else(|| format!("{}: expected a list of [H, B] pairs", context))?;
+    let points = points.iter().map(|p| {
+        match p.as_array().map(|a| a.as_slice()) {
+            Some([h, b]) => Ok((number(h, context)?, number(b, context)?)),
+            _ => Err(format!("{}: expected a list of [H, B] pairs", context))
+        }
+    }).collect::<Result<Vec<_>, String>>()?;
+    BhCurve::new(points).map_err(|e| format!("{}: {}", context, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::FRAC_PI_4;
+
+    const TOML: &str = r#"
+        [materials.steel]
+        density = 7850
+        conductivity = 45.0
+        specific_heat = 460.0
+        elastic = { young = 200e9, poisson = 0.3 }
+
+        [materials.cfrp.elastic]
+        e1 = 140e9
+        e2 = 10e9
+        nu12 = 0.3
+        g12 = 5e9
+        angle = 45.0
+
+        [materials.iron]
+        bh_curve = [[100.0, 0.6], [200.0, 1.0]]
+
+        [assignments]
+        PLATE = "steel"
+    "#;
+
+    const JSON: &str = r#"{
+        "materials": {
+            "steel": { "density": 7850, "conductivity": 45.0, "specific_heat": 460.0,
+                       "elastic": { "young": 200e9, "poisson": 0.3 } },
+            "cfrp": { "elastic": { "e1": 140e9, "e2": 10e9, "nu12": 0.3, "g12": 5e9, "angle": 45.0 } },
+            "iron": { "bh_curve": [[100.0, 0.6], [200.0, 1.0]] }
+        },
+        "assignments": { "PLATE": "steel" }
+    }"#;
+
+    #[test]
+    fn toml_and_json_agree() {
+        let a = from_toml(TOML).unwrap();
+        let b = from_json(JSON).unwrap();
+        assert_eq!(a.names(), vec!["cfrp", "iron", "steel"]);
+        for name in a.names() {
+            assert_eq!(a.get(&name).unwrap(), b.get(&name).unwrap());
+        }
+
+        let steel = a.material_for("PLATE").unwrap();
+        assert_eq!(steel.name, "steel");
+        assert_eq!(steel.density, Some(7850.0));
+        assert_eq!(steel.elastic().unwrap(), ElasticMaterial::Isotropic { young: 200e9, poisson: 0.3 });
+        assert!(steel.relative_permittivity().is_err());
+
+        match a.get("cfrp").unwrap().elastic().unwrap() {
+            ElasticMaterial::Orthotropic { e3, nu23, angle, .. } => {
+                assert_eq!((e3, nu23), (10e9, 0.3));
+                assert!((angle - FRAC_PI_4).abs() < 1e-15);
+            },
+            _ => panic!("expected an orthotropic material")
+        }
+        assert!(a.get("iron").unwrap().permeability().is_ok());
+    }
+
+    #[test]
+    fn errors_name_the_culprit() {
+        let err = |text: &str| from_toml(text).unwrap_err();
+        assert!(err("[materials.steel]\ndensty = 1.0").contains("steel.densty"));
+        assert!(err("[materials.steel]\ndensity = \"heavy\"").contains("steel.density"));
+        assert!(err("[materials.steel.elastic]\nyoung = 1.0").contains("poisson"));
+        assert!(err("[materials.steel.elastic]\nyoung = 1.0\npoisson = 0.3\ne1 = 2.0").contains("e1"));
+        assert!(err("[assignments]\nPLATE = \"stel\"").contains("stel"));
+        assert!(err("[materials.iron]\nbh_curve = [[1.0]]").contains("iron.bh_curve"));
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use base_types::*;
+use fem::shape;
+use super::Mesh;
+
+// Point location for repeated queries on one mesh: a uniform grid of element
+// bounding boxes gives the candidates, and a walk across element edges
+// continues from a nearby element, e.g. the one of the previous point.
+pub struct PointLocator<'m> {
+    mesh: &'m Mesh,
+    origin: Vec2,
+    cell: Vec2,
+    nx: usize,
+    ny: usize,
+    // elements whose bounding box overlaps a grid cell
+    cells: Vec<Vec<usize>>,
+    // element across every corner edge, None on the boundary
+    neighbours: Vec<Vec<Option<usize>>>
+}
+
+// A point of a sampled polyline
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineSample {
+    // Arc length from the first polyline point
+    pub distance: f64,
+    pub point: Vec2,
+    // None outside the mesh
+    pub value: Option<f64>
+}
+
+impl<'m> PointLocator<'m> {
+    pub fn new(mesh: &'m Mesh) -> PointLocator<'m> {
+        let boxes: Vec<(Vec2, Vec2)> = mesh.elements.iter().map(|e| {
+            let coords = mesh.coords(&e.nodes);
+            let min = coords.iter().fold(Vec2(f64::MAX, f64::MAX), |m, p| Vec2(m.0.min(p.0), m.1.min(p.1)));
+            let max = coords.iter().fold(Vec2(f64::MIN, f64::MIN), |m, p| Vec2(m.0.max(p.0), m.1.max(p.1)));
+            // curved edges may bulge out of the box of the nodes
+            let size = (max.0 - min.0).max(max.1 - min.1);
+            let pad = if e.kind.is_quadratic() { 0.1 * size } else { 1e-9 * size };
+            (min - Vec2(pad, pad), max + Vec2(pad, pad))
+        }).collect();
+        let min = boxes.iter().fold(Vec2(f64::MAX, f64::MAX), |m, b| Vec2(m.0.min(b.0 .0), m.1.min(b.0 .1)));
+        let max = boxes.iter().fold(Vec2(f64::MIN, f64::MIN), |m, b| Vec2(m.0.max(b.1 .0), m.1.max(b.1 .1)));
+
+        // about two elements per cell
+        let (w, h) = ((max.0 - min.0).max(f64::MIN_POSITIVE), (max.1 - min.1).max(f64::MIN_POSITIVE));
+        let count = (boxes.len() as f64 / 2.0).max(1.0);
+        let nx = ((count * w / h).sqrt().ceil() as usize).clamp(1, boxes.len().max(1));
+        let ny = ((count * h / w).sqrt().ceil() as usize).clamp(1, boxes.len().max(1));
+        let mut locator = PointLocator {
+            mesh,
+            cell: Vec2(w / nx as f64, h / ny as f64),
+            origin: min,
+            nx, ny,
+            cells: vec![Vec::new(); nx * ny],
+            neighbours: Vec::with_capacity(mesh.elements.len())
+        };
+        for (i, (lo, hi)) in boxes.iter().enumerate() {
+            let (x0, y0) = locator.cell_of(lo);
+            let (x1, y1) = locator.cell_of(hi);
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    locator.cells[y * nx + x].push(i);
+                }
+            }
+        }
+
+        let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (i, e) in mesh.elements.iter().enumerate() {
+            let nc = e.kind.corner_count();
+            for k in 0..nc {
+                let (a, b) = (e.nodes[k], e.nodes[(k + 1) % nc]);
+                edges.entry((a.min(b), a.max(b))).or_default().push(i);
+            }
+        }
+        for (i, e) in mesh.elements.iter().enumerate() {
+            let nc = e.kind.corner_count();
+            locator.neighbours.push((0..nc).map(|k| {
+                let (a, b) = (e.nodes[k], e.nodes[(k + 1) % nc]);
+                edges[&(a.min(b), a.max(b))].iter().cloned().find(|&j| j != i)
+            }).collect());
+        }
+        locator
+    }
+
+    pub fn mesh(&self) -> &'m Mesh { self.mesh }
+
+    // Grid cell of a point, clamped to the grid
+    fn cell_of(&self, p: &Vec2) -> (usize, usize) {
+        let index = |v: f64, o: f64, s: f64, n: usize| (((v - o) / s).floor().max(0.0) as usize).min(n - 1);
+        (index(p.0, self.origin.0, self.cell.0, self.nx), index(p.1, self.origin.1, self.cell.1, self.ny))
+    }
+
+    fn inside_grid(&self, p: &Vec2) -> bool {
+        let max = Vec2(self.origin.0 + self.cell.0 * self.nx as f64, self.origin.1 + self.cell.1 * self.ny as f64);
+        p.0 >= self.origin.0 && p.1 >= self.origin.1 && p.0 <= max.0 && p.1 <= max.1
+    }
+
+    // Natural coordinates of `p` in element `i` if it lies inside
+    fn inside(&self, i: usize, p: &Vec2) -> Option<Vec2> {
+        let e = &self.mesh.elements[i];
+        let xi = shape::inverse_map(e.kind, &self.mesh.coords(&e.nodes), p).ok()?;
+        if e.kind.outside(&xi) < 1e-9 { Some(xi) } else { None }
+    }
+
+    // Element containing `p` and the natural coordinates of `p` in it
+    pub fn locate(&self, p: &Vec2) -> Option<(usize, Vec2)> {
+        if self.mesh.elements.is_empty() || !self.inside_grid(p) {
+            return None;
+        }
+        let (x, y) = self.cell_of(p);
+        self.cells[y * self.nx + x].iter().filter_map(|&i| self.inside(i, p).map(|xi| (i, xi))).next()
+    }
+
+    // As `locate`, walking across the element edges from element `start`
+    // towards `p` first. Cheap where consecutive points are close together.
+    pub fn locate_from(&self, start: usize, p: &Vec2) -> Option<(usize, Vec2)> {
+        let mut current = start;
+        for _ in 0..self.mesh.elements.len().min(1000) {
+            if let Some(xi) = self.inside(current, p) {
+                return Some((current, xi));
+            }
+            // leave through a corner edge that has `p` on its outer side
+            let e = &self.mesh.elements[current];
+            let nc = e.kind.corner_count();
+            let exit = (0..nc).filter(|&k| {
+                let a = &self.mesh.nodes[e.nodes[k]];
+                let b = &self.mesh.nodes[e.nodes[(k + 1) % nc]];
+                (b.clone() - a.clone()).cross(&(p.clone() - a.clone())) < 0.0
+            }).find_map(|k| self.neighbours[current][k]);
+            match exit {
+                Some(next) => current = next,
+                None => break
+            }
+        }
+        self.locate(p)
+    }
+
+    // Element closest to `p` and the natural coordinates of the closest point
+    // of it, for points outside the mesh by at most `tolerance` as well
+    pub fn closest(&self, p: &Vec2, tolerance: f64) -> Option<(usize, Vec2)> {
+        if let Some(found) = self.locate(p) {
+            return Some(found);
+        }
+        if self.mesh.elements.is_empty() {
+            return None;
+        }
+        let (x0, y0) = self.cell_of(&(p.clone() - Vec2(tolerance, tolerance)));
+        let (x1, y1) = self.cell_of(&(p.clone() + Vec2(tolerance, tolerance)));
+        let mut best: Option<(f64, usize, Vec2)> = None;
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                for &i in &self.cells[y * self.nx + x] {
+                    let e = &self.mesh.elements[i];
+                    let coords = self.mesh.coords(&e.nodes);
+                    let xi = match shape::inverse_map(e.kind, &coords, p) {
+                        Ok(xi) => e.kind.clamp(&xi),
+                        Err(_) => continue
+                    };
+                    let n = e.kind.shape_functions(&xi);
+                    let q = n.iter().zip(&coords).fold(Vec2(0.0, 0.0), |acc, (n, c)| acc + c.clone() * *n);
+                    let distance = (q - p.clone()).length().sqrt();
+                    if distance <= tolerance && best.as_ref().map_or(true, |b| distance < b.0) {
+                        best = Some((distance, i, xi));
+                    }
+                }
+            }
+        }
+        best.map(|b| (b.1, b.2))
+    }
+
+    fn interpolate_in(&self, values: &[f64], element: usize, xi: &Vec2) -> f64 {
+        let e = &self.mesh.elements[element];
+        e.kind.shape_functions(xi).iter().zip(&e.nodes).map(|(n, &a)| n * values[a]).sum()
+    }
+
+    // Nodal `values` at `p`, for points outside the mesh by at most
+    // `tolerance` as well
+    pub fn probe(&self, values: &[f64], p: &Vec2, tolerance: f64) -> Option<f64> {
+        self.closest(p, tolerance).map(|(e, xi)| self.interpolate_in(values, e, &xi))
+    }
+
+    // Nodal `values` at `samples` points evenly spaced along a polyline, for
+    // line plots
+    pub fn sample_polyline(&self, values: &[f64], polyline: &[Vec2], samples: usize, tolerance: f64)
+        -> Result<Vec<LineSample>, String> {
+        if polyline.len() < 2 || samples < 2 {
+            return Err("a polyline needs two points and two samples at least".to_string());
+        }
+        if values.len() != self.mesh.node_count() {
+            return Err(format!("{} values for a mesh of {} nodes", values.len(), self.mesh.node_count()));
+        }
+        let lengths: Vec<f64> = polyline.windows(2).map(|w| (w[1].clone() - w[0].clone()).length().sqrt()).collect();
+        let total: f64 = lengths.iter().sum();
+        let mut result = Vec::with_capacity(samples);
+        let (mut segment, mut start) = (0, 0.0);
+        let mut last: Option<usize> = None;
+        for k in 0..samples {
+            let distance = total * k as f64 / (samples - 1) as f64;
+            while segment + 1 < lengths.len() && distance > start + lengths[segment] {
+                start += lengths[segment];
+                segment += 1;
+            }
+            let t = if lengths[segment] > 0.0 { ((distance - start) / lengths[segment]).min(1.0) } else { 0.0 };
+            let point = polyline[segment].clone() + (polyline[segment + 1].clone() - polyline[segment].clone()) * t;
+            let found = match last {
+                Some(e) => self.locate_from(e, &point).or_else(|| self.closest(&point, tolerance)),
+                None => self.closest(&point, tolerance)
+            };
+            last = found.as_ref().map(|f| f.0).or(last);
+            let value = found.map(|(e, xi)| self.interpolate_in(values, e, &xi));
+            result.push(LineSample { distance, point, value });
+        }
+        Ok(result)
+    }
+
+    // Nodal `values` of this mesh interpolated at the nodes of `target`.
+    // Target nodes outside this mesh by more than `tolerance` are an error.
+    pub fn transfer(&self, values: &[f64], target: &Mesh, tolerance: f64) -> Result<Vec<f64>, String> {
+        if values.len() != self.mesh.node_count() {
+            return Err(format!("{} values for a mesh of {} nodes", values.len(), self.mesh.node_count()));
+        }
+        let mut last: Option<usize> = None;
+        let mut mapped = Vec::with_capacity(target.node_count());
+        for p in &target.nodes {
+            let found = match last {
+                Some(e) => self.locate_from(e, p).or_else(|| self.closest(p, tolerance)),
+                None => self.closest(p, tolerance)
+            };
+            let (e, xi) = found.ok_or_else(|| format!("node at {:?} is outside the source mesh", p))?;
+            mapped.push(self.interpolate_in(values, e, &xi));
+            last = Some(e);
+        }
+        Ok(mapped)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing::rectangle;
+
+    // Whether any element holds `p`, by trying them all
+    fn contained(mesh: &Mesh, p: &Vec2) -> bool {
+        mesh.elements.iter().any(|e| shape::inverse_map(e.kind, &mesh.coords(&e.nodes), p)
+            .map(|xi| e.kind.outside(&xi) < 1e-9).unwrap_or(false))
+    }
+
+    fn linear(p: &Vec2) -> f64 {
+        3.0 * p.0 - 2.0 * p.1 + 1.0
+    }
+
+    #[test]
+    fn locate_agrees_with_the_mesh() {
+        for kind in [ElementKind::Tri3, ElementKind::Quad4, ElementKind::Quad8].iter() {
+            let mesh = rectangle(Vec2(-1.0, 0.0), Vec2(3.0, 2.0), 12, 7, *kind, "plate");
+            let locator = PointLocator::new(&mesh);
+            for k in 0..50 {
+                // a pseudo-random spread over and beyond the mesh
+                let p = Vec2(-1.2 + 3.4 * ((k * 37 % 50) as f64 / 49.0), -0.1 + 2.2 * ((k * 13 % 50) as f64 / 49.0));
+                let expected = contained(&mesh, &p);
+                let found = locator.locate(&p);
+                let walked = locator.locate_from(0, &p);
+                assert_eq!(found.is_some(), expected, "{:?}", p);
+                assert_eq!(walked.is_some(), expected, "{:?}", p);
+                for (e, xi) in found.iter().chain(walked.iter()) {
+                    let el = &mesh.elements[*e];
+                    let x = el.kind.shape_functions(xi).iter().zip(&el.nodes)
+                        .fold(Vec2(0.0, 0.0), |acc, (n, &a)| acc + mesh.nodes[a].clone() * *n);
+                    assert!((x - p.clone()).length().sqrt() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn probe_and_sample() {
+        let mesh = rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 6, 3, ElementKind::Tri6, "plate");
+        let values: Vec<f64> = mesh.nodes.iter().map(linear).collect();
+        let locator = PointLocator::new(&mesh);
+        assert!((locator.probe(&values, &Vec2(0.3, 0.7), 0.0).unwrap() - linear(&Vec2(0.3, 0.7))).abs() < 1e-12);
+        // just outside
+        assert!((locator.probe(&values, &Vec2(2.0 + 1e-7, 0.5), 1e-6).unwrap() - linear(&Vec2(2.0, 0.5))).abs() < 1e-6);
+        assert_eq!(locator.probe(&values, &Vec2(2.5, 0.5), 1e-6), None);
+        // quadratic fields are reproduced exactly
+        let quadratic: Vec<f64> = mesh.nodes.iter().map(|p| p.0 * p.0 - 2.0 * p.0 * p.1).collect();
+        assert!((locator.probe(&quadratic, &Vec2(1.3, 0.6), 0.0).unwrap() - (1.69 - 1.56)).abs() < 1e-12);
+
+        // a line plot leaving the mesh
+        let polyline = [Vec2(0.0, 0.0), Vec2(2.0, 1.0), Vec2(3.0, 1.0)];
+        let samples = locator.sample_polyline(&values, &polyline, 11, 1e-9).unwrap();
+        assert_eq!(samples.len(), 11);
+        let total = 5.0f64.sqrt() + 1.0;
+        assert!((samples[10].distance - total).abs() < 1e-12);
+        for s in &samples {
+            match s.value {
+                Some(v) => assert!((v - linear(&s.point)).abs() < 1e-9),
+                None => assert!(s.point.0 > 2.0)
+            }
+        }
+        assert!(samples.iter().filter(|s| s.value.is_none()).count() >= 2);
+        assert!(locator.sample_polyline(&values, &polyline[..1], 11, 1e-9).is_err());
+    }
+
+    #[test]
+    fn transfer_between_meshes() {
+        let source = rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 4, 2, ElementKind::Quad8, "plate");
+        let target = rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 7, 5, ElementKind::Tri3, "plate");
+        // quadratic fields are reproduced by the quadratic source mesh
+        let field = |p: &Vec2| p.0 * p.0 + p.1 * p.0 - p.1;
+        let values: Vec<f64> = source.nodes.iter().map(field).collect();
+        let mapped = PointLocator::new(&source).transfer(&values, &target, 1e-9).unwrap();
+        for (p, v) in target.nodes.iter().zip(&mapped) {
+            assert!((v - field(p)).abs() < 1e-9, "{:?}", p);
+        }
+        let larger = rectangle(Vec2(0.0, 0.0), Vec2(2.5, 1.0), 5, 2, ElementKind::Quad4, "plate");
+        assert!(PointLocator::new(&source).transfer(&values, &larger, 1e-9).is_err());
+    }
+}
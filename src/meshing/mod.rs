@@ -0,0 +1,137 @@
+pub mod structured;
+pub mod locate;
+pub mod unstructured;
+
+pub use self::structured::rectangle;
+pub use self::locate::PointLocator;
+pub use self::unstructured::triangulate;
+
+use std::collections::BTreeSet;
+use base_types::Vec2;
+use fem::shape::ElementKind;
+
+#[derive(Debug, Clone)]
+pub struct Element {
+    pub kind: ElementKind,
+    pub nodes: Vec<usize>,
+    // Region tag (e.g. the DXF layer the element was meshed from)
+    pub region: String
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundaryEdge {
+    // Line2 or Line3, oriented so that the domain lies on the left
+    pub kind: ElementKind,
+    pub nodes: Vec<usize>,
+    pub tag: String
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub nodes: Vec<Vec2>,
+    pub elements: Vec<Element>,
+    pub boundary: Vec<BoundaryEdge>
+}
+
+impl Mesh {
+    pub fn new() -> Mesh {
+        Mesh::default()
+    }
+
+    pub fn add_node(&mut self, p: Vec2) -> usize {
+        self.nodes.push(p);
+        self.nodes.len() - 1
+    }
+
+    pub fn add_element(&mut self, kind: ElementKind, nodes: Vec<usize>, region: &str) -> usize {
+        assert_eq!(kind.dimension(), 2);
+        assert_eq!(nodes.len(), kind.node_count());
+        self.elements.push(Element { kind, nodes, region: region.to_string() });
+        self.elements.len() - 1
+    }
+
+    pub fn add_boundary_edge(&mut self, kind: ElementKind, nodes: Vec<usize>, tag: &str) {
+        assert_eq!(kind.dimension(), 1);
+        assert_eq!(nodes.len(), kind.node_count());
+        self.boundary.push(BoundaryEdge { kind, nodes, tag: tag.to_string() });
+    }
+
+    // Adds the nodes, elements and boundary edges of `other` without merging
+    // coincident nodes, e.g. for parts tied or in contact. The tags of its
+    // boundary edges get `tag_prefix` in front.
+    pub fn append(&mut self, other: &Mesh, tag_prefix: &str) {
+        let offset = self.nodes.len();
+        self.nodes.extend(other.nodes.iter().cloned());
+        for e in &other.elements {
+            self.add_element(e.kind, e.nodes.iter().map(|n| n + offset).collect(), &e.region);
+        }
+        for b in &other.boundary {
+            self.add_boundary_edge(b.kind, b.nodes.iter().map(|n| n + offset).collect(),
+                                   &format!("{}{}", tag_prefix, b.tag));
+        }
+    }
+
+    pub fn node_count(&self) -> usize { self.nodes.len() }
+
+    pub fn coords(&self, nodes: &[usize]) -> Vec<Vec2> {
+        nodes.iter().map(|&n| self.nodes[n].clone()).collect()
+    }
+
+    pub fn boundary_with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a BoundaryEdge> + 'a {
+        self.boundary.iter().filter(move |e| e.tag == tag)
+    }
+
+    // Fails for a tag no boundary edge carries, so that a misspelt boundary
+    // condition is not silently dropped
+    pub fn check_tag(&self, tag: &str) -> Result<(), String> {
+        if self.boundary_with_tag(tag).next().is_none() {
+            return Err(format!("no boundary edges tagged '{}'", tag));
+        }
+        Ok(())
+    }
+
+    // Sorted, unique nodes of all boundary edges carrying `tag`
+    pub fn boundary_nodes(&self, tag: &str) -> Vec<usize> {
+        self.boundary_with_tag(tag)
+            .flat_map(|e| e.nodes.iter().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn regions(&self) -> Vec<String> {
+        self.elements.iter()
+            .map(|e| e.region.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn boundary_tags(&self) -> Vec<String> {
+        self.boundary.iter()
+            .map(|e| e.tag.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn append_and_tags() {
+        let mut mesh = rectangle(Vec2(0.0, 0.0), Vec2(1.0, 1.0), 2, 1, ElementKind::Quad4, "base");
+        let upper = rectangle(Vec2(0.0, 1.0), Vec2(1.0, 1.0), 3, 1, ElementKind::Tri3, "upper");
+        mesh.append(&upper, "upper_");
+        assert_eq!(mesh.node_count(), 6 + 8);
+        assert_eq!(mesh.elements.len(), 2 + 6);
+        assert_eq!(mesh.regions(), vec!["base".to_string(), "upper".to_string()]);
+        let bottom: Vec<Vec2> = mesh.boundary_nodes("upper_bottom").iter().map(|&n| mesh.nodes[n].clone()).collect();
+        assert_eq!(bottom.len(), 4);
+        assert!(bottom.iter().all(|p| p.1 == 1.0));
+        assert!(mesh.check_tag("upper_top").is_ok() && mesh.check_tag("top").is_ok());
+        assert!(mesh.check_tag("middle").is_err());
+    }
+}
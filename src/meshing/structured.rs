@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use base_types::Vec2;
+use fem::shape::ElementKind;
+use super::Mesh;
+
+// Structured mesh of the axis-aligned rectangle [origin, origin + size] with
+// nx by ny cells. Triangles split every cell along its rising diagonal. The
+// sides are tagged "bottom", "right", "top" and "left".
+pub fn rectangle(origin: Vec2, size: Vec2, nx: usize, ny: usize,
+                 kind: ElementKind, region: &str) -> Mesh {
+    assert!(nx > 0 && ny > 0);
+    assert_eq!(kind.dimension(), 2);
+    let p = kind.order();
+    let (gx, gy) = (nx * p, ny * p);
+
+    let mut mesh = Mesh::new();
+    let mut index = HashMap::new();
+    for j in 0..gy + 1 {
+        for i in 0..gx + 1 {
+            // serendipity elements have no centre node
+            if kind == ElementKind::Quad8 && i % 2 == 1 && j % 2 == 1 {
+                continue;
+            }
+            let x = origin.0 + size.0 * i as f64 / gx as f64;
+            let y = origin.1 + size.1 * j as f64 / gy as f64;
+            index.insert((i, j), mesh.add_node(Vec2(x, y)));
+        }
+    }
+    let node = |i: usize, j: usize| index[&(i, j)];
+
+    for cy in 0..ny {
+        for cx in 0..nx {
+            let (i, j) = (cx * p, cy * p);
+            match kind {
+                ElementKind::Quad4 => {
+                    mesh.add_element(kind, vec![node(i, j), node(i + 1, j),
+                                                node(i + 1, j + 1), node(i, j + 1)], region);
+                },
+                ElementKind::Quad8 | ElementKind::Quad9 => {
+                    let mut nodes = vec![node(i, j), node(i + 2, j), node(i + 2, j + 2), node(i, j + 2),
+                                         node(i + 1, j), node(i + 2, j + 1), node(i + 1, j + 2), node(i, j + 1)];
+                    if kind == ElementKind::Quad9 {
+                        nodes.push(node(i + 1, j + 1));
+                    }
+                    mesh.add_element(kind, nodes, region);
+                },
+                ElementKind::Tri3 => {
+                    mesh.add_element(kind, vec![node(i, j), node(i + 1, j), node(i + 1, j + 1)], region);
+                    mesh.add_element(kind, vec![node(i, j), node(i + 1, j + 1), node(i, j + 1)], region);
+                },
+                ElementKind::Tri6 => {
+                    mesh.add_element(kind, vec![node(i, j), node(i + 2, j), node(i + 2, j + 2),
+                                                node(i + 1, j), node(i + 2, j + 1), node(i + 1, j + 1)], region);
+                    mesh.add_element(kind, vec![node(i, j), node(i + 2, j + 2), node(i, j + 2),
+                                                node(i + 1, j + 1), node(i + 1, j + 2), node(i, j + 1)], region);
+                },
+                _ => unreachable!()
+            }
+        }
+    }
+
+    // boundary edges, counter-clockwise around the rectangle
+    let edge_kind = kind.edge_kind();
+    let mut add_edge = |a: (usize, usize), b: (usize, usize), tag: &str| {
+        let mut nodes = vec![node(a.0, a.1), node(b.0, b.1)];
+        if p == 2 {
+            nodes.push(node((a.0 + b.0) / 2, (a.1 + b.1) / 2));
+        }
+        mesh.add_boundary_edge(edge_kind, nodes, tag);
+    };
+    for c in 0..nx {
+        add_edge((c * p, 0), ((c + 1) * p, 0), "bottom");
+    }
+    for c in 0..ny {
+        add_edge((gx, c * p), (gx, (c + 1) * p), "right");
+    }
+    for c in (0..nx).rev() {
+        add_edge(((c + 1) * p, gy), (c * p, gy), "top");
+    }
+    for c in (0..ny).rev() {
+        add_edge((0, (c + 1) * p), (0, c * p), "left");
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape;
+
+    #[test]
+    fn counts_and_orientation() {
+        let cases = [(ElementKind::Tri3, 12, 12), (ElementKind::Tri6, 35, 12),
+                     (ElementKind::Quad4, 12, 6), (ElementKind::Quad8, 29, 6),
+                     (ElementKind::Quad9, 35, 6)];
+        for &(kind, nodes, elements) in cases.iter() {
+            let mesh = rectangle(Vec2(0.0, 0.0), Vec2(3.0, 2.0), 3, 2, kind, "part");
+            assert_eq!(mesh.node_count(), nodes, "{:?}", kind);
+            assert_eq!(mesh.elements.len(), elements, "{:?}", kind);
+            assert_eq!(mesh.boundary.len(), 10);
+            assert_eq!(mesh.boundary_nodes("left").len(), 2 * kind.order() + 1);
+
+            let mut area = 0.0;
+            for e in &mesh.elements {
+                let coords = mesh.coords(&e.nodes);
+                for q in e.kind.quadrature(2) {
+                    area += shape::map(e.kind, &coords, &q.point).unwrap().det_j * q.weight;
+                }
+            }
+            assert!((area - 6.0).abs() < 1e-12);
+        }
+    }
+}
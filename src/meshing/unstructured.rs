@@ -0,0 +1,627 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f64;
+use base_types::*;
+use fem::shape::ElementKind;
+use geometry::GeometryObject;
+use super::Mesh;
+
+// Largest circumradius to shortest edge ratio, about 20.7 degrees minimum angle
+const QUALITY: f64 = f64::consts::SQRT_2;
+const MAX_POINTS: usize = 200_000;
+
+// Piece of the outline between two points, with the curve it lies on and
+// the curve parameters of its ends so that it is split on the curve
+#[derive(Debug, Clone)]
+struct Segment {
+    a: usize,
+    b: usize,
+    curve: usize,
+    ta: f64,
+    tb: f64
+}
+
+// Point of `curve` at parameter t in [0, 1]
+fn curve_point(curve: &GeometryObject, t: f64) -> Vec2 {
+    match curve {
+        GeometryObject::Segment { beg, end } => beg.clone() + (end.clone() - beg.clone()) * t,
+        GeometryObject::Circle { center, radius } =>
+            center.clone() + Vec2::from_angle(2.0 * f64::consts::PI * t) * *radius,
+        GeometryObject::Arc { center, radius, start, sweep } =>
+            center.clone() + Vec2::from_angle(start + sweep * t) * *radius,
+        GeometryObject::PolyLine { .. } => unreachable!()
+    }
+}
+
+// Delaunay triangulation by Bowyer-Watson insertion. The first three points
+// are the corners of a triangle enclosing everything.
+struct Triangulation {
+    points: Vec<Vec2>,
+    // counterclockwise, None once removed
+    triangles: Vec<Option<[usize; 3]>>,
+    // circumcentre and squared circumradius
+    circles: Vec<(Vec2, f64)>,
+    // triangles on every edge, keyed by the sorted point pair
+    edges: HashMap<(usize, usize), Vec<usize>>,
+    // slots of removed triangles, reused by new ones
+    free: Vec<usize>,
+    // the triangle added last, where point location starts
+    last: usize
+}
+
+fn key(a: usize, b: usize) -> (usize, usize) { (a.min(b), a.max(b)) }
+
+fn orientation(a: &Vec2, b: &Vec2, c: &Vec2) -> f64 {
+    (b.clone() - a.clone()).cross(&(c.clone() - a.clone()))
+}
+
+fn circumcircle(a: &Vec2, b: &Vec2, c: &Vec2) -> (Vec2, f64) {
+    let (ab, ac) = (b.clone() - a.clone(), c.clone() - a.clone());
+    let d = 2.0 * ab.cross(&ac);
+    let (lb, lc) = (ab.dot(&ab), ac.dot(&ac));
+    let offset = Vec2((ac.1 * lb - ab.1 * lc) / d, (ab.0 * lc - ac.0 * lb) / d);
+    let r2 = offset.dot(&offset);
+    (a.clone() + offset, r2)
+}
+
+impl Triangulation {
+    fn new(min: &Vec2, max: &Vec2) -> Triangulation {
+        let centre = (min.clone() + max.clone()) * 0.5;
+        let r = 100.0 * (max.0 - min.0).hypot(max.1 - min.1);
+        let mut t = Triangulation {
+            points: vec![centre.clone() + Vec2(-r, -r), centre.clone() + Vec2(r, -r), centre + Vec2(0.0, r)],
+            triangles: Vec::new(),
+            circles: Vec::new(),
+            edges: HashMap::new(),
+            free: Vec::new(),
+            last: 0
+        };
+        t.add_triangle([0, 1, 2]);
+        t
+    }
+
+    fn add_triangle(&mut self, nodes: [usize; 3]) -> usize {
+        let i = self.free.pop().unwrap_or_else(|| {
+            self.triangles.push(None);
+            self.circles.push((Vec2(0.0, 0.0), 0.0));
+            self.triangles.len() - 1
+        });
+        for k in 0..3 {
+            self.edges.entry(key(nodes[k], nodes[(k + 1) % 3])).or_default().push(i);
+        }
+        let p = &self.points;
+        self.circles[i] = circumcircle(&p[nodes[0]], &p[nodes[1]], &p[nodes[2]]);
+        self.triangles[i] = Some(nodes);
+        self.last = i;
+        i
+    }
+
+    fn remove_triangle(&mut self, i: usize) {
+        if let Some(nodes) = self.triangles[i].take() {
+            for k in 0..3 {
+                let e = key(nodes[k], nodes[(k + 1) % 3]);
+                let on_edge = self.edges.get_mut(&e).unwrap();
+                on_edge.retain(|&t| t != i);
+                if on_edge.is_empty() {
+                    self.edges.remove(&e);
+                }
+            }
+            self.free.push(i);
+        }
+    }
+
+    fn in_circle(&self, i: usize, p: &Vec2) -> bool {
+        let (ref c, r2) = self.circles[i];
+        let d = p.clone() - c.clone();
+        self.triangles[i].is_some() && d.dot(&d) < r2 * (1.0 - 1e-12)
+    }
+
+    // Triangle across edge k of triangle i
+    fn neighbour(&self, i: usize, k: usize) -> Option<usize> {
+        let nodes = self.triangles[i]?;
+        self.edges[&key(nodes[k], nodes[(k + 1) % 3])].iter().cloned().find(|&t| t != i)
+    }
+
+    // Triangle holding `p`, by walking towards it from the triangle added
+    // last. The walk ends on Delaunay triangulations; the step limit only
+    // guards against round-off.
+    fn locate(&self, p: &Vec2) -> Option<usize> {
+        let mut t = self.last;
+        for _ in 0..self.triangles.len() {
+            let nodes = self.triangles[t]?;
+            let side = (0..3).find(|&k| orientation(&self.points[nodes[k]], &self.points[nodes[(k + 1) % 3]], p) < 0.0);
+            match side {
+                Some(k) => t = self.neighbour(t, k)?,
+                None => return Some(t)
+            }
+        }
+        None
+    }
+
+    // Inserts `p`, starting the search for the triangles whose circumcircle
+    // holds it at `hints` or else at the triangle holding it, and returns
+    // the new triangles
+    fn insert(&mut self, p: Vec2, hints: &[usize]) -> Result<Vec<usize>, String> {
+        let seed = hints.iter().cloned().find(|&t| self.in_circle(t, &p))
+            .or_else(|| self.locate(&p).filter(|&t| self.in_circle(t, &p)))
+            .or_else(|| (0..self.triangles.len()).find(|&t| self.in_circle(t, &p)))
+            .ok_or_else(|| format!("{:?} lies outside the triangulation", p))?;
+        let mut cavity = HashSet::new();
+        cavity.insert(seed);
+        let mut stack = vec![seed];
+        while let Some(t) = stack.pop() {
+            for k in 0..3 {
+                if let Some(n) = self.neighbour(t, k) {
+                    if !cavity.contains(&n) && self.in_circle(n, &p) {
+                        cavity.insert(n);
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+        // keep the cavity star-shaped around `p` despite round-off
+        let boundary = loop {
+            let mut boundary = Vec::new();
+            let mut flat = None;
+            for &t in &cavity {
+                for k in 0..3 {
+                    if self.neighbour(t, k).map_or(true, |n| !cavity.contains(&n)) {
+                        let nodes = self.triangles[t].unwrap();
+                        let (a, b) = (nodes[k], nodes[(k + 1) % 3]);
+                        if orientation(&self.points[a], &self.points[b], &p) <= 0.0 {
+                            flat = Some(t);
+                        }
+                        boundary.push((a, b));
+                    }
+                }
+            }
+            match flat {
+                Some(t) if cavity.len() > 1 => { cavity.remove(&t); },
+                Some(_) => return Err(format!("could not insert {:?}", p)),
+                None => break boundary
+            }
+        };
+        for &t in &cavity {
+            self.remove_triangle(t);
+        }
+        self.points.push(p);
+        let n = self.points.len() - 1;
+        Ok(boundary.into_iter().map(|(a, b)| self.add_triangle([a, b, n])).collect())
+    }
+}
+
+// First and last cell index, inclusive
+type Span = (i64, i64);
+
+// Outline pieces by square grid cell, so that encroachment and inside tests
+// only look at the pieces nearby
+struct SegmentGrid {
+    cell: f64,
+    // pieces whose diametral circle overlaps a cell
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    // pieces that cross the height of a row of cells
+    rows: HashMap<i64, Vec<usize>>
+}
+
+impl SegmentGrid {
+    fn new(cell: f64) -> SegmentGrid {
+        SegmentGrid { cell, cells: HashMap::new(), rows: HashMap::new() }
+    }
+
+    fn index(&self, x: f64) -> i64 { (x / self.cell).floor() as i64 }
+
+    // Cell and row ranges of piece (a, b); horizontal pieces cross no row
+    fn ranges(&self, a: &Vec2, b: &Vec2) -> (Span, Span, Option<Span>) {
+        let c = (a.clone() + b.clone()) * 0.5;
+        let r = 0.5 * (b.clone() - a.clone()).length().sqrt();
+        let rows = if a.1 == b.1 { None } else { Some((self.index(a.1.min(b.1)), self.index(a.1.max(b.1)))) };
+        ((self.index(c.0 - r), self.index(c.0 + r)), (self.index(c.1 - r), self.index(c.1 + r)), rows)
+    }
+
+    fn add(&mut self, i: usize, a: &Vec2, b: &Vec2) {
+        let (xs, ys, rows) = self.ranges(a, b);
+        for y in ys.0..=ys.1 {
+            for x in xs.0..=xs.1 {
+                self.cells.entry((x, y)).or_default().push(i);
+            }
+        }
+        if let Some(rows) = rows {
+            for y in rows.0..=rows.1 {
+                self.rows.entry(y).or_default().push(i);
+            }
+        }
+    }
+
+    fn remove(&mut self, i: usize, a: &Vec2, b: &Vec2) {
+        let (xs, ys, rows) = self.ranges(a, b);
+        for y in ys.0..=ys.1 {
+            for x in xs.0..=xs.1 {
+                self.cells.get_mut(&(x, y)).unwrap().retain(|&j| j != i);
+            }
+        }
+        if let Some(rows) = rows {
+            for y in rows.0..=rows.1 {
+                self.rows.get_mut(&y).unwrap().retain(|&j| j != i);
+            }
+        }
+    }
+
+    // Pieces whose diametral circle may hold `p`
+    fn near(&self, p: &Vec2) -> &[usize] {
+        self.cells.get(&(self.index(p.0), self.index(p.1))).map_or(&[], |c| c)
+    }
+
+    // Pieces that may cross the horizontal line through `p`
+    fn row(&self, p: &Vec2) -> &[usize] {
+        self.rows.get(&self.index(p.1)).map_or(&[], |r| r)
+    }
+}
+
+// Conforming Delaunay refinement of a closed outline (Ruppert's algorithm):
+// outline pieces with a point inside their diametral circle are split, and
+// triangles that are too large for the size field or badly shaped get their
+// circumcentre inserted.
+struct Mesher<'s> {
+    curves: Vec<GeometryObject>,
+    segments: Vec<Segment>,
+    grid: SegmentGrid,
+    // pieces at every outline point
+    at_point: HashMap<usize, Vec<usize>>,
+    // pieces to check for encroachment
+    suspects: VecDeque<usize>,
+    triangulation: Triangulation,
+    size: &'s dyn Fn(&Vec2) -> f64
+}
+
+impl<'s> Mesher<'s> {
+    fn size(&self, p: &Vec2) -> Result<f64, String> {
+        let h = (self.size)(p);
+        if h.is_finite() && h > 0.0 { Ok(h) } else { Err(format!("invalid element size {} at {:?}", h, p)) }
+    }
+
+    // Even-odd rule over the outline, so holes are outside
+    fn inside(&self, p: &Vec2) -> bool {
+        let points = &self.triangulation.points;
+        self.grid.row(p).iter().map(|&i| &self.segments[i]).filter(|s| {
+            let (a, b) = (&points[s.a], &points[s.b]);
+            (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) / (b.1 - a.1) * (b.0 - a.0)
+        }).count() % 2 == 1
+    }
+
+    fn encroaches(&self, p: &Vec2, s: &Segment) -> bool {
+        let points = &self.triangulation.points;
+        let (a, b) = (points[s.a].clone() - p.clone(), points[s.b].clone() - p.clone());
+        let ab = points[s.b].clone() - points[s.a].clone();
+        a.dot(&b) < -1e-10 * ab.dot(&ab)
+    }
+
+    // A piece is encroached when it is no edge of the triangulation or a
+    // triangle on it has its third point inside the diametral circle
+    fn encroached(&self, s: &Segment) -> bool {
+        let t = &self.triangulation;
+        match t.edges.get(&key(s.a, s.b)) {
+            None => true,
+            Some(triangles) => triangles.iter().any(|&i| {
+                let nodes = t.triangles[i].unwrap();
+                let apex = nodes.iter().cloned().find(|&n| n != s.a && n != s.b).unwrap();
+                self.encroaches(&t.points[apex], s)
+            })
+        }
+    }
+
+    // Puts piece i in the grid and at its points, and on the suspects
+    fn register(&mut self, i: usize) {
+        let s = &self.segments[i];
+        self.grid.add(i, &self.triangulation.points[s.a], &self.triangulation.points[s.b]);
+        for &n in &[s.a, s.b] {
+            self.at_point.entry(n).or_default().push(i);
+        }
+        self.suspects.push_back(i);
+    }
+
+    fn unregister(&mut self, i: usize) {
+        let s = &self.segments[i];
+        self.grid.remove(i, &self.triangulation.points[s.a], &self.triangulation.points[s.b]);
+        for n in &[s.a, s.b] {
+            self.at_point.get_mut(n).unwrap().retain(|&j| j != i);
+        }
+    }
+
+    // Sets up the grid, with cells about as large as the pieces
+    fn index_segments(&mut self) {
+        let points = &self.triangulation.points;
+        let length: f64 = self.segments.iter().map(|s| (points[s.b].clone() - points[s.a].clone()).length().sqrt()).sum();
+        self.grid = SegmentGrid::new(length / self.segments.len() as f64);
+        for i in 0..self.segments.len() {
+            self.register(i);
+        }
+    }
+
+    // Inserts `p`. Only the pieces at the points around it can have lost
+    // their edge or got a new triangle, so these become suspects.
+    fn insert(&mut self, p: Vec2, hints: &[usize]) -> Result<Vec<usize>, String> {
+        let created = self.triangulation.insert(p, hints)?;
+        for &t in &created {
+            for n in &self.triangulation.triangles[t].unwrap() {
+                if let Some(pieces) = self.at_point.get(n) {
+                    self.suspects.extend(pieces.iter().cloned());
+                }
+            }
+        }
+        Ok(created)
+    }
+
+    fn split(&mut self, i: usize) -> Result<Vec<usize>, String> {
+        let s = self.segments[i].clone();
+        let tm = 0.5 * (s.ta + s.tb);
+        let hints = self.triangulation.edges.get(&key(s.a, s.b)).cloned().unwrap_or_default();
+        let created = self.insert(curve_point(&self.curves[s.curve], tm), &hints)?;
+        let m = self.triangulation.points.len() - 1;
+        self.unregister(i);
+        self.segments[i] = Segment { b: m, tb: tm, ..s.clone() };
+        self.segments.push(Segment { a: m, ta: tm, ..s });
+        self.register(i);
+        self.register(self.segments.len() - 1);
+        Ok(created)
+    }
+
+    fn refine(&mut self) -> Result<(), String> {
+        let mut queue: VecDeque<usize> = (0..self.triangulation.triangles.len()).collect();
+        loop {
+            if self.triangulation.points.len() > MAX_POINTS {
+                return Err("too many points, the size field is too fine or the outline has sharp angles".to_string());
+            }
+            if let Some(i) = self.suspects.pop_front() {
+                if self.encroached(&self.segments[i]) {
+                    queue.extend(self.split(i)?);
+                }
+                continue;
+            }
+            let t = match queue.pop_front() {
+                Some(t) => t,
+                None => return Ok(())
+            };
+            let nodes = match self.triangulation.triangles[t] {
+                Some(nodes) if nodes.iter().all(|&n| n > 2) => nodes,
+                _ => continue
+            };
+            let p: Vec<Vec2> = nodes.iter().map(|&n| self.triangulation.points[n].clone()).collect();
+            let centroid = (p[0].clone() + p[1].clone() + p[2].clone()) * (1.0 / 3.0);
+            if !self.inside(&centroid) {
+                continue;
+            }
+            let (cc, r2) = self.triangulation.circles[t].clone();
+            let r = r2.sqrt();
+            let shortest = (0..3).map(|k| (p[(k + 1) % 3].clone() - p[k].clone()).length().sqrt())
+                .fold(f64::INFINITY, f64::min);
+            // an equilateral triangle of side h has circumradius h / sqrt(3)
+            if r * 3f64.sqrt() <= self.size(&centroid)? && r <= QUALITY * shortest {
+                continue;
+            }
+            if let Some(i) = self.grid.near(&cc).iter().cloned().find(|&i| self.encroaches(&cc, &self.segments[i])) {
+                queue.extend(self.split(i)?);
+                queue.push_back(t);
+            } else if self.inside(&cc) {
+                queue.extend(self.insert(cc, &[t])?);
+            }
+        }
+    }
+}
+
+// Triangle mesh of the area enclosed by `outline` with elements of about
+// size(p) at p. The objects must form closed loops; loops inside others are
+// holes. Tri3 and Tri6 elements are supported, boundary edges are tagged
+// "outline" and curved boundaries get their mid-edge nodes on the curve.
+pub fn triangulate<F>(outline: &[GeometryObject], size: F, kind: ElementKind, region: &str) -> Result<Mesh, String>
+    where F: Fn(&Vec2) -> f64 {
+    if kind != ElementKind::Tri3 && kind != ElementKind::Tri6 {
+        return Err(format!("cannot triangulate with {:?} elements", kind));
+    }
+    // polylines become segments
+    let mut curves = Vec::new();
+    for o in outline {
+        match o {
+            GeometryObject::PolyLine { points } => curves.extend(points.windows(2)
+                .filter(|w| w[0] != w[1])
+                .map(|w| GeometryObject::Segment { beg: w[0].clone(), end: w[1].clone() })),
+            GeometryObject::Arc { sweep, .. } if *sweep == 0.0 => (),
+            _ => curves.push(o.clone())
+        }
+    }
+    if curves.is_empty() {
+        return Err("nothing to mesh".to_string());
+    }
+    let samples: Vec<Vec2> = curves.iter()
+        .flat_map(|c| (0..9).map(move |k| curve_point(c, k as f64 / 8.0)))
+        .collect();
+    let min = samples.iter().fold(Vec2(f64::MAX, f64::MAX), |m, p| Vec2(m.0.min(p.0), m.1.min(p.1)));
+    let max = samples.iter().fold(Vec2(f64::MIN, f64::MIN), |m, p| Vec2(m.0.max(p.0), m.1.max(p.1)));
+    let tolerance = 1e-6 * (max.0 - min.0).hypot(max.1 - min.1);
+
+    let mut mesher = Mesher {
+        curves: Vec::new(),
+        segments: Vec::new(),
+        grid: SegmentGrid::new(1.0),
+        at_point: HashMap::new(),
+        suspects: VecDeque::new(),
+        triangulation: Triangulation::new(&min, &max),
+        size: &size
+    };
+    // curve ends are shared between curves, the points in between are not
+    let mut ends: Vec<usize> = Vec::new();
+    let mut degree: HashMap<usize, usize> = HashMap::new();
+    for (c, curve) in curves.iter().enumerate() {
+        let closed = matches!(curve, GeometryObject::Circle { .. });
+        let mut end = |mesher: &mut Mesher, p: Vec2| -> Result<usize, String> {
+            if let Some(&i) = ends.iter().find(|&&i| (mesher.triangulation.points[i].clone() - p.clone()).length().sqrt() <= tolerance) {
+                return Ok(i);
+            }
+            mesher.triangulation.insert(p, &[])?;
+            ends.push(mesher.triangulation.points.len() - 1);
+            Ok(mesher.triangulation.points.len() - 1)
+        };
+        let first = end(&mut mesher, curve_point(curve, 0.0))?;
+        let last = if closed { first } else { end(&mut mesher, curve_point(curve, 1.0))? };
+        *degree.entry(first).or_insert(0) += 1;
+        *degree.entry(last).or_insert(0) += 1;
+
+        // pieces no longer than the local size, arcs in steps of 45 degrees at most
+        let sweep = match curve {
+            GeometryObject::Circle { .. } => 2.0 * f64::consts::PI,
+            GeometryObject::Arc { sweep, .. } => sweep.abs(),
+            _ => 0.0
+        };
+        let pieces = ((sweep / (0.25 * f64::consts::PI)).ceil() as usize).max(1);
+        let mut params: Vec<f64> = (0..pieces + 1).map(|k| k as f64 / pieces as f64).collect();
+        let mut k = 0;
+        while k + 1 < params.len() {
+            let (ta, tb) = (params[k], params[k + 1]);
+            let chord = (curve_point(curve, tb) - curve_point(curve, ta)).length().sqrt();
+            if chord > mesher.size(&curve_point(curve, 0.5 * (ta + tb)))? {
+                params.insert(k + 1, 0.5 * (ta + tb));
+            } else {
+                k += 1;
+            }
+        }
+        let mut ids = vec![first];
+        for &t in &params[1..params.len() - 1] {
+            mesher.triangulation.insert(curve_point(curve, t), &[])?;
+            ids.push(mesher.triangulation.points.len() - 1);
+        }
+        ids.push(last);
+        for k in 0..ids.len() - 1 {
+            mesher.segments.push(Segment { a: ids[k], b: ids[k + 1], curve: c, ta: params[k], tb: params[k + 1] });
+        }
+    }
+    if let Some((&i, _)) = degree.iter().find(|d| d.1 % 2 == 1) {
+        return Err(format!("the outline is not closed at {:?}", mesher.triangulation.points[i]));
+    }
+    mesher.curves = curves;
+    mesher.index_segments();
+    mesher.refine()?;
+
+    // triangles inside the outline, their points renumbered
+    let kept: Vec<[usize; 3]> = mesher.triangulation.triangles.iter().filter_map(|t| *t)
+        .filter(|nodes| nodes.iter().all(|&n| n > 2))
+        .filter(|nodes| {
+            let p = &mesher.triangulation.points;
+            mesher.inside(&((p[nodes[0]].clone() + p[nodes[1]].clone() + p[nodes[2]].clone()) * (1.0 / 3.0)))
+        })
+        .collect();
+    let mut mesh = Mesh::new();
+    let mut index = HashMap::new();
+    for nodes in &kept {
+        for &n in nodes {
+            index.entry(n).or_insert_with(|| mesh.add_node(mesher.triangulation.points[n].clone()));
+        }
+    }
+    let on_outline: HashMap<(usize, usize), &Segment> = mesher.segments.iter().map(|s| (key(s.a, s.b), s)).collect();
+    let mut mids = HashMap::new();
+    let mut edge_count: HashMap<(usize, usize), usize> = HashMap::new();
+    for nodes in &kept {
+        for k in 0..3 {
+            *edge_count.entry(key(nodes[k], nodes[(k + 1) % 3])).or_insert(0) += 1;
+        }
+    }
+    for nodes in &kept {
+        let mut element: Vec<usize> = nodes.iter().map(|n| index[n]).collect();
+        for k in 0..3 {
+            let (a, b) = (nodes[k], nodes[(k + 1) % 3]);
+            let mut edge = vec![index[&a], index[&b]];
+            if kind == ElementKind::Tri6 {
+                let mid = *mids.entry(key(a, b)).or_insert_with(|| {
+                    let p = match on_outline.get(&key(a, b)) {
+                        Some(s) => curve_point(&mesher.curves[s.curve], 0.5 * (s.ta + s.tb)),
+                        None => (mesher.triangulation.points[a].clone() + mesher.triangulation.points[b].clone()) * 0.5
+                    };
+                    mesh.add_node(p)
+                });
+                element.push(mid);
+                edge.push(mid);
+            }
+            if edge_count[&key(a, b)] == 1 {
+                let line = if kind == ElementKind::Tri6 { ElementKind::Line3 } else { ElementKind::Line2 };
+                mesh.add_boundary_edge(line, edge, "outline");
+            }
+        }
+        mesh.add_element(kind, element, region);
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape;
+
+    fn area(mesh: &Mesh) -> f64 {
+        mesh.elements.iter().map(|e| {
+            let coords = mesh.coords(&e.nodes);
+            e.kind.quadrature(2).iter().map(|q| q.weight * shape::map(e.kind, &coords, &q.point).unwrap().det_j).sum::<f64>()
+        }).sum()
+    }
+
+    fn square_with_hole() -> Vec<GeometryObject> {
+        let corners = vec![Vec2(0.0, 0.0), Vec2(2.0, 0.0), Vec2(2.0, 2.0), Vec2(0.0, 2.0), Vec2(0.0, 0.0)];
+        vec![GeometryObject::PolyLine { points: corners },
+             GeometryObject::Circle { center: Vec2(1.0, 1.0), radius: 0.5 }]
+    }
+
+    #[test]
+    fn uniform_meshes() {
+        for &kind in &[ElementKind::Tri3, ElementKind::Tri6] {
+            let mesh = triangulate(&square_with_hole(), |_| 0.2, kind, "plate").unwrap();
+            let exact = 4.0 - f64::consts::PI * 0.25;
+            // the hole is polygonal for linear elements
+            let tolerance = if kind == ElementKind::Tri3 { 2e-2 } else { 1e-4 };
+            assert!((area(&mesh) - exact).abs() < tolerance, "{:?} {}", kind, area(&mesh));
+            for e in &mesh.elements {
+                let c = mesh.coords(&e.nodes);
+                assert!(orientation(&c[0], &c[1], &c[2]) > 0.0);
+                for k in 0..3 {
+                    let (u, v) = (c[(k + 1) % 3].clone() - c[k].clone(), c[(k + 2) % 3].clone() - c[k].clone());
+                    assert!(u.cross(&v).atan2(u.dot(&v)) > 20f64.to_radians());
+                }
+                // the circumradius of an equilateral triangle of side 0.2 at most
+                for k in 0..3 {
+                    assert!((c[(k + 1) % 3].clone() - c[k].clone()).length().sqrt() < 0.4 / 3f64.sqrt() + 1e-9);
+                }
+            }
+            // the boundary is the outer square and the hole, domain on the left
+            let length: f64 = mesh.boundary.iter()
+                .map(|e| (mesh.nodes[e.nodes[1]].clone() - mesh.nodes[e.nodes[0]].clone()).length().sqrt()).sum();
+            assert!((length - 8.0 - f64::consts::PI).abs() < 0.05, "{}", length);
+            for e in &mesh.boundary {
+                let n = &mesh.nodes[e.nodes[0]];
+                let on_hole = ((n.0 - 1.0).hypot(n.1 - 1.0) - 0.5).abs() < 1e-9;
+                let on_square = n.0.abs() < 1e-12 || n.1.abs() < 1e-12 || (n.0 - 2.0).abs() < 1e-12 || (n.1 - 2.0).abs() < 1e-12;
+                assert!(on_hole || on_square);
+            }
+        }
+    }
+
+    #[test]
+    fn graded_meshes() {
+        let outline = vec![GeometryObject::PolyLine { points: vec![Vec2(0.0, 0.0), Vec2(4.0, 0.0), Vec2(4.0, 1.0),
+                                                                   Vec2(0.0, 1.0), Vec2(0.0, 0.0)] }];
+        let size = |p: &Vec2| 0.05 + 0.1 * p.0;
+        let mesh = triangulate(&outline, size, ElementKind::Tri3, "strip").unwrap();
+        assert!((area(&mesh) - 4.0).abs() < 1e-9);
+        let longest = |e: &::meshing::Element| {
+            let c = mesh.coords(&e.nodes);
+            (0..3).map(|k| (c[(k + 1) % 3].clone() - c[k].clone()).length().sqrt()).fold(0.0, f64::max)
+        };
+        let (near, far): (Vec<_>, Vec<_>) = mesh.elements.iter().partition(|e| mesh.nodes[e.nodes[0]].0 < 0.5);
+        let near_size = near.iter().map(|e| longest(e)).fold(0.0, f64::max);
+        let far_size = far.iter().filter(|e| mesh.nodes[e.nodes[0]].0 > 3.0).map(|e| longest(e)).fold(0.0, f64::max);
+        assert!(near_size < 0.15 && far_size > 0.2, "{} {}", near_size, far_size);
+    }
+
+    #[test]
+    fn invalid_outlines() {
+        let open = vec![GeometryObject::PolyLine { points: vec![Vec2(0.0, 0.0), Vec2(1.0, 0.0), Vec2(1.0, 1.0)] }];
+        assert!(triangulate(&open, |_| 0.2, ElementKind::Tri3, "a").is_err());
+        assert!(triangulate(&square_with_hole(), |_| 0.2, ElementKind::Quad4, "a").is_err());
+        assert!(triangulate(&square_with_hole(), |_| 0.0, ElementKind::Tri3, "a").is_err());
+        assert!(triangulate(&[], |_| 0.2, ElementKind::Tri3, "a").is_err());
+    }
+}
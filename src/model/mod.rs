@@ -0,0 +1,340 @@
+use std::path::Path;
+use base_types::*;
+use geometry::{GeometryEntity, GeometryObject};
+use meshing::Mesh;
+use analysis::*;
+
+// Selects drawing objects a boundary condition is attached to
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeometryRef {
+    // Position in the entity list
+    Index(usize),
+    // Every object on a DXF layer
+    Layer(String),
+    // DXF entity handle
+    Handle(u32)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    // Prescribed value of a scalar field (temperature, potential, A_z)
+    Fixed(f64),
+    // Flux of a scalar field into the domain (heat flux, surface charge)
+    Flux(f64),
+    // Exchange with an ambient value, e.g. convection
+    Robin { coefficient: f64, ambient: f64 },
+    // Prescribed displacement, `None` leaves the component free
+    Displacement { ux: Option<f64>, uy: Option<f64> },
+    // Normal pressure, positive pushing into the part
+    Pressure(f64),
+    Traction(Vec2),
+    // Force at the anchor point of every referenced object
+    PointLoad(Vec2)
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundaryDefinition {
+    // Also the tag of the mesh boundary edges the condition is transferred to
+    pub name: String,
+    pub target: GeometryRef,
+    pub condition: Condition
+}
+
+// Boundary conditions defined on the drawing rather than on a mesh. They are
+// transferred to the boundary edges lying on the referenced objects, so they
+// survive remeshing.
+pub struct Model {
+    entities: Vec<GeometryEntity>,
+    definitions: Vec<BoundaryDefinition>,
+    tolerance: f64
+}
+
+impl Model {
+    pub fn new(entities: Vec<GeometryEntity>) -> Model {
+        Model { entities, definitions: Vec::new(), tolerance: 1e-6 }
+    }
+
+    pub fn from_file(file_name: &Path) -> Result<Model, String> {
+        Ok(Model::new(GeometryObject::read_entities_from_file(file_name)?))
+    }
+
+    pub fn entities(&self) -> &[GeometryEntity] { &self.entities }
+
+    pub fn definitions(&self) -> &[BoundaryDefinition] { &self.definitions }
+
+    // Distance within which a mesh node counts as lying on an object,
+    // relative to the size of the mesh
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance;
+    }
+
+    pub fn add_condition(&mut self, name: &str, target: GeometryRef, condition: Condition) -> Result<(), String> {
+        if self.definitions.iter().any(|d| d.name == name) {
+            return Err(format!("boundary condition '{}' is already defined", name));
+        }
+        self.objects(&target)?;
+        self.definitions.push(BoundaryDefinition { name: name.to_string(), target, condition });
+        Ok(())
+    }
+
+    pub fn objects(&self, target: &GeometryRef) -> Result<Vec<&GeometryObject>, String> {
+        let objects: Vec<&GeometryObject> = match target {
+            GeometryRef::Index(i) => self.entities.get(*i).map(|e| &e.object).into_iter().collect(),
+            GeometryRef::Layer(layer) => self.entities.iter()
+                .filter(|e| e.layer == *layer).map(|e| &e.object).collect(),
+            GeometryRef::Handle(handle) => self.entities.iter()
+                .filter(|e| e.handle == *handle).map(|e| &e.object).collect()
+        };
+        if objects.is_empty() {
+            return Err(format!("{:?} selects no objects", target));
+        }
+        Ok(objects)
+    }
+
+    // Tags the boundary edges of `mesh` lying on the objects of every
+    // definition with its name. Edges tagged by an earlier transfer are
+    // replaced, so this can be repeated on a modified mesh.
+    pub fn transfer(&self, mesh: &mut Mesh) -> Result<(), String> {
+        mesh.boundary.retain(|e| self.definitions.iter().all(|d| d.name != e.tag));
+        let tolerance = self.tolerance * mesh_size(mesh);
+        let edges = mesh.boundary.clone();
+        for d in &self.definitions {
+            if let Condition::PointLoad(_) = d.condition {
+                continue;
+            }
+            let objects = self.objects(&d.target)?;
+            let mut found = false;
+            for edge in &edges {
+                // the midpoint keeps edges cutting a corner between two
+                // referenced objects out
+                let (a, b) = (&mesh.nodes[edge.nodes[0]], &mesh.nodes[edge.nodes[1]]);
+                let mid = (a.clone() + b.clone()) * 0.5;
+                let on_objects = edge.nodes.iter().map(|&n| &mesh.nodes[n]).chain(Some(&mid))
+                    .all(|p| objects.iter().any(|o| o.distance(p) <= tolerance));
+                if on_objects {
+                    mesh.add_boundary_edge(edge.kind, edge.nodes.clone(), &d.name);
+                    found = true;
+                }
+            }
+            if !found {
+                return Err(format!("boundary condition '{}' does not touch the mesh boundary", d.name));
+            }
+        }
+        Ok(())
+    }
+
+    // Meshes the drawing with `mesher` and transfers the boundary conditions
+    pub fn mesh_with<F>(&self, mesher: F) -> Result<Mesh, String>
+        where F: FnOnce(&[GeometryEntity]) -> Result<Mesh, String> {
+        let mut mesh = mesher(&self.entities)?;
+        self.transfer(&mut mesh)?;
+        Ok(mesh)
+    }
+
+    fn unsupported(d: &BoundaryDefinition, analysis: &str) -> String {
+        format!("boundary condition '{}' ({:?}) does not apply to {}", d.name, d.condition, analysis)
+    }
+
+    fn heat_boundaries(&self) -> Result<Vec<HeatBoundary>, String> {
+        self.definitions.iter().map(|d| {
+            let tag = d.name.clone();
+            match d.condition {
+                Condition::Fixed(value) => Ok(HeatBoundary::Temperature { tag, value }),
+                Condition::Flux(flux) => Ok(HeatBoundary::HeatFlux { tag, flux }),
+                Condition::Robin { coefficient, ambient } =>
+                    Ok(HeatBoundary::Convection { tag, film_coefficient: coefficient, ambient }),
+                _ => Err(Model::unsupported(d, "heat conduction"))
+            }
+        }).collect()
+    }
+
+    // The apply_* functions expect the problem's mesh to have been through
+    // `transfer`
+    pub fn apply_heat(&self, problem: &mut HeatProblem) -> Result<(), String> {
+        for bc in self.heat_boundaries()? {
+            problem.add_boundary(bc);
+        }
+        Ok(())
+    }
+
+    pub fn apply_transient_heat(&self, problem: &mut TransientHeatProblem) -> Result<(), String> {
+        for bc in self.heat_boundaries()? {
+            problem.add_boundary(bc, Amplitude::Constant);
+        }
+        Ok(())
+    }
+
+    pub fn apply_elasticity(&self, problem: &mut ElasticityProblem) -> Result<(), String> {
+        for d in &self.definitions {
+            let tag = d.name.clone();
+            match d.condition {
+                Condition::Displacement { ux, uy } => problem.add_support(Support::Displacement { tag, ux, uy }),
+                Condition::Pressure(pressure) => problem.add_load(Load::Pressure { tag, pressure }),
+                Condition::Traction(ref traction) =>
+                    problem.add_load(Load::Traction { tag, traction: traction.clone() }),
+                Condition::PointLoad(ref force) => {
+                    for o in self.objects(&d.target)? {
+                        // a marker circle must hold a node
+                        let tolerance = match o {
+                            GeometryObject::Circle { radius, .. } | GeometryObject::Arc { radius, .. } => *radius,
+                            _ => self.tolerance * mesh_size(problem.mesh())
+                        };
+                        problem.add_load(Load::Point { position: o.anchor(), force: force.clone(), tolerance });
+                    }
+                },
+                _ => return Err(Model::unsupported(d, "elasticity"))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_electrostatics(&self, problem: &mut ElectrostaticProblem) -> Result<(), String> {
+        for d in &self.definitions {
+            let tag = d.name.clone();
+            match d.condition {
+                Condition::Fixed(value) => problem.add_boundary(ElectrostaticBoundary::Potential { tag, value }),
+                Condition::Flux(density) =>
+                    problem.add_boundary(ElectrostaticBoundary::SurfaceCharge { tag, density }),
+                _ => return Err(Model::unsupported(d, "electrostatics"))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_magnetostatics(&self, problem: &mut MagnetostaticProblem) -> Result<(), String> {
+        for d in &self.definitions {
+            match d.condition {
+                Condition::Fixed(value) =>
+                    problem.add_boundary(MagneticBoundary::VectorPotential { tag: d.name.clone(), value }),
+                _ => return Err(Model::unsupported(d, "magnetostatics"))
+            }
+        }
+        Ok(())
+    }
+}
+
+// Diagonal of the bounding box of the mesh nodes
+fn mesh_size(mesh: &Mesh) -> f64 {
+    let (lo, hi) = mesh.nodes.iter().fold(
+        (Vec2(f64::INFINITY, f64::INFINITY), Vec2(f64::NEG_INFINITY, f64::NEG_INFINITY)),
+        |(lo, hi), p| (Vec2(lo.0.min(p.0), lo.1.min(p.1)), Vec2(hi.0.max(p.0), hi.1.max(p.1))));
+    if mesh.nodes.is_empty() { 0.0 } else { (hi.0 - lo.0).hypot(hi.1 - lo.1) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use analysis::elasticity::PlaneMode;
+    use meshing;
+    use solvers::LinearSolver;
+
+    // 2 x 1 plate outlined by four segments; the right side is split in two
+    fn plate() -> Vec<GeometryEntity> {
+        let segment = |a: Vec2, b: Vec2, layer: &str, handle: u32| GeometryEntity {
+            object: GeometryObject::Segment { beg: a, end: b },
+            layer: layer.to_string(),
+            handle
+        };
+        vec![
+            segment(Vec2(0.0, 0.0), Vec2(2.0, 0.0), "OUTLINE", 0x10),
+            segment(Vec2(2.0, 0.0), Vec2(2.0, 0.5), "EDGE", 0x11),
+            segment(Vec2(2.0, 0.5), Vec2(2.0, 1.0), "EDGE", 0x12),
+            segment(Vec2(2.0, 1.0), Vec2(0.0, 1.0), "OUTLINE", 0x13),
+            segment(Vec2(0.0, 1.0), Vec2(0.0, 0.0), "HOT", 0x14),
+            GeometryEntity {
+                object: GeometryObject::Circle { center: Vec2(2.0, 1.0), radius: 0.01 },
+                layer: "LOADS".to_string(),
+                handle: 0x20
+            }
+        ]
+    }
+
+    #[test]
+    fn conditions_survive_remeshing() {
+        let mut model = Model::new(plate());
+        model.add_condition("hot", GeometryRef::Layer("HOT".to_string()), Condition::Fixed(100.0)).unwrap();
+        model.add_condition("cold", GeometryRef::Layer("EDGE".to_string()), Condition::Fixed(0.0)).unwrap();
+
+        for &(n, kind) in &[(2, ElementKind::Tri3), (5, ElementKind::Quad8)] {
+            let mesh = model.mesh_with(|_| {
+                Ok(meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 2 * n, n, kind, "plate"))
+            }).unwrap();
+            assert_eq!(mesh.boundary_nodes("hot"), mesh.boundary_nodes("left"));
+            assert_eq!(mesh.boundary_nodes("cold"), mesh.boundary_nodes("right"));
+
+            let mut p = HeatProblem::new(&mesh);
+            p.set_conductivity("plate", 1.0);
+            model.apply_heat(&mut p).unwrap();
+            let t = p.solve(&LinearSolver::default()).unwrap().temperature;
+            for (x, t) in mesh.nodes.iter().zip(&t) {
+                assert!((t - 50.0 * (2.0 - x.0)).abs() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn transfer_replaces_earlier_tags() {
+        let mut model = Model::new(plate());
+        model.add_condition("clamp", GeometryRef::Index(4), Condition::Displacement { ux: Some(0.0), uy: Some(0.0) })
+            .unwrap();
+        let mut mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 4, 2, ElementKind::Quad4, "plate");
+        model.transfer(&mut mesh).unwrap();
+        model.transfer(&mut mesh).unwrap();
+        assert_eq!(mesh.boundary_with_tag("clamp").count(), 2);
+    }
+
+    #[test]
+    fn loads_by_handle_and_marker() {
+        let mut model = Model::new(plate());
+        model.add_condition("clamp", GeometryRef::Layer("HOT".to_string()),
+                            Condition::Displacement { ux: Some(0.0), uy: Some(0.0) }).unwrap();
+        model.add_condition("pull", GeometryRef::Handle(0x11), Condition::Traction(Vec2(1e6, 0.0))).unwrap();
+        model.add_condition("tip", GeometryRef::Layer("LOADS".to_string()), Condition::PointLoad(Vec2(0.0, -1e4)))
+            .unwrap();
+
+        let mut mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 8, 4, ElementKind::Quad8, "plate");
+        model.transfer(&mut mesh).unwrap();
+        let mut p = ElasticityProblem::new(&mesh, PlaneMode::PlaneStress, 0.01);
+        p.set_material("plate", ElasticMaterial::Isotropic { young: 70e9, poisson: 0.33 });
+        model.apply_elasticity(&mut p).unwrap();
+        let r = p.solve(&LinearSolver::default()).unwrap().total_reaction();
+        // traction over half the right side plus the point load
+        assert!((r.0 + 1e6 * 0.5 * 0.01).abs() < 1e-3 && (r.1 - 1e4).abs() < 1e-3);
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        let mut model = Model::new(plate());
+        assert!(model.add_condition("a", GeometryRef::Layer("NONE".to_string()), Condition::Fixed(0.0)).is_err());
+        assert!(model.add_condition("a", GeometryRef::Index(9), Condition::Fixed(0.0)).is_err());
+        model.add_condition("p", GeometryRef::Handle(0x10), Condition::Pressure(1.0)).unwrap();
+        assert!(model.add_condition("p", GeometryRef::Handle(0x13), Condition::Pressure(1.0)).is_err());
+
+        let mut mesh = meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 2, 1, ElementKind::Tri3, "plate");
+        model.transfer(&mut mesh).unwrap();
+        let mut p = HeatProblem::new(&mesh);
+        assert!(model.apply_heat(&mut p).is_err());
+
+        // geometry away from the meshed part
+        let mut small = meshing::rectangle(Vec2(0.0, 0.0), Vec2(1.0, 0.5), 2, 1, ElementKind::Tri3, "plate");
+        model.add_condition("wall", GeometryRef::Handle(0x11), Condition::Fixed(0.0)).unwrap();
+        assert!(model.transfer(&mut small).unwrap_err().contains("wall"));
+    }
+
+    #[test]
+    fn distance_to_arc() {
+        let arc = GeometryObject::Arc { center: Vec2(0.0, 0.0), radius: 1.0, start: 0.0, sweep: ::std::f64::consts::PI };
+        assert!((arc.distance(&Vec2(0.0, 2.0)) - 1.0).abs() < 1e-12);
+        assert!((arc.distance(&Vec2(0.0, -1.0)) - 2f64.sqrt()).abs() < 1e-12);
+        // from 350 to 10 degrees across the positive x axis
+        let across = GeometryObject::dxf_arc(Vec2(0.0, 0.0), 1.0, 350.0, 10.0);
+        assert!((across.distance(&Vec2(2.0, 0.0)) - 1.0).abs() < 1e-12);
+        assert!(across.distance(&Vec2(5f64.to_radians().cos(), -5f64.to_radians().sin())) < 1e-12);
+        assert!(across.distance(&Vec2(-1.0, 0.0)) > 1.9);
+        match GeometryObject::dxf_arc(Vec2(0.0, 0.0), 1.0, 30.0, 30.0) {
+            GeometryObject::Arc { sweep, .. } => assert!((sweep - 2.0 * ::std::f64::consts::PI).abs() < 1e-12),
+            _ => unreachable!()
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use base_types::Vec2;
+use meshing::{Mesh, PointLocator};
+
+// Where the values of a field are stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldLocation {
+    Nodal,
+    // One value set per element
+    Element,
+    // Integration points given by their element and natural coordinates
+    GaussPoints(Vec<(usize, Vec2)>)
+}
+
+// Named result quantity with one or more components per node, element or
+// integration point, e.g. "displacement" with components ux and uy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub location: FieldLocation,
+    pub units: String,
+    pub components: Vec<String>,
+    // entity-major: values[entity * components + component]
+    values: Vec<f64>
+}
+
+impl Field {
+    pub fn new(name: &str, location: FieldLocation, components: &[&str], values: Vec<f64>) -> Result<Field, String> {
+        if components.is_empty() {
+            return Err(format!("field '{}' has no components", name));
+        }
+        Ok(Field {
+            name: name.to_string(),
+            location,
+            units: String::new(),
+            components: components.iter().map(|c| c.to_string()).collect(),
+            values
+        })
+    }
+
+    // Nodal field of 2D vectors
+    pub fn nodal_vectors(name: &str, components: [&str; 2], vectors: &[Vec2]) -> Field {
+        let values = vectors.iter().flat_map(|v| vec![v.0, v.1]).collect();
+        Field {
+            name: name.to_string(),
+            location: FieldLocation::Nodal,
+            units: String::new(),
+            components: components.iter().map(|c| c.to_string()).collect(),
+            values
+        }
+    }
+
+    pub fn set_units(&mut self, units: &str) {
+        self.units = units.to_string();
+    }
+
+    pub fn component_count(&self) -> usize { self.components.len() }
+
+    // Number of nodes, elements or integration points
+    pub fn entity_count(&self) -> usize { self.values.len() / self.components.len().max(1) }
+
+    pub fn component(&self, name: &str) -> Result<usize, String> {
+        self.components.iter().position(|c| c == name)
+            .ok_or_else(|| format!("field '{}' has no component '{}'", self.name, name))
+    }
+
+    // None for an entity or component out of range
+    pub fn value(&self, entity: usize, component: usize) -> Option<f64> {
+        if component >= self.components.len() {
+            return None;
+        }
+        self.values.get(entity * self.components.len() + component).cloned()
+    }
+
+    // All values of one component, one per entity
+    pub fn values(&self, component: usize) -> Option<Vec<f64>> {
+        if component >= self.components.len() {
+            return None;
+        }
+        Some(self.values.iter().skip(component).step_by(self.components.len()).cloned().collect())
+    }
+
+    // Entity with the smallest value of a component and that value
+    pub fn min(&self, component: usize) -> Option<(usize, f64)> {
+        self.values(component)?.into_iter().enumerate().fold(None, |m, (i, v)| match m {
+            Some((_, w)) if w <= v => m,
+            _ => Some((i, v))
+        })
+    }
+
+    pub fn max(&self, component: usize) -> Option<(usize, f64)> {
+        self.values(component)?.into_iter().enumerate().fold(None, |m, (i, v)| match m {
+            Some((_, w)) if w >= v => m,
+            _ => Some((i, v))
+        })
+    }
+
+    fn check(&self, mesh: &Mesh) -> Result<(), String> {
+        if self.components.is_empty() {
+            return Err(format!("field '{}' has no components", self.name));
+        }
+        let entities = match &self.location {
+            FieldLocation::Nodal => mesh.node_count(),
+            FieldLocation::Element => mesh.elements.len(),
+            FieldLocation::GaussPoints(points) => {
+                if let Some(p) = points.iter().find(|p| p.0 >= mesh.elements.len()) {
+                    return Err(format!("field '{}' has a point in element {} of {}",
+                                       self.name, p.0, mesh.elements.len()));
+                }
+                points.len()
+            }
+        };
+        if self.values.len() != entities * self.components.len() {
+            return Err(format!("field '{}' has {} values instead of {} x {}",
+                               self.name, self.values.len(), entities, self.components.len()));
+        }
+        Ok(())
+    }
+
+    // Value of a component at `p`: nodal fields are interpolated, element
+    // fields are constant per element and integration point fields take the
+    // value of the closest point of the element. Points outside the mesh are
+    // accepted within `tolerance`.
+    pub fn at(&self, locator: &PointLocator, p: &Vec2, component: usize, tolerance: f64) -> Option<f64> {
+        let (i, xi) = locator.closest(p, tolerance)?;
+        let e = &locator.mesh().elements[i];
+        let n = e.kind.shape_functions(&xi);
+        match &self.location {
+            FieldLocation::Nodal => n.iter().zip(&e.nodes).map(|(n, &a)| self.value(a, component).map(|v| n * v)).sum(),
+            FieldLocation::Element => self.value(i, component),
+            FieldLocation::GaussPoints(points) => points.iter().enumerate()
+                .filter(|(_, q)| q.0 == i)
+                .map(|(k, q)| (k, (q.1.clone() - xi.clone()).length()))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .and_then(|(k, _)| self.value(k, component))
+        }
+    }
+}
+
+// Fields of one time or load step
+#[derive(Debug, Clone)]
+pub struct Step {
+    // Time, or load factor for static analyses
+    pub time: f64,
+    pub fields: Vec<Field>
+}
+
+impl Step {
+    pub fn field(&self, name: &str) -> Result<&Field, String> {
+        self.fields.iter().find(|f| f.name == name)
+            .ok_or_else(|| format!("no field '{}' at time {}", name, self.time))
+    }
+}
+
+// Result history of an analysis on one mesh, steps in increasing time
+pub struct Results<'a> {
+    mesh: &'a Mesh,
+    steps: Vec<Step>
+}
+
+impl<'a> Results<'a> {
+    pub fn new(mesh: &'a Mesh) -> Results<'a> {
+        Results { mesh, steps: Vec::new() }
+    }
+
+    pub fn mesh(&self) -> &'a Mesh { self.mesh }
+    pub fn steps(&self) -> &[Step] { &self.steps }
+
+    // Starts a new step and returns its index
+    pub fn add_step(&mut self, time: f64) -> Result<usize, String> {
+        if let Some(last) = self.steps.last() {
+            if time <= last.time {
+                return Err(format!("step at time {} does not follow time {}", time, last.time));
+            }
+        }
+        self.steps.push(Step { time, fields: Vec::new() });
+        Ok(self.steps.len() - 1)
+    }
+
+    pub fn add_field(&mut self, step: usize, field: Field) -> Result<(), String> {
+        field.check(self.mesh)?;
+        let s = self.steps.get_mut(step).ok_or_else(|| format!("no step {}", step))?;
+        if s.fields.iter().any(|f| f.name == field.name) {
+            return Err(format!("field '{}' already exists at time {}", field.name, s.time));
+        }
+        s.fields.push(field);
+        Ok(())
+    }
+
+    pub fn step(&self, step: usize) -> Result<&Step, String> {
+        self.steps.get(step).ok_or_else(|| format!("no step {}", step))
+    }
+
+    // Last step at or before `time`
+    pub fn step_at(&self, time: f64) -> Option<usize> {
+        self.steps.iter().rposition(|s| s.time <= time)
+    }
+
+    pub fn field(&self, step: usize, name: &str) -> Result<&Field, String> {
+        self.step(step)?.field(name)
+    }
+
+    // Smallest and largest value of a component over all steps holding the
+    // field, e.g. for a common color scale
+    pub fn range(&self, name: &str, component: &str) -> Result<(f64, f64), String> {
+        let mut range: Option<(f64, f64)> = None;
+        for f in self.steps.iter().filter_map(|s| s.field(name).ok()) {
+            let c = f.component(component)?;
+            if let (Some(min), Some(max)) = (f.min(c), f.max(c)) {
+                range = Some(range.map_or((min.1, max.1), |r| (r.0.min(min.1), r.1.max(max.1))));
+            }
+        }
+        range.ok_or_else(|| format!("no values of field '{}'", name))
+    }
+
+    // Value of a component at `p` in every step holding the field, as
+    // (time, value)
+    pub fn history(&self, name: &str, component: &str, p: &Vec2, tolerance: f64) -> Result<Vec<(f64, f64)>, String> {
+        let locator = PointLocator::new(self.mesh);
+        let mut history = Vec::new();
+        for s in &self.steps {
+            if let Ok(f) = s.field(name) {
+                let value = f.at(&locator, p, f.component(component)?, tolerance)
+                    .ok_or_else(|| format!("{:?} lies outside the mesh", p))?;
+                history.push((s.time, value));
+            }
+        }
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fem::shape::ElementKind;
+    use meshing;
+
+    fn mesh() -> Mesh {
+        meshing::rectangle(Vec2(0.0, 0.0), Vec2(2.0, 1.0), 2, 1, ElementKind::Quad4, "plate")
+    }
+
+    // u = (t x, -t y) at time t
+    fn displacement(mesh: &Mesh, t: f64) -> Field {
+        let u: Vec<Vec2> = mesh.nodes.iter().map(|p| Vec2(t * p.0, -t * p.1)).collect();
+        let mut f = Field::nodal_vectors("displacement", ["ux", "uy"], &u);
+        f.set_units("mm");
+        f
+    }
+
+    #[test]
+    fn steps_and_queries() {
+        let mesh = mesh();
+        let mut results = Results::new(&mesh);
+        for &t in &[0.5, 1.0] {
+            let step = results.add_step(t).unwrap();
+            results.add_field(step, displacement(&mesh, t)).unwrap();
+            let stress = Field::new("stress", FieldLocation::Element, &["sxx", "syy", "sxy"],
+                                    vec![t, 0.0, 0.0, 2.0 * t, 0.0, 0.0]).unwrap();
+            results.add_field(step, stress).unwrap();
+        }
+        assert!(results.add_step(0.7).is_err());
+        assert!(results.add_field(1, displacement(&mesh, 1.0)).is_err());
+        assert!(results.add_field(1, Field::new("short", FieldLocation::Nodal, &["a"], vec![1.0]).unwrap()).is_err());
+        assert!(Field::new("empty", FieldLocation::Nodal, &[], vec![]).is_err());
+
+        assert_eq!(results.step_at(0.7), Some(0));
+        assert_eq!(results.step_at(0.1), None);
+        let u = results.field(1, "displacement").unwrap();
+        assert_eq!(u.units, "mm");
+        let uy = u.component("uy").unwrap();
+        assert_eq!(u.min(uy), Some((3, -1.0)));
+        assert_eq!(u.max(uy).unwrap().1, 0.0);
+        assert!(u.component("uz").is_err());
+        assert!(results.field(0, "strain").is_err());
+
+        let s = results.field(1, "stress").unwrap();
+        assert_eq!(s.max(0), Some((1, 2.0)));
+        assert_eq!(s.max(3), None);
+        assert_eq!(s.value(1, 0), Some(2.0));
+        assert_eq!(s.value(1, 3), None);
+        assert_eq!(s.value(2, 0), None);
+        assert_eq!(results.range("stress", "sxx").unwrap(), (0.5, 2.0));
+
+        // interpolated nodal values, element constants
+        let locator = PointLocator::new(&mesh);
+        let p = Vec2(1.5, 0.25);
+        assert!((u.at(&locator, &p, 0, 1e-9).unwrap() - 1.5).abs() < 1e-12);
+        assert_eq!(s.at(&locator, &p, 0, 1e-9), Some(2.0));
+        assert_eq!(s.at(&locator, &Vec2(3.0, 0.0), 0, 1e-9), None);
+        assert_eq!(u.at(&locator, &p, 2, 1e-9), None);
+        let history = results.history("displacement", "uy", &p, 1e-9).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!((history[0].1 + 0.125).abs() < 1e-12 && (history[1].1 + 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gauss_point_fields() {
+        let mesh = mesh();
+        let points: Vec<(usize, Vec2)> = mesh.elements.iter().enumerate()
+            .flat_map(|(i, e)| e.kind.quadrature(2).into_iter().map(move |q| (i, q.point)))
+            .collect();
+        let values = (0..points.len()).map(|k| k as f64).collect();
+        let f = Field::new("plastic strain", FieldLocation::GaussPoints(points.clone()), &["eq"], values).unwrap();
+        let mut results = Results::new(&mesh);
+        let step = results.add_step(1.0).unwrap();
+        results.add_field(step, f.clone()).unwrap();
+        assert_eq!(f.entity_count(), 8);
+        let locator = PointLocator::new(&mesh);
+        // the value of the closest integration point
+        for (k, (e, xi)) in points.iter().enumerate() {
+            let n = mesh.elements[*e].kind.shape_functions(xi);
+            let x = n.iter().zip(&mesh.elements[*e].nodes)
+                .fold(Vec2(0.0, 0.0), |acc, (n, &a)| acc + mesh.nodes[a].clone() * *n);
+            assert_eq!(f.at(&locator, &x, 0, 1e-9), Some(k as f64));
+        }
+
+        let outside = Field::new("bad", FieldLocation::GaussPoints(vec![(5, Vec2(0.0, 0.0))]), &["eq"], vec![0.0]).unwrap();
+        assert!(results.add_field(step, outside).is_err());
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::VecDeque;
+use base_types::CsrMatrix;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reordering {
+    Natural,
+    // Reduces the bandwidth, and therefore the skyline profile
+    ReverseCuthillMcKee
+}
+
+// Symmetric adjacency (pattern of A + A^T without the diagonal)
+fn adjacency(a: &CsrMatrix) -> Vec<Vec<usize>> {
+    let mut adj = vec![Vec::new(); a.nrows()];
+    for i in 0..a.nrows() {
+        for (j, _) in a.row(i) {
+            if i != j {
+                adj[i].push(j);
+                adj[j].push(i);
+            }
+        }
+    }
+    for list in adj.iter_mut() {
+        list.sort();
+        list.dedup();
+    }
+    adj
+}
+
+// Breadth-first level structure rooted at `root`, limited to unvisited nodes.
+fn level_structure(adj: &[Vec<usize>], root: usize, visited: &[bool]) -> Vec<Vec<usize>> {
+    let mut seen = visited.to_vec();
+    seen[root] = true;
+    let mut levels = vec![vec![root]];
+    loop {
+        let mut next = Vec::new();
+        for &v in levels.last().unwrap() {
+            for &w in &adj[v] {
+                if !seen[w] {
+                    seen[w] = true;
+                    next.push(w);
+                }
+            }
+        }
+        if next.is_empty() {
+            return levels;
+        }
+        levels.push(next);
+    }
+}
+
+// George-Liu heuristic for a node of (nearly) maximal eccentricity.
+fn pseudo_peripheral(adj: &[Vec<usize>], start: usize, visited: &[bool]) -> usize {
+    let mut root = start;
+    let mut levels = level_structure(adj, root, visited);
+    loop {
+        let candidate = *levels.last().unwrap().iter()
+            .min_by_key(|&&v| adj[v].len())
+            .unwrap();
+        let candidate_levels = level_structure(adj, candidate, visited);
+        if candidate_levels.len() <= levels.len() {
+            return root;
+        }
+        root = candidate;
+        levels = candidate_levels;
+    }
+}
+
+// Returns `perm` with perm[new] = old.
+pub fn reverse_cuthill_mckee(a: &CsrMatrix) -> Vec<usize> {
+    let n = a.nrows();
+    let adj = adjacency(a);
+    let mut visited = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+
+    while order.len() < n {
+        let start = (0..n).filter(|&v| !visited[v])
+            .min_by_key(|&v| adj[v].len())
+            .unwrap();
+        let root = pseudo_peripheral(&adj, start, &visited);
+
+        let mut queue = VecDeque::new();
+        visited[root] = true;
+        queue.push_back(root);
+        while let Some(v) = queue.pop_front() {
+            order.push(v);
+            let mut next: Vec<usize> = adj[v].iter().cloned().filter(|&w| !visited[w]).collect();
+            next.sort_by_key(|&w| adj[w].len());
+            for w in next {
+                visited[w] = true;
+                queue.push_back(w);
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+// LDL^T only reads one triangle, so a non-symmetric matrix would silently be
+// replaced by a symmetric one; those need one of the iterative solvers.
+fn check_symmetric(a: &CsrMatrix) -> Result<(), String> {
+    let scale = a.values().iter().fold(0.0, |m: f64, v| m.max(v.abs()));
+    for i in 0..a.nrows() {
+        for (j, v) in a.row(i).filter(|&(j, _)| j > i) {
+            if (v - a.get(j, i)).abs() > 1e-10 * scale {
+                return Err(format!("Skyline: matrix is not symmetric (entries ({}, {}) and ({}, {}) differ)",
+                                   i, j, j, i));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Root-free skyline Cholesky factorisation A = L D L^T of a symmetric matrix.
+// `a` must hold both triangles: the upper triangle of the reordered matrix is
+// read, which draws on either triangle of `a` once rows and columns are
+// permuted. Because the pivots are not required to be positive, symmetric
+// indefinite (but non-singular) matrices such as the shifted K - sigma M of an
+// eigenproblem can be factored as well.
+#[derive(Debug, Clone)]
+pub struct SkylineCholesky {
+    // perm[new] = old, inv_perm[old] = new
+    perm: Vec<usize>,
+    inv_perm: Vec<usize>,
+    // first non-zero row of every column
+    first: Vec<usize>,
+    // start of every column in `values`, column j holds rows first[j]..=j
+    offset: Vec<usize>,
+    // L below the diagonal (stored transposed, by columns) and D on it
+    values: Vec<f64>
+}
+
+impl SkylineCholesky {
+    pub fn factor(a: &CsrMatrix, reordering: Reordering) -> Result<SkylineCholesky, String> {
+        if a.nrows() != a.ncols() {
+            return Err(format!("Skyline: matrix is not square ({}x{})", a.nrows(), a.ncols()));
+        }
+        check_symmetric(a)?;
+        let n = a.nrows();
+        let perm = match reordering {
+            Reordering::Natural => (0..n).collect(),
+            Reordering::ReverseCuthillMcKee => reverse_cuthill_mckee(a)
+        };
+        let mut inv_perm = vec![0; n];
+        for (new, &old) in perm.iter().enumerate() {
+            inv_perm[old] = new;
+        }
+
+        let mut first: Vec<usize> = (0..n).collect();
+        for i in 0..n {
+            for (j, _) in a.row(i) {
+                let (r, c) = (inv_perm[i], inv_perm[j]);
+                let (r, c) = if r < c { (r, c) } else { (c, r) };
+                first[c] = first[c].min(r);
+            }
+        }
+
+        let mut offset = Vec::with_capacity(n + 1);
+        offset.push(0);
+        for j in 0..n {
+            let last = offset[j];
+            offset.push(last + j - first[j] + 1);
+        }
+
+        let mut values = vec![0.0; offset[n]];
+        for i in 0..n {
+            for (j, v) in a.row(i) {
+                let (r, c) = (inv_perm[i], inv_perm[j]);
+                if r <= c {
+                    values[offset[c] + r - first[c]] = v;
+                }
+            }
+        }
+
+        let mut factor = SkylineCholesky { perm, inv_perm, first, offset, values };
+        factor.decompose()?;
+        Ok(factor)
+    }
+
+    fn decompose(&mut self) -> Result<(), String> {
+        let n = self.first.len();
+        let scale = (0..n).map(|j| self.values[self.offset[j] + j - self.first[j]].abs())
+            .fold(0.0, f64::max);
+
+        for j in 0..n {
+            let fj = self.first[j];
+            let cj = self.offset[j];
+
+            for i in fj + 1..j {
+                let fi = self.first[i];
+                let ci = self.offset[i];
+                let r0 = fi.max(fj);
+                let s: f64 = (r0..i)
+                    .map(|r| self.values[ci + r - fi] * self.values[cj + r - fj])
+                    .sum();
+                self.values[cj + i - fj] -= s;
+            }
+
+            let mut d = self.values[cj + j - fj];
+            for i in fj..j {
+                let g = self.values[cj + i - fj];
+                let l = g / self.values[self.offset[i] + i - self.first[i]];
+                d -= l * g;
+                self.values[cj + i - fj] = l;
+            }
+            if d.abs() <= 1e-14 * scale || !d.is_finite() {
+                return Err(format!("Skyline: matrix is singular (pivot {:e} at row {})",
+                                   d, self.perm[j]));
+            }
+            self.values[cj + j - fj] = d;
+        }
+        Ok(())
+    }
+
+    pub fn size(&self) -> usize { self.first.len() }
+
+    // Number of stored coefficients, the quantity the reordering minimises.
+    pub fn profile(&self) -> usize { self.values.len() }
+
+    fn pivot(&self, j: usize) -> f64 {
+        self.values[self.offset[j] + j - self.first[j]]
+    }
+
+    // Number of negative pivots, i.e. of negative eigenvalues (Sylvester).
+    pub fn negative_pivots(&self) -> usize {
+        (0..self.size()).filter(|&j| self.pivot(j) < 0.0).count()
+    }
+
+    pub fn is_positive_definite(&self) -> bool {
+        self.negative_pivots() == 0
+    }
+
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let mut x = b.to_vec();
+        self.solve_in_place(&mut x);
+        x
+    }
+
+    pub fn solve_in_place(&self, b: &mut [f64]) {
+        let n = self.size();
+        assert_eq!(b.len(), n);
+        let mut z: Vec<f64> = self.perm.iter().map(|&old| b[old]).collect();
+
+        for j in 0..n {
+            let fj = self.first[j];
+            let cj = self.offset[j];
+            let s: f64 = (fj..j).map(|r| self.values[cj + r - fj] * z[r]).sum();
+            z[j] -= s;
+        }
+        for (j, zj) in z.iter_mut().enumerate() {
+            *zj /= self.pivot(j);
+        }
+        for j in (0..n).rev() {
+            let fj = self.first[j];
+            let cj = self.offset[j];
+            let zj = z[j];
+            let column = &self.values[cj..cj + j - fj];
+            z[fj..j].iter_mut().zip(column).for_each(|(zr, l)| *zr -= l * zj);
+        }
+
+        for (old, bi) in b.iter_mut().enumerate() {
+            *bi = z[self.inv_perm[old]];
+        }
+    }
+
+    pub fn solve_many(&self, rhs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        rhs.iter().map(|b| self.solve(b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base_types::TripletMatrix;
+
+    fn laplacian(n: usize, shuffle: bool) -> CsrMatrix {
+        // a fixed pseudo-random relabeling that destroys the band structure
+        let label = |k: usize| if shuffle { (k * 37 + 11) % (n * n) } else { k };
+        let mut t = TripletMatrix::new(n * n, n * n);
+        for i in 0..n {
+            for j in 0..n {
+                let k = i * n + j;
+                t.add(label(k), label(k), 4.0);
+                if i + 1 < n {
+                    t.add(label(k), label(k + n), -1.0);
+                    t.add(label(k + n), label(k), -1.0);
+                }
+                if j + 1 < n {
+                    t.add(label(k), label(k + 1), -1.0);
+                    t.add(label(k + 1), label(k), -1.0);
+                }
+            }
+        }
+        t.to_csr()
+    }
+
+    fn assert_solves(a: &CsrMatrix, f: &SkylineCholesky) {
+        let x: Vec<f64> = (0..a.nrows()).map(|i| (i as f64).cos()).collect();
+        let sol = f.solve(&a.mul_vec(&x));
+        for (s, e) in sol.iter().zip(&x) {
+            assert!((s - e).abs() < 1e-10, "{} != {}", s, e);
+        }
+    }
+
+    #[test]
+    fn solves_spd() {
+        let a = laplacian(7, false);
+        let f = SkylineCholesky::factor(&a, Reordering::Natural).unwrap();
+        assert!(f.is_positive_definite());
+        assert_solves(&a, &f);
+    }
+
+    #[test]
+    fn rcm_reduces_profile() {
+        let a = laplacian(10, true);
+        let natural = SkylineCholesky::factor(&a, Reordering::Natural).unwrap();
+        let rcm = SkylineCholesky::factor(&a, Reordering::ReverseCuthillMcKee).unwrap();
+        assert!(rcm.profile() * 2 < natural.profile(),
+                "{} vs {}", rcm.profile(), natural.profile());
+        assert_solves(&a, &natural);
+        assert_solves(&a, &rcm);
+
+        let mut perm = reverse_cuthill_mckee(&a);
+        perm.sort();
+        assert_eq!(perm, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn many_right_hand_sides() {
+        let a = laplacian(5, true);
+        let f = SkylineCholesky::factor(&a, Reordering::ReverseCuthillMcKee).unwrap();
+        let rhs: Vec<Vec<f64>> = (0..3).map(|k| vec![k as f64 + 1.0; 25]).collect();
+        let sols = f.solve_many(&rhs);
+        for (b, x) in rhs.iter().zip(&sols) {
+            let ax = a.mul_vec(x);
+            assert!(ax.iter().zip(b).all(|(u, v)| (u - v).abs() < 1e-10));
+        }
+    }
+
+    #[test]
+    fn indefinite_and_singular() {
+        // eigenvalues 3 and -1
+        let mut t = TripletMatrix::new(2, 2);
+        t.add(0, 0, 1.0);
+        t.add(0, 1, 2.0);
+        t.add(1, 0, 2.0);
+        t.add(1, 1, 1.0);
+        let a = t.to_csr();
+        let f = SkylineCholesky::factor(&a, Reordering::Natural).unwrap();
+        assert_eq!(f.negative_pivots(), 1);
+        assert_solves(&a, &f);
+
+        let mut t = TripletMatrix::new(2, 2);
+        t.add(0, 0, 1.0);
+        t.add(0, 1, 1.0);
+        t.add(1, 0, 1.0);
+        t.add(1, 1, 1.0);
+        assert!(SkylineCholesky::factor(&t.to_csr(), Reordering::Natural).is_err());
+    }
+
+    #[test]
+    fn rejects_non_symmetric() {
+        let mut t = TripletMatrix::new(2, 2);
+        t.add(0, 0, 2.0);
+        t.add(0, 1, 1.0);
+        t.add(1, 1, 2.0);
+        let a = t.to_csr();
+        assert!(SkylineCholesky::factor(&a, Reordering::Natural).is_err());
+        assert!(::solvers::LinearSolver::default().solve(&a, &[1.0, 1.0]).is_err());
+    }
+}
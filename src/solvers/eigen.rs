@@ -0,0 +1,218 @@
+use base_types::*;
+use super::direct::{SkylineCholesky, Reordering};
+use super::dot;
+
+#[derive(Debug, Clone)]
+pub struct EigenOptions {
+    // Number of eigenpairs wanted
+    pub count: usize,
+    // The eigenvalues closest to the shift are found; a small negative shift
+    // makes structures with rigid body modes tractable
+    pub shift: f64,
+    // Relative change of the eigenvalues between iterations
+    pub tolerance: f64,
+    pub max_iterations: usize
+}
+
+impl Default for EigenOptions {
+    fn default() -> EigenOptions {
+        EigenOptions { count: 6, shift: 0.0, tolerance: 1e-10, max_iterations: 200 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EigenPairs {
+    // Ascending eigenvalues
+    pub values: Vec<f64>,
+    // M-orthonormal eigenvectors, x_i^T M x_j = delta_ij
+    pub vectors: Vec<Vec<f64>>,
+    pub iterations: usize
+}
+
+// Small pseudo-random start vectors, so results are reproducible.
+fn start_vectors(m: &CsrMatrix, count: usize) -> Vec<Vec<f64>> {
+    let n = m.nrows();
+    let diag = m.diagonal();
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        (seed >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+    };
+    (0..count).map(|j| {
+        (0..n).map(|i| {
+            let w = if diag[i] > 0.0 { diag[i] } else { 1.0 };
+            if j == 0 { w } else { w * next() }
+        }).collect()
+    }).collect()
+}
+
+// Subspace iteration with shift-invert for K x = lambda M x, K and M
+// symmetric and either M positive semi-definite or K - sigma M positive
+// definite. The shifted matrix is factored once; every iteration applies its
+// inverse to M X and solves the projected problem on the subspace. With an
+// indefinite M the vectors are normalized to |x^T M x| = 1.
+pub fn subspace_iteration(k: &CsrMatrix, m: &CsrMatrix, options: &EigenOptions)
+    -> Result<EigenPairs, String> {
+    let n = k.nrows();
+    if m.nrows() != n || k.ncols() != n || m.ncols() != n {
+        return Err("eigen: K and M must be square and of the same size".to_string());
+    }
+    let p = options.count;
+    if p == 0 || p > n {
+        return Err(format!("eigen: cannot compute {} eigenpairs of a system of size {}", p, n));
+    }
+    let q = (2 * p).max(p + 8).min(n);
+    let sigma = options.shift;
+    let factor = SkylineCholesky::factor(&k.scaled_sum(1.0, m, -sigma), Reordering::ReverseCuthillMcKee)
+        .map_err(|e| format!("eigen: cannot factor the shifted matrix, try another shift ({})", e))?;
+    let positive = factor.is_positive_definite();
+
+    let mut x = start_vectors(m, q);
+    let mut previous: Vec<f64> = vec![f64::INFINITY; p];
+    for iteration in 1..options.max_iterations + 1 {
+        let y: Vec<Vec<f64>> = x.iter().map(|xi| m.mul_vec(xi)).collect();
+        let xbar = factor.solve_many(&y);
+        let mxbar: Vec<Vec<f64>> = xbar.iter().map(|xi| m.mul_vec(xi)).collect();
+
+        // projected (K - sigma M) and M
+        let mut kr = DMatrix::zeros(q, q);
+        let mut mr = DMatrix::zeros(q, q);
+        for i in 0..q {
+            for j in i..q {
+                kr[(i, j)] = dot(&xbar[i], &y[j]);
+                kr[(j, i)] = kr[(i, j)];
+                mr[(i, j)] = dot(&xbar[i], &mxbar[j]);
+                mr[(j, i)] = mr[(i, j)];
+            }
+        }
+
+        let (mu, phi, order) = if positive {
+            // M_r z = nu K_r z with nu = 1 / mu through K_r = L L^T, which
+            // allows an indefinite M such as the geometric stiffness
+            let linv = kr.cholesky()
+                .map_err(|_| "eigen: subspace lost rank".to_string())?
+                .l().inverse()?;
+            let (nu, z) = linv.matmul(&mr).matmul(&linv.transpose()).symmetric_eigen();
+            let mut phi = linv.tr_matmul(&z);
+            for c in 0..q {
+                let mc = phi.column(c);
+                let s = dot(&mc, &mr.mul_vec(&mc)).abs();
+                if s > 0.0 {
+                    (0..q).for_each(|i| phi[(i, c)] /= s.sqrt());
+                }
+            }
+            let mut order: Vec<usize> = (0..q).collect();
+            order.sort_by(|&a, &b| nu[b].abs().partial_cmp(&nu[a].abs()).unwrap());
+            (nu.iter().map(|v| 1.0 / v).collect::<Vec<f64>>(), phi, order)
+        } else {
+            // K_r z = mu M_r z through M_r = L L^T
+            let linv = mr.cholesky()
+                .map_err(|_| "eigen: subspace lost rank, M may be singular on the free dofs".to_string())?
+                .l().inverse()?;
+            let (mu, z) = linv.matmul(&kr).matmul(&linv.transpose()).symmetric_eigen();
+            // most dominant first, i.e. closest to the shift
+            let mut order: Vec<usize> = (0..q).collect();
+            order.sort_by(|&a, &b| mu[a].abs().partial_cmp(&mu[b].abs()).unwrap());
+            (mu, linv.tr_matmul(&z), order)
+        };
+
+        x = order.iter().map(|&c| {
+            let mut v = vec![0.0; n];
+            for (i, xi) in xbar.iter().enumerate() {
+                let f = phi[(i, c)];
+                v.iter_mut().zip(xi).for_each(|(vk, xk)| *vk += f * xk);
+            }
+            v
+        }).collect();
+
+        let values: Vec<f64> = order.iter().take(p).map(|&c| mu[c] + sigma).collect();
+        let scale = values.iter().fold(sigma.abs(), |s, v| s.max(v.abs()));
+        let converged = values.iter().zip(&previous)
+            .all(|(v, old)| (v - old).abs() <= options.tolerance * v.abs().max(1e-3 * scale));
+        previous = values;
+
+        if converged {
+            let mut pairs: Vec<(f64, Vec<f64>)> = previous.into_iter().zip(x).collect();
+            pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            let (values, vectors) = pairs.into_iter().unzip();
+            return Ok(EigenPairs { values, vectors, iterations: iteration });
+        }
+    }
+    Err(format!("eigen: no convergence after {} iterations", options.max_iterations))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn chain(n: usize, diagonal: f64) -> CsrMatrix {
+        let mut t = TripletMatrix::new(n, n);
+        for i in 0..n {
+            t.add(i, i, diagonal);
+            if i + 1 < n {
+                t.add(i, i + 1, -1.0);
+                t.add(i + 1, i, -1.0);
+            }
+        }
+        t.to_csr()
+    }
+
+    #[test]
+    fn spring_chain() {
+        // eigenvalues of tridiag(-1, 2, -1): 2 - 2 cos(j pi / (n + 1))
+        let n = 60;
+        let k = chain(n, 2.0);
+        let mut m = TripletMatrix::new(n, n);
+        (0..n).for_each(|i| m.add(i, i, 2.0));
+        let m = m.to_csr();
+        let pairs = subspace_iteration(&k, &m, &EigenOptions { count: 4, ..Default::default() }).unwrap();
+        for (j, value) in pairs.values.iter().enumerate() {
+            let exact = (2.0 - 2.0 * (PI * (j + 1) as f64 / (n + 1) as f64).cos()) / 2.0;
+            assert!((value - exact).abs() < 1e-9 * exact, "{} vs {}", value, exact);
+        }
+        for (i, a) in pairs.vectors.iter().enumerate() {
+            for (j, b) in pairs.vectors.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((dot(a, &m.mul_vec(b)) - expected).abs() < 1e-9);
+            }
+            let residual: f64 = k.mul_vec(a).iter().zip(m.mul_vec(a))
+                .map(|(ka, ma)| (ka - pairs.values[i] * ma).abs()).fold(0.0, f64::max);
+            assert!(residual < 1e-6);
+        }
+    }
+
+    #[test]
+    fn shift_selects_interior_eigenvalues() {
+        // diag(1..n) has eigenvalues 1..n; around 10.2 the closest are 10, 11, 9
+        let n = 30;
+        let mut k = TripletMatrix::new(n, n);
+        (0..n).for_each(|i| k.add(i, i, (i + 1) as f64));
+        let options = EigenOptions { count: 3, shift: 10.2, ..Default::default() };
+        let pairs = subspace_iteration(&k.to_csr(), &CsrMatrix::identity(n), &options).unwrap();
+        assert_eq!(pairs.values.len(), 3);
+        for (v, e) in pairs.values.iter().zip(&[9.0, 10.0, 11.0]) {
+            assert!((v - e).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn singular_stiffness_needs_a_shift() {
+        // free-free chain with a zero eigenvalue
+        let n = 20;
+        let mut k = TripletMatrix::new(n, n);
+        for i in 0..n - 1 {
+            k.add(i, i, 1.0);
+            k.add(i + 1, i + 1, 1.0);
+            k.add(i, i + 1, -1.0);
+            k.add(i + 1, i, -1.0);
+        }
+        let k = k.to_csr();
+        let m = CsrMatrix::identity(n);
+        assert!(subspace_iteration(&k, &m, &EigenOptions { count: 2, ..Default::default() }).is_err());
+        let options = EigenOptions { count: 2, shift: -0.1, ..Default::default() };
+        let pairs = subspace_iteration(&k, &m, &options).unwrap();
+        assert!(pairs.values[0].abs() < 1e-10);
+        assert!((pairs.values[1] - (2.0 - 2.0 * (PI / n as f64).cos())).abs() < 1e-9);
+    }
+}
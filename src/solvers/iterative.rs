@@ -0,0 +1,396 @@
+use base_types::CsrMatrix;
+use super::preconditioner::Preconditioner;
+use super::{dot, norm, axpy};
+
+#[derive(Debug, Clone)]
+pub struct SolverOptions {
+    // Convergence is reached when ||b - Ax|| / ||b|| < tolerance
+    pub tolerance: f64,
+    pub max_iterations: usize,
+    // Krylov subspace size between GMRES restarts
+    pub restart: usize
+}
+
+impl Default for SolverOptions {
+    fn default() -> SolverOptions {
+        SolverOptions { tolerance: 1e-10, max_iterations: 1000, restart: 30 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Convergence {
+    pub converged: bool,
+    pub iterations: usize,
+    // Relative residual norm, starting with the initial guess
+    pub residuals: Vec<f64>
+}
+
+impl Convergence {
+    fn new(initial: f64) -> Convergence {
+        Convergence { converged: false, iterations: 0, residuals: vec![initial] }
+    }
+
+    fn push(&mut self, residual: f64, tolerance: f64) -> bool {
+        self.iterations += 1;
+        self.residuals.push(residual);
+        self.converged = residual < tolerance;
+        self.converged
+    }
+
+    pub fn final_residual(&self) -> f64 {
+        *self.residuals.last().unwrap()
+    }
+
+    pub fn into_result(self, method: &str) -> Result<Convergence, String> {
+        if self.converged {
+            Ok(self)
+        } else {
+            Err(format!("{} did not converge after {} iterations (residual {:e})",
+                        method, self.iterations, self.final_residual()))
+        }
+    }
+}
+
+fn residual(a: &CsrMatrix, b: &[f64], x: &[f64]) -> Vec<f64> {
+    let mut r = a.mul_vec(x);
+    r.iter_mut().zip(b).for_each(|(ri, bi)| *ri = bi - *ri);
+    r
+}
+
+fn check_dims(a: &CsrMatrix, b: &[f64], x: &[f64]) {
+    assert_eq!(a.nrows(), a.ncols(), "iterative solvers need a square matrix");
+    assert_eq!(b.len(), a.nrows());
+    assert_eq!(x.len(), a.ncols());
+}
+
+// Preconditioned conjugate gradient for symmetric positive definite systems.
+// `x` holds the initial guess and receives the solution.
+pub fn conjugate_gradient<P: Preconditioner + ?Sized>(
+    a: &CsrMatrix, b: &[f64], x: &mut [f64], precond: &P, options: &SolverOptions)
+    -> Convergence {
+    check_dims(a, b, x);
+    let n = b.len();
+    let b_norm = norm(b);
+    if b_norm == 0.0 {
+        x.iter_mut().for_each(|xi| *xi = 0.0);
+        return Convergence { converged: true, iterations: 0, residuals: vec![0.0] };
+    }
+
+    let mut r = residual(a, b, x);
+    let mut history = Convergence::new(norm(&r) / b_norm);
+    if history.final_residual() < options.tolerance {
+        history.converged = true;
+        return history;
+    }
+
+    let mut z = vec![0.0; n];
+    precond.apply(&r, &mut z);
+    let mut p = z.clone();
+    let mut rz = dot(&r, &z);
+    let mut ap = vec![0.0; n];
+
+    for _ in 0..options.max_iterations {
+        a.mul_vec_into(&p, &mut ap);
+        let pap = dot(&p, &ap);
+        if pap <= 0.0 {
+            // matrix (or preconditioner) is not positive definite
+            break;
+        }
+        let alpha = rz / pap;
+        axpy(alpha, &p, x);
+        axpy(-alpha, &ap, &mut r);
+
+        if history.push(norm(&r) / b_norm, options.tolerance) {
+            break;
+        }
+
+        precond.apply(&r, &mut z);
+        let rz_new = dot(&r, &z);
+        let beta = rz_new / rz;
+        rz = rz_new;
+        p.iter_mut().zip(&z).for_each(|(pi, zi)| *pi = zi + beta * *pi);
+    }
+    history
+}
+
+// Right-preconditioned BiCGSTAB for general non-symmetric systems.
+pub fn bicgstab<P: Preconditioner + ?Sized>(
+    a: &CsrMatrix, b: &[f64], x: &mut [f64], precond: &P, options: &SolverOptions)
+    -> Convergence {
+    check_dims(a, b, x);
+    let n = b.len();
+    let b_norm = norm(b);
+    if b_norm == 0.0 {
+        x.iter_mut().for_each(|xi| *xi = 0.0);
+        return Convergence { converged: true, iterations: 0, residuals: vec![0.0] };
+    }
+
+    let mut r = residual(a, b, x);
+    let mut history = Convergence::new(norm(&r) / b_norm);
+    if history.final_residual() < options.tolerance {
+        history.converged = true;
+        return history;
+    }
+
+    let r_hat = r.clone();
+    let (mut rho, mut alpha, mut omega) = (1.0, 1.0, 1.0);
+    let mut v = vec![0.0; n];
+    let mut p = vec![0.0; n];
+    let mut p_hat = vec![0.0; n];
+    let mut s_hat = vec![0.0; n];
+    let mut t = vec![0.0; n];
+
+    for _ in 0..options.max_iterations {
+        let rho_new = dot(&r_hat, &r);
+        if rho_new == 0.0 || omega == 0.0 {
+            break;
+        }
+        let beta = (rho_new / rho) * (alpha / omega);
+        rho = rho_new;
+        for i in 0..n {
+            p[i] = r[i] + beta * (p[i] - omega * v[i]);
+        }
+
+        precond.apply(&p, &mut p_hat);
+        a.mul_vec_into(&p_hat, &mut v);
+        let r_hat_v = dot(&r_hat, &v);
+        if r_hat_v == 0.0 {
+            break;
+        }
+        alpha = rho / r_hat_v;
+
+        // r becomes s = r - alpha v
+        axpy(-alpha, &v, &mut r);
+        axpy(alpha, &p_hat, x);
+        let s_norm = norm(&r) / b_norm;
+        if s_norm < options.tolerance {
+            history.push(s_norm, options.tolerance);
+            break;
+        }
+
+        precond.apply(&r, &mut s_hat);
+        a.mul_vec_into(&s_hat, &mut t);
+        let tt = dot(&t, &t);
+        omega = if tt > 0.0 { dot(&t, &r) / tt } else { 0.0 };
+        axpy(omega, &s_hat, x);
+        axpy(-omega, &t, &mut r);
+
+        if history.push(norm(&r) / b_norm, options.tolerance) {
+            break;
+        }
+    }
+    history
+}
+
+// Restarted GMRES(m) with right preconditioning.
+pub fn gmres<P: Preconditioner + ?Sized>(
+    a: &CsrMatrix, b: &[f64], x: &mut [f64], precond: &P, options: &SolverOptions)
+    -> Convergence {
+    check_dims(a, b, x);
+    let n = b.len();
+    let m = options.restart.max(1);
+    let b_norm = norm(b);
+    if b_norm == 0.0 {
+        x.iter_mut().for_each(|xi| *xi = 0.0);
+        return Convergence { converged: true, iterations: 0, residuals: vec![0.0] };
+    }
+
+    let mut r = residual(a, b, x);
+    let mut history = Convergence::new(norm(&r) / b_norm);
+    if history.final_residual() < options.tolerance {
+        history.converged = true;
+        return history;
+    }
+
+    let mut w = vec![0.0; n];
+    let mut z = vec![0.0; n];
+
+    while history.iterations < options.max_iterations && !history.converged {
+        let beta = norm(&r);
+        let mut basis: Vec<Vec<f64>> = vec![r.iter().map(|ri| ri / beta).collect()];
+        // Hessenberg matrix, stored by columns
+        let mut h: Vec<Vec<f64>> = Vec::with_capacity(m);
+        let mut cs: Vec<f64> = Vec::with_capacity(m);
+        let mut sn: Vec<f64> = Vec::with_capacity(m);
+        let mut g = vec![0.0; m + 1];
+        g[0] = beta;
+
+        for j in 0..m {
+            precond.apply(&basis[j], &mut z);
+            a.mul_vec_into(&z, &mut w);
+
+            // modified Gram-Schmidt
+            let mut col = vec![0.0; j + 2];
+            for (i, vi) in basis.iter().enumerate() {
+                col[i] = dot(&w, vi);
+                axpy(-col[i], vi, &mut w);
+            }
+            col[j + 1] = norm(&w);
+
+            for i in 0..j {
+                let tmp = cs[i] * col[i] + sn[i] * col[i + 1];
+                col[i + 1] = -sn[i] * col[i] + cs[i] * col[i + 1];
+                col[i] = tmp;
+            }
+            let denom = col[j].hypot(col[j + 1]);
+            let (c, s) = if denom == 0.0 { (1.0, 0.0) } else { (col[j] / denom, col[j + 1] / denom) };
+            cs.push(c);
+            sn.push(s);
+            col[j] = denom;
+            col[j + 1] = 0.0;
+            g[j + 1] = -s * g[j];
+            g[j] *= c;
+
+            let next_norm = norm(&w);
+            if next_norm > 0.0 {
+                basis.push(w.iter().map(|wi| wi / next_norm).collect());
+            }
+            h.push(col);
+
+            let done = history.push(g[j + 1].abs() / b_norm, options.tolerance);
+            if done || next_norm == 0.0 || history.iterations >= options.max_iterations {
+                break;
+            }
+        }
+
+        // back substitution for the Krylov coefficients
+        let k = h.len();
+        let mut y = vec![0.0; k];
+        for i in (0..k).rev() {
+            let s: f64 = (i + 1..k).map(|j| h[j][i] * y[j]).sum();
+            y[i] = if h[i][i] != 0.0 { (g[i] - s) / h[i][i] } else { 0.0 };
+        }
+
+        let mut update = vec![0.0; n];
+        for (yi, vi) in y.iter().zip(&basis) {
+            axpy(*yi, vi, &mut update);
+        }
+        precond.apply(&update, &mut z);
+        axpy(1.0, &z, x);
+
+        r = residual(a, b, x);
+        let true_residual = norm(&r) / b_norm;
+        *history.residuals.last_mut().unwrap() = true_residual;
+        history.converged = true_residual < options.tolerance;
+        if k == 0 || g[k].abs() == 0.0 && !history.converged {
+            // stagnation: the Krylov space cannot improve the solution any further
+            break;
+        }
+    }
+    history
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base_types::TripletMatrix;
+    use solvers::preconditioner::*;
+
+    // 2D Laplacian on an n x n grid (SPD)
+    fn laplacian(n: usize) -> CsrMatrix {
+        let mut t = TripletMatrix::new(n * n, n * n);
+        for i in 0..n {
+            for j in 0..n {
+                let k = i * n + j;
+                t.add(k, k, 4.0);
+                if i > 0 { t.add(k, k - n, -1.0); }
+                if i + 1 < n { t.add(k, k + n, -1.0); }
+                if j > 0 { t.add(k, k - 1, -1.0); }
+                if j + 1 < n { t.add(k, k + 1, -1.0); }
+            }
+        }
+        t.to_csr()
+    }
+
+    // 1D convection-diffusion (non-symmetric)
+    fn convection(n: usize) -> CsrMatrix {
+        let mut t = TripletMatrix::new(n, n);
+        for i in 0..n {
+            t.add(i, i, 2.5);
+            if i > 0 { t.add(i, i - 1, -1.5); }
+            if i + 1 < n { t.add(i, i + 1, -0.5); }
+        }
+        t.to_csr()
+    }
+
+    fn check(a: &CsrMatrix, expected: &[f64], solve: &dyn Fn(&[f64], &mut [f64]) -> Convergence) {
+        let b = a.mul_vec(expected);
+        let mut x = vec![0.0; b.len()];
+        let history = solve(&b, &mut x);
+        assert!(history.converged, "{:?}", history);
+        assert_eq!(history.residuals.len(), history.iterations + 1);
+        for (xi, ei) in x.iter().zip(expected) {
+            assert!((xi - ei).abs() < 1e-7, "{} != {}", xi, ei);
+        }
+    }
+
+    fn expected(n: usize) -> Vec<f64> {
+        (0..n).map(|i| (i as f64 * 0.37).sin() + 1.0).collect()
+    }
+
+    #[test]
+    fn cg_with_preconditioners() {
+        let a = laplacian(8);
+        let x = expected(64);
+        let opts = SolverOptions::default();
+        let mut iterations = Vec::new();
+        let preconds: Vec<Box<dyn Preconditioner>> = vec![
+            Box::new(Identity),
+            Box::new(Jacobi::new(&a).unwrap()),
+            Box::new(Ssor::new(&a, 1.2).unwrap()),
+            Box::new(Ic0::new(&a).unwrap()),
+        ];
+        for p in &preconds {
+            check(&a, &x, &|b, x| conjugate_gradient(&a, b, x, p.as_ref(), &opts));
+            let mut x0 = vec![0.0; 64];
+            let h = conjugate_gradient(&a, &a.mul_vec(&x), &mut x0, p.as_ref(), &opts);
+            iterations.push(h.iterations);
+        }
+        // IC(0) must beat plain CG
+        assert!(iterations[3] < iterations[0]);
+    }
+
+    #[test]
+    fn bicgstab_nonsymmetric() {
+        let a = convection(50);
+        let x = expected(50);
+        let opts = SolverOptions::default();
+        check(&a, &x, &|b, x| bicgstab(&a, b, x, &Identity, &opts));
+        check(&a, &x, &|b, x| bicgstab(&a, b, x, &Jacobi::new(&a).unwrap(), &opts));
+        check(&a, &x, &|b, x| bicgstab(&a, b, x, &Ilu0::new(&a).unwrap(), &opts));
+    }
+
+    #[test]
+    fn gmres_nonsymmetric() {
+        let a = convection(50);
+        let x = expected(50);
+        let opts = SolverOptions { restart: 10, ..SolverOptions::default() };
+        check(&a, &x, &|b, x| gmres(&a, b, x, &Identity, &opts));
+        check(&a, &x, &|b, x| gmres(&a, b, x, &Ilu0::new(&a).unwrap(), &opts));
+    }
+
+    #[test]
+    fn ilu0_is_exact_for_tridiagonal() {
+        // no fill-in for tridiagonal matrices, so ILU(0) is the exact LU
+        let a = convection(20);
+        let x = expected(20);
+        let opts = SolverOptions::default();
+        let b = a.mul_vec(&x);
+        let mut sol = vec![0.0; 20];
+        let h = gmres(&a, &b, &mut sol, &Ilu0::new(&a).unwrap(), &opts);
+        assert!(h.converged);
+        assert!(h.iterations <= 2);
+    }
+
+    #[test]
+    fn iteration_limit() {
+        let a = laplacian(10);
+        let b = vec![1.0; 100];
+        let mut x = vec![0.0; 100];
+        let opts = SolverOptions { max_iterations: 3, ..SolverOptions::default() };
+        let h = conjugate_gradient(&a, &b, &mut x, &Identity, &opts);
+        assert!(!h.converged);
+        assert_eq!(h.iterations, 3);
+        assert!(h.into_result("CG").is_err());
+    }
+}
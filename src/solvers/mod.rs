@@ -0,0 +1,91 @@
+pub mod preconditioner;
+pub mod iterative;
+pub mod direct;
+pub mod eigen;
+
+pub use self::preconditioner::*;
+pub use self::iterative::*;
+pub use self::direct::*;
+pub use self::eigen::*;
+
+use base_types::CsrMatrix;
+
+// Solver selection handed to the analyses.
+#[derive(Debug, Clone)]
+pub enum LinearSolver {
+    // Conjugate gradient with IC(0), for symmetric positive definite systems
+    ConjugateGradient(SolverOptions),
+    // BiCGSTAB with ILU(0), for non-symmetric systems
+    BiCgStab(SolverOptions),
+    Gmres(SolverOptions),
+    // Direct LDL^T, for symmetric (possibly indefinite) systems only
+    Skyline(Reordering)
+}
+
+impl Default for LinearSolver {
+    fn default() -> LinearSolver {
+        LinearSolver::Skyline(Reordering::ReverseCuthillMcKee)
+    }
+}
+
+impl LinearSolver {
+    pub fn solve(&self, a: &CsrMatrix, b: &[f64]) -> Result<Vec<f64>, String> {
+        self.prepare(a)?.solve(b)
+    }
+
+    // Factors `a`, or builds its preconditioner, for solving many right-hand
+    // sides with the same matrix
+    pub fn prepare(&self, a: &CsrMatrix) -> Result<PreparedSolver, String> {
+        Ok(match self {
+            LinearSolver::ConjugateGradient(options) =>
+                PreparedSolver::ConjugateGradient(a.clone(), Ic0::new(a)?, options.clone()),
+            LinearSolver::BiCgStab(options) => PreparedSolver::BiCgStab(a.clone(), Ilu0::new(a)?, options.clone()),
+            LinearSolver::Gmres(options) => PreparedSolver::Gmres(a.clone(), Ilu0::new(a)?, options.clone()),
+            LinearSolver::Skyline(reordering) => PreparedSolver::Skyline(SkylineCholesky::factor(a, *reordering)?)
+        })
+    }
+}
+
+// A linear solver set up for one matrix
+pub enum PreparedSolver {
+    ConjugateGradient(CsrMatrix, Ic0, SolverOptions),
+    BiCgStab(CsrMatrix, Ilu0, SolverOptions),
+    Gmres(CsrMatrix, Ilu0, SolverOptions),
+    Skyline(SkylineCholesky)
+}
+
+impl PreparedSolver {
+    pub fn solve(&self, b: &[f64]) -> Result<Vec<f64>, String> {
+        let mut x = vec![0.0; b.len()];
+        match self {
+            PreparedSolver::ConjugateGradient(a, precond, options) => {
+                conjugate_gradient(a, b, &mut x, precond, options).into_result("CG")?;
+            },
+            PreparedSolver::BiCgStab(a, precond, options) => {
+                bicgstab(a, b, &mut x, precond, options).into_result("BiCGSTAB")?;
+            },
+            PreparedSolver::Gmres(a, precond, options) => {
+                gmres(a, b, &mut x, precond, options).into_result("GMRES")?;
+            },
+            PreparedSolver::Skyline(factor) => {
+                x = factor.solve(b);
+            }
+        }
+        Ok(x)
+    }
+}
+
+pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+    debug_assert_eq!(a.len(), b.len());
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+pub fn norm(a: &[f64]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+// y += alpha * x
+pub fn axpy(alpha: f64, x: &[f64], y: &mut [f64]) {
+    debug_assert_eq!(x.len(), y.len());
+    y.iter_mut().zip(x).for_each(|(yi, xi)| *yi += alpha * xi);
+}
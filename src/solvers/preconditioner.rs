@@ -0,0 +1,248 @@
+use base_types::CsrMatrix;
+
+pub trait Preconditioner {
+    // z = M^-1 r
+    fn apply(&self, r: &[f64], z: &mut [f64]);
+}
+
+pub struct Identity;
+
+impl Preconditioner for Identity {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        z.copy_from_slice(r);
+    }
+}
+
+pub struct Jacobi {
+    inv_diag: Vec<f64>
+}
+
+impl Jacobi {
+    pub fn new(a: &CsrMatrix) -> Result<Jacobi, String> {
+        let inv_diag = a.diagonal().iter().enumerate()
+            .map(|(i, &d)| if d != 0.0 {
+                Ok(1.0 / d)
+            } else {
+                Err(format!("Jacobi: zero diagonal in row {}", i))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Jacobi { inv_diag })
+    }
+}
+
+impl Preconditioner for Jacobi {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        for i in 0..r.len() {
+            z[i] = r[i] * self.inv_diag[i];
+        }
+    }
+}
+
+// Symmetric successive over-relaxation:
+// M = (D + wL) D^-1 (D + wU) / (w (2 - w))
+pub struct Ssor<'a> {
+    a: &'a CsrMatrix,
+    diag: Vec<f64>,
+    omega: f64
+}
+
+impl<'a> Ssor<'a> {
+    pub fn new(a: &'a CsrMatrix, omega: f64) -> Result<Ssor<'a>, String> {
+        if omega <= 0.0 || omega >= 2.0 {
+            return Err(format!("SSOR: relaxation factor {} outside of (0, 2)", omega));
+        }
+        let diag = a.diagonal();
+        if let Some(i) = diag.iter().position(|&d| d == 0.0) {
+            return Err(format!("SSOR: zero diagonal in row {}", i));
+        }
+        Ok(Ssor { a, diag, omega })
+    }
+}
+
+impl<'a> Preconditioner for Ssor<'a> {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        let n = r.len();
+        let w = self.omega;
+
+        // (D + wL) y = r
+        for i in 0..n {
+            let s: f64 = self.a.row(i)
+                .filter(|&(j, _)| j < i)
+                .map(|(j, v)| v * z[j])
+                .sum();
+            z[i] = (r[i] - w * s) / self.diag[i];
+        }
+
+        // D y
+        z.iter_mut().zip(&self.diag).for_each(|(zi, d)| *zi *= d);
+
+        // (D + wU) z = D y
+        for i in (0..n).rev() {
+            let s: f64 = self.a.row(i)
+                .filter(|&(j, _)| j > i)
+                .map(|(j, v)| v * z[j])
+                .sum();
+            z[i] = (z[i] - w * s) / self.diag[i];
+        }
+
+        let scale = w * (2.0 - w);
+        z.iter_mut().for_each(|zi| *zi *= scale);
+    }
+}
+
+// Incomplete Cholesky with no fill-in. Only the lower triangle of `a` is read,
+// so the matrix must be symmetric positive definite.
+pub struct Ic0 {
+    // Lower triangular factor, rows sorted with the diagonal last.
+    l: CsrMatrix
+}
+
+impl Ic0 {
+    pub fn new(a: &CsrMatrix) -> Result<Ic0, String> {
+        let n = a.nrows();
+        let mut row_ptr = vec![0];
+        let mut col_idx = Vec::new();
+        let mut values: Vec<f64> = Vec::new();
+
+        for i in 0..n {
+            let beg = col_idx.len();
+            for (j, v) in a.row(i).filter(|&(j, _)| j <= i) {
+                col_idx.push(j);
+                values.push(v);
+            }
+            let end = col_idx.len();
+            if end == beg || col_idx[end - 1] != i {
+                return Err(format!("IC(0): missing diagonal in row {}", i));
+            }
+
+            for k in beg..end {
+                let j = col_idx[k];
+                // sum over the common sparsity of rows i and j, columns < j
+                let mut s = 0.0;
+                let (mut p, mut q) = (beg, row_ptr[j]);
+                let q_end = if j == i { k } else { row_ptr[j + 1] };
+                while p < k && q < q_end {
+                    if col_idx[p] == col_idx[q] {
+                        s += values[p] * values[q];
+                        p += 1;
+                        q += 1;
+                    } else if col_idx[p] < col_idx[q] {
+                        p += 1;
+                    } else {
+                        q += 1;
+                    }
+                }
+                if j < i {
+                    let ljj = values[row_ptr[j + 1] - 1];
+                    values[k] = (values[k] - s) / ljj;
+                } else {
+                    let d = values[k] - s;
+                    if d <= 0.0 {
+                        return Err(format!("IC(0): non-positive pivot in row {}", i));
+                    }
+                    values[k] = d.sqrt();
+                }
+            }
+            row_ptr.push(end);
+        }
+
+        Ok(Ic0 { l: CsrMatrix::from_raw(n, n, row_ptr, col_idx, values) })
+    }
+}
+
+impl Preconditioner for Ic0 {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        let n = r.len();
+        let ptr = self.l.row_ptr();
+        let col = self.l.col_idx();
+        let val = self.l.values();
+
+        // L y = r
+        for i in 0..n {
+            let diag = ptr[i + 1] - 1;
+            let s: f64 = (ptr[i]..diag).map(|k| val[k] * z[col[k]]).sum();
+            z[i] = (r[i] - s) / val[diag];
+        }
+
+        // L^T z = y
+        for i in (0..n).rev() {
+            let diag = ptr[i + 1] - 1;
+            z[i] /= val[diag];
+            for k in ptr[i]..diag {
+                z[col[k]] -= val[k] * z[i];
+            }
+        }
+    }
+}
+
+// Incomplete LU with no fill-in. L has an implicit unit diagonal and shares
+// the storage of U.
+pub struct Ilu0 {
+    lu: CsrMatrix,
+    diag: Vec<usize>
+}
+
+impl Ilu0 {
+    pub fn new(a: &CsrMatrix) -> Result<Ilu0, String> {
+        let n = a.nrows();
+        let mut lu = a.clone();
+        let ptr = lu.row_ptr().to_vec();
+        let col = lu.col_idx().to_vec();
+
+        let mut diag = vec![0; n];
+        for i in 0..n {
+            diag[i] = (ptr[i]..ptr[i + 1]).find(|&k| col[k] == i)
+                .ok_or_else(|| format!("ILU(0): missing diagonal in row {}", i))?;
+        }
+
+        // position of each column in the current row, usize::MAX if absent
+        let mut pos = vec![usize::MAX; n];
+        let val = lu.values_mut();
+        for i in 0..n {
+            for k in ptr[i]..ptr[i + 1] {
+                pos[col[k]] = k;
+            }
+            for k in ptr[i]..diag[i] {
+                let j = col[k];
+                let pivot = val[diag[j]];
+                if pivot == 0.0 {
+                    return Err(format!("ILU(0): zero pivot in row {}", j));
+                }
+                val[k] /= pivot;
+                let lik = val[k];
+                for m in diag[j] + 1..ptr[j + 1] {
+                    let p = pos[col[m]];
+                    if p != usize::MAX {
+                        val[p] -= lik * val[m];
+                    }
+                }
+            }
+            for k in ptr[i]..ptr[i + 1] {
+                pos[col[k]] = usize::MAX;
+            }
+        }
+        if let Some(i) = (0..n).find(|&i| val[diag[i]] == 0.0) {
+            return Err(format!("ILU(0): zero pivot in row {}", i));
+        }
+
+        Ok(Ilu0 { lu, diag })
+    }
+}
+
+impl Preconditioner for Ilu0 {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        let n = r.len();
+        let ptr = self.lu.row_ptr();
+        let col = self.lu.col_idx();
+        let val = self.lu.values();
+
+        for i in 0..n {
+            let s: f64 = (ptr[i]..self.diag[i]).map(|k| val[k] * z[col[k]]).sum();
+            z[i] = r[i] - s;
+        }
+        for i in (0..n).rev() {
+            let s: f64 = (self.diag[i] + 1..ptr[i + 1]).map(|k| val[k] * z[col[k]]).sum();
+            z[i] = (z[i] - s) / val[self.diag[i]];
+        }
+    }
+}